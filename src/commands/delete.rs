@@ -0,0 +1,140 @@
+use poise::CreateReply;
+use std::{path::Path, time::Duration};
+
+use serenity::{
+    all::{CreateEmbed, CreateInteractionResponse},
+    model::{colour::Colour, prelude::*},
+};
+
+use crate::{
+    util::{
+        autocomplete::autocomplete_announcements,
+        check::is_admin,
+        component_ids::DELETE_CONFIRM_BUTTON,
+        messages::create_delete_buttons,
+        paths::{index_dir, index_path},
+        submissions::uninstall_file,
+        util::{send_debug, send_error}
+    },
+    PContext, PError,
+};
+
+const CONFIRM_TIMEOUT_DURATION: Duration = Duration::from_secs(60);
+
+fn delete_help() -> String {
+    "\
+Delete an announcement after confirming it.
+Usage:
+!delete <discordname> <announcement-name>
+
+If the deleted announcement was active, another announcement of the user becomes active.
+Without any announcements left, the name is announced with TTS again.
+Only admins may delete the announcements of other users.
+"
+    .to_string()
+}
+
+#[doc = "Delete an announcement."]
+#[poise::command(
+    category = "Main Commands",
+    guild_only,
+    prefix_command,
+    slash_command,
+    required_bot_permissions = "SEND_MESSAGES",
+    help_text_fn = "delete_help"
+)]
+pub async fn delete(
+    ctx: PContext<'_>,
+    #[description = "The user whose announcement to delete."] user: User,
+    #[description = "Name of the announcement."]
+    #[autocomplete = "autocomplete_announcements"]
+    announcement: String,
+) -> Result<(), PError> {
+    if user.id != ctx.author().id && !is_admin(ctx).await {
+        let why = ctx.author().id;
+        let err_str = "You may only delete your own announcements!".to_string();
+        return send_debug(ctx, err_str, why.to_string()).await;
+    }
+
+    let guild_id = match ctx.guild_id() {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+
+    let discord_name = match user.nick_in(&ctx, guild_id).await {
+        Some(nick) => nick,
+        None => user.name.clone(),
+    };
+
+    let indexed_path = index_path(&discord_name, &announcement);
+    if !Path::new(&indexed_path).exists() {
+        let why = indexed_path;
+        let err_str = "Please choose a valid announcement".to_string();
+        return send_debug(ctx, err_str, why).await;
+    }
+
+    if !confirm_delete(ctx, &discord_name, &announcement).await? {
+        return Ok(());
+    }
+
+    let fallback = match uninstall_file(&discord_name, &announcement) {
+        Ok(fallback) => fallback,
+        Err(err_str) => {
+            let why = indexed_path;
+            return send_error(ctx, err_str, why).await;
+        }
+    };
+
+    let description = match fallback {
+        Some(fallback) => format!("`{}` is now the active announcement of {}", fallback, user.mention()),
+        None if !Path::new(&index_dir(&discord_name)).exists() => {
+            format!("{} has no announcements left and is announced with TTS", user.mention())
+        }
+        None => format!("Announcement of {}", user.mention()),
+    };
+
+    let reply = CreateReply::default()
+        .embed(CreateEmbed::new()
+            .title(format!("Successfully deleted {}", &announcement))
+            .description(description)
+            .colour(Colour::from_rgb(128, 128, 128))
+        );
+
+    ctx.send(reply)
+        .await
+        .map(drop)
+        .map_err(Into::into)
+}
+
+/// Asks the author to confirm deleting the announcement, returns whether they did.
+async fn confirm_delete(ctx: PContext<'_>, name: &str, announcement_name: &str) -> Result<bool, PError> {
+    let reply = CreateReply::default()
+        .embed(CreateEmbed::new()
+            .title(format!("Delete {}?", announcement_name))
+            .description(format!("The announcement `{}` of {} and its original can't be restored.", announcement_name, name))
+            .colour(Colour::from_rgb(211, 47, 47))
+        )
+        .components(create_delete_buttons())
+        .ephemeral(true);
+
+    let handle = ctx.send(reply).await?;
+    let message = handle.message().await?.into_owned();
+
+    let interaction = message
+        .await_component_interaction(ctx)
+        .author_id(ctx.author().id)
+        .timeout(CONFIRM_TIMEOUT_DURATION)
+        .await;
+
+    let _ = handle.delete(ctx).await;
+
+    match interaction {
+        Some(interaction) => {
+            interaction
+                .create_response(&ctx, CreateInteractionResponse::Acknowledge)
+                .await?;
+            Ok(interaction.data.custom_id == DELETE_CONFIRM_BUTTON)
+        }
+        None => Ok(false),
+    }
+}
@@ -0,0 +1,189 @@
+use std::{fs, path::Path};
+
+use crate::{
+    commands::new::{commit_file, delete_processing_files, preview_file, process_file, ProcessOptions},
+    util::{
+        autocomplete::autocomplete_announcements,
+        check::is_admin,
+        jobs::{Job, JobStage},
+        limits::{author_clip_length_limit, too_long_message, TRIM_TOLERANCE},
+        media::read_tags,
+        originals::original_path,
+        parse::parse_duration,
+        paths::{index_path, ProcessingFiles},
+        presets::{autocomplete_presets, resolve_filters, FilterPreset},
+        util::{send_debug, send_error}
+    },
+    PContext, PError,
+};
+
+use serenity::model::prelude::*;
+
+fn edit_help() -> String {
+    "\
+Process an announcement again from its original, without uploading it again.
+Usage:
+!edit <announcement-name> [<discordname>] [<start time>] [<end time>]
+
+Examples:
+!edit \"funny noise\"
+!edit \"funny noise\" @Yzarul 00:02 00:06
+
+Start and end time are relative to the original, without them the previous trim is kept.
+New filters replace the previous ones, remove_filters removes them.
+Originals are only kept for a while, older announcements may not be editable anymore.
+"
+    .to_string()
+}
+
+#[doc = "Re-trim, change the filters of or re-normalise an announcement."]
+#[poise::command(
+    category = "Main Commands",
+    guild_only,
+    prefix_command,
+    slash_command,
+    required_bot_permissions = "SEND_MESSAGES",
+    help_text_fn = "edit_help"
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn edit(
+    ctx: PContext<'_>,
+    #[description = "Name of the announcement."]
+    #[autocomplete = "autocomplete_announcements"]
+    announcement: String,
+    #[description = "The user whose announcement to edit."] user: Option<User>,
+    #[description = "Start time within the original."] start: Option<String>,
+    #[description = "End time within the original."] end: Option<String>,
+    #[description = "Filter preset to transform audio."] preset: Option<FilterPreset>,
+    #[description = "Presets applied in order, e.g. \"chipmunk echo:500\"."]
+    #[autocomplete = "autocomplete_presets"]
+    presets: Option<String>,
+    #[description = "FFMPEG filters to transform audio."] filters: Option<String>,
+    #[description = "Remove the filters of the announcement."] remove_filters: Option<bool>,
+    #[description = "Remove silence and fade out clips that are cut off."] auto_trim: Option<bool>,
+) -> Result<(), PError> {
+    let user = match user {
+        Some(user) => user,
+        None => ctx.author().clone(),
+    };
+
+    if user.id != ctx.author().id && !is_admin(ctx).await {
+        let why = ctx.author().id;
+        let err_str = "You may only edit your own announcements!".to_string();
+        return send_debug(ctx, err_str, why.to_string()).await;
+    }
+
+    let guild_id = match ctx.guild_id() {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+
+    let discord_name = match user.nick_in(&ctx, guild_id).await {
+        Some(nick) => nick,
+        None => user.name.clone(),
+    };
+
+    let indexed_path = index_path(&discord_name, &announcement);
+    if !Path::new(&indexed_path).exists() {
+        let why = indexed_path;
+        let err_str = "Please choose a valid announcement".to_string();
+        return send_debug(ctx, err_str, why).await;
+    }
+
+    let kept_path = original_path(&discord_name, &announcement);
+    if !Path::new(&kept_path).exists() {
+        let why = kept_path;
+        let err_str = "The original of this announcement isn't kept anymore, please submit it again".to_string();
+        return send_debug(ctx, err_str, why).await;
+    }
+
+    let (mut options, mut trim) = ProcessOptions::from_tags(&read_tags(&indexed_path));
+
+    if preset.is_some() || presets.is_some() || filters.is_some() {
+        options.filters = match resolve_filters(guild_id, preset, presets.as_deref(), filters.as_deref()) {
+            Ok(filters) => filters,
+            Err(why) => {
+                let err_str = format!("Invalid filters: {}", &why);
+                return send_debug(ctx, err_str, why).await;
+            }
+        };
+    } else if remove_filters.unwrap_or(false) {
+        options.filters = None;
+    }
+
+    // clips that overrode the limit keep doing so, all others get the limit of whoever edits them
+    if options.length_limit.is_some() {
+        options.length_limit = Some(author_clip_length_limit(ctx).await);
+    }
+
+    if let Some(auto_trim) = auto_trim {
+        options.auto_trim = auto_trim;
+    }
+
+    match (start, end) {
+        (Some(start), Some(end)) => {
+            match (parse_duration(&start), parse_duration(&end)) {
+                (Ok(start_time), Ok(end_time)) if start_time < end_time => {
+                    if let Some(limit) = options.length_limit {
+                        if (end_time - start_time).as_secs_f64() > limit + TRIM_TOLERANCE {
+                            let why = (end_time - start_time).as_secs_f64();
+                            let err_str = too_long_message(limit);
+                            return send_debug(ctx, err_str, why.to_string()).await;
+                        }
+                    }
+                }
+                _ => {
+                    let why = format!("{} - {}", start, end);
+                    let err_str = "Please provide a valid start and end time".to_string();
+                    return send_debug(ctx, err_str, why).await;
+                }
+            }
+            trim = Some((start, end));
+        }
+        (None, None) => (),
+        _ => {
+            let why = "";
+            let err_str = "Please provide both a start and an end time".to_string();
+            return send_debug(ctx, err_str, why.to_string()).await;
+        }
+    }
+
+    let job = Job::start(ctx, &discord_name, &announcement).await?;
+    let files = job.files();
+    let ProcessingFiles { filename, processed_filename } = &files;
+    let processing_path = "/config/processing/";
+
+    if let Err(why) = fs::copy(&kept_path, format!("{}{}", processing_path, &filename)) {
+        job.fail(&why.to_string()).await;
+        let err_str = format!("Failed to copy original of {}", &announcement);
+        return send_error(ctx, err_str, why.to_string()).await;
+    }
+
+    job.stage(JobStage::Normalising).await;
+
+    let trim_ref = trim.as_ref().map(|(start, end)| (start.as_str(), end.as_str()));
+    let filter_res = process_file(&job, processing_path, filename, processed_filename, &options, trim_ref).await;
+
+    if let Err(why) = filter_res {
+        job.fail(&why).await;
+        delete_processing_files(processing_path, filename, processed_filename);
+        let err_str = format!("Failed to apply audio filter for file {}", &filename);
+        return send_error(ctx, err_str, why).await;
+    }
+    job.done().await;
+
+    let confirmed = match preview_file(ctx, &discord_name, &announcement, &user, &files, &options).await {
+        Ok(confirmed) => confirmed,
+        Err(why) => {
+            delete_processing_files(processing_path, filename, processed_filename);
+            return Err(why);
+        }
+    };
+
+    if !confirmed {
+        delete_processing_files(processing_path, filename, processed_filename);
+        return Ok(());
+    }
+
+    commit_file(ctx, None, &discord_name, &announcement, &user, ctx.author().id, &files, true).await
+}
@@ -0,0 +1,90 @@
+use poise::CreateReply;
+use rusqlite::{params, Connection};
+use std::path::Path;
+
+use serenity::{all::CreateEmbed, model::{colour::Colour, id::GuildId}};
+
+use crate::{
+    util::{
+        check::is_admin,
+        consts::EMBED_DESCRIPTION_MAX_LENGTH,
+        util::{send_debug, send_error}
+    },
+    PContext, PError,
+};
+
+const ELEMENTS_PER_PAGE: usize = 10;
+
+#[doc = "List the announcements that failed to play most often."]
+#[poise::command(
+    category = "Admin Commands",
+    guild_only,
+    prefix_command,
+    slash_command,
+    required_bot_permissions = "SEND_MESSAGES"
+)]
+pub async fn failures(ctx: PContext<'_>) -> Result<(), PError> {
+    if !is_admin(ctx).await {
+        let why = ctx.author().id;
+        let err_str = "You are not allowed to list playback failures!".to_string();
+        return send_debug(ctx, err_str, why.to_string()).await;
+    }
+
+    let guild_id = match ctx.guild_id() {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+
+    let rows = match query_failures(guild_id) {
+        Ok(rows) => rows,
+        Err(why) => {
+            let err_str = "Failed to query playback failures".to_string();
+            return send_error(ctx, err_str, why.to_string()).await;
+        }
+    };
+
+    if rows.is_empty() {
+        let why = "";
+        let err_str = "No announcement failed to play so far".to_string();
+        return send_debug(ctx, err_str, why.to_string()).await;
+    }
+
+    let mut content = "".to_string();
+    for (path, name, failures, last_error) in rows {
+        let line_str = format!("• `{}` ({}) failed {}x\n  {}\n", path, name, failures, last_error);
+        if content.chars().count() + line_str.chars().count() > EMBED_DESCRIPTION_MAX_LENGTH {
+            break;
+        }
+        content.push_str(&line_str);
+    }
+
+    let reply = CreateReply::default()
+        .embed(CreateEmbed::new()
+            .title("Announcements that failed to play")
+            .description(content)
+            .colour(Colour::from_rgb(128, 128, 128))
+        );
+
+    ctx.send(reply)
+        .await
+        .map(drop)
+        .map_err(Into::into)
+}
+
+fn query_failures(guild_id: GuildId) -> Result<Vec<(String, String, i64, String)>, rusqlite::Error> {
+    let db_path = Path::new("/config/database/db.sqlite");
+    let db = Connection::open(db_path)?;
+
+    let mut statement = db.prepare(
+        "SELECT path, name, failures, last_error FROM playback_failures
+            WHERE guild_id=?1
+            ORDER BY failures DESC, last_failure DESC
+            LIMIT ?2",
+    )?;
+
+    let rows = statement.query_map(params![guild_id.get() as i64, ELEMENTS_PER_PAGE as i64], |row| {
+        Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+    })?;
+
+    rows.collect()
+}
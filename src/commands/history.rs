@@ -0,0 +1,195 @@
+use rusqlite::{params, Connection};
+use std::{path::Path, time::Duration};
+use tracing::debug;
+
+use poise::{futures_util::StreamExt, CreateReply};
+use serenity::{
+    all::{CreateEmbed, CreateInteractionResponse, CreateInteractionResponseMessage, EditMessage},
+    model::{colour::Colour, prelude::*},
+};
+
+use crate::{
+    util::{
+        component_ids::HISTORY_REPLAY_BUTTON,
+        consts::EMBED_DESCRIPTION_MAX_LENGTH,
+        messages::create_history_buttons,
+        playback::{Announcement, PlaybackEvent},
+        util::{play_file, send_debug, send_error, send_warning, user_voice_channel}
+    },
+    PContext, PError,
+};
+
+const TIMEOUT_DURATION: Duration = Duration::from_secs(600);
+const DEFAULT_HISTORY_LENGTH: usize = 10;
+
+/// A recorded playback.
+struct HistoryEntry {
+    channel_id: u64,
+    user_id: u64,
+    name: String,
+    announcement_name: String,
+    path: String,
+    tts: bool,
+    event: String,
+    timestamp: i64,
+}
+
+fn history_help() -> String {
+    "\
+List the announcements that were played last in this server, newest first.
+Usage:
+!history [<count>]
+
+Each entry shows whose announcement it was, the clip or TTS, the channel and what caused it.
+Replay plays an entry again in your voice channel.
+"
+    .to_string()
+}
+
+#[doc = "List the last played announcements."]
+#[poise::command(
+    category = "Main Commands",
+    guild_only,
+    prefix_command,
+    slash_command,
+    required_bot_permissions = "SEND_MESSAGES",
+    help_text_fn = "history_help"
+)]
+pub async fn history(
+    ctx: PContext<'_>,
+    #[description = "How many announcements to list, 10 by default."]
+    #[min = 1]
+    #[max = 10]
+    count: Option<usize>,
+) -> Result<(), PError> {
+    let guild_id = match ctx.guild_id() {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+    let count = count.unwrap_or(DEFAULT_HISTORY_LENGTH).clamp(1, DEFAULT_HISTORY_LENGTH);
+
+    let entries = match query_history(guild_id, count) {
+        Ok(entries) => entries,
+        Err(why) => {
+            let err_str = "Failed to query announcement history".to_string();
+            return send_error(ctx, err_str, why.to_string()).await;
+        }
+    };
+
+    if entries.is_empty() {
+        let why = guild_id;
+        let err_str = "No announcements were played so far".to_string();
+        return send_debug(ctx, err_str, why.to_string()).await;
+    }
+
+    let mut content = "".to_string();
+    for (index, entry) in entries.iter().enumerate() {
+        let clip = if entry.tts { "TTS".to_string() } else { format!("`{}`", &entry.announcement_name) };
+        let line_str = format!(
+            "**{}.** {} of {} [{}] in <#{}>, {} <t:{}:R>\n",
+            index + 1,
+            clip,
+            &entry.name,
+            UserId::new(entry.user_id).mention(),
+            entry.channel_id,
+            entry.event.replace('_', " "),
+            entry.timestamp
+        );
+        if content.chars().count() + line_str.chars().count() > EMBED_DESCRIPTION_MAX_LENGTH {
+            break;
+        }
+        content.push_str(&line_str);
+    }
+
+    let reply = CreateReply::default()
+        .embed(CreateEmbed::new()
+            .title("Announcement history")
+            .description(content)
+            .colour(Colour::from_rgb(128, 128, 128))
+        )
+        .components(create_history_buttons(entries.len()));
+
+    let mut message = ctx
+        .send(reply)
+        .await?
+        .into_message()
+        .await?;
+
+    let mut collector = message
+        .await_component_interactions(ctx)
+        .timeout(TIMEOUT_DURATION)
+        .stream();
+    while let Some(interaction) = collector.next().await {
+        let entry = match interaction.data.custom_id.split_once(':') {
+            Some((HISTORY_REPLAY_BUTTON, index)) => index.parse::<usize>().ok().and_then(|index| entries.get(index)),
+            _ => None,
+        };
+        let entry = match entry {
+            Some(entry) => entry,
+            None => {
+                let why = &interaction.data.custom_id;
+                let err_str = "Unknown component interaction".to_string();
+                return send_warning(ctx, err_str, why.to_string()).await;
+            }
+        };
+
+        let content = if !Path::new(&entry.path).exists() {
+            Some("This announcement doesn't exist anymore")
+        } else if let Some(channel_id) = user_voice_channel(ctx.serenity_context(), guild_id, interaction.user.id) {
+            interaction
+                .create_response(&ctx, CreateInteractionResponse::Acknowledge)
+                .await?;
+
+            let announcement = if entry.tts {
+                Announcement::tts(&entry.name, entry.user_id, &entry.path)
+            } else {
+                Announcement::new(&entry.name, entry.user_id, &entry.path)
+            };
+            play_file(ctx.serenity_context(), channel_id, guild_id, &[announcement], PlaybackEvent::Replay).await;
+            None
+        } else {
+            Some("Join a voice channel to play the announcement")
+        };
+
+        if let Some(content) = content {
+            let interaction_response = CreateInteractionResponseMessage::default()
+                .content(content)
+                .ephemeral(true);
+            interaction
+                .create_response(&ctx, CreateInteractionResponse::Message(interaction_response))
+                .await?;
+        }
+    }
+
+    debug!("Timeout");
+    // the history stays readable, only the buttons stop working
+    let _ = message.edit(&ctx, EditMessage::new().components(vec![])).await;
+    Ok(())
+}
+
+fn query_history(guild_id: GuildId, count: usize) -> Result<Vec<HistoryEntry>, rusqlite::Error> {
+    let db_path = Path::new("/config/database/db.sqlite");
+    let db = Connection::open(db_path)?;
+
+    let mut statement = db.prepare(
+        "SELECT channel_id, user_id, name, announcement, path, tts, event, timestamp FROM playbacks
+            WHERE guild_id=?1
+            ORDER BY id DESC
+            LIMIT ?2",
+    )?;
+
+    let rows = statement.query_map(params![guild_id.get() as i64, count as i64], |row| {
+        Ok(HistoryEntry {
+            channel_id: row.get::<_, i64>(0)? as u64,
+            user_id: row.get::<_, i64>(1)? as u64,
+            name: row.get(2)?,
+            announcement_name: row.get(3)?,
+            path: row.get(4)?,
+            tts: row.get(5)?,
+            event: row.get(6)?,
+            timestamp: row.get(7)?,
+        })
+    })?;
+
+    rows.collect()
+}
@@ -0,0 +1,527 @@
+use poise::CreateReply;
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+};
+use tokio::process::Command;
+use tracing::{debug, error};
+
+use serenity::{
+    all::CreateEmbed,
+    model::{
+        colour::Colour,
+        prelude::*,
+    }
+};
+
+use crate::{
+    commands::new::{delete_processing_files, process_file, ProcessOptions},
+    util::{
+        check::is_admin,
+        consts::BOT_ADMIN_USER_ID,
+        jobs::{Job, JobStage},
+        limits::guild_clip_length,
+        loudness::guild_loudness_target,
+        media::{check_media, probe_audio, MAX_ATTACHMENT_SIZE},
+        paths::{index_path, validate_name},
+        settings::{get_bool_setting, MODERATION},
+        submissions::{install_file, moderation_channel, submit_for_moderation},
+        util::{send_debug, send_error}
+    },
+    PContext, PError,
+};
+
+/// Name of the manifest in the root of the archive or directory.
+const MANIFEST_FILENAME: &str = "manifest.csv";
+/// Most entries a single import may contain.
+const MAX_IMPORT_ENTRIES: usize = 100;
+/// Longest report that fits into an embed description.
+const MAX_REPORT_LENGTH: usize = 4000;
+/// Most bytes an archive may unpack to.
+const MAX_UNPACKED_SIZE: u64 = 1024 * 1024 * 1024;
+/// Most files and directories an archive may unpack to.
+const MAX_UNPACKED_ENTRIES: usize = 1000;
+
+fn import_help() -> String {
+    "\
+Import many announcements at once from a zip or tar archive, or from a directory under /config.
+Usage:
+!import [<archive>] [<path>]
+
+The archive or directory needs a manifest.csv in its root with one announcement per line:
+<file>,<user id or mention>,<announcement-name>
+
+Example manifest.csv:
+# file, user, announcement
+sounds/noise.mp3,180995420196044809,funny noise
+hello.wav,<@180995420196044809>,hello there
+
+Every file is processed like a new file with the length limit of the server and added without preview.
+With moderation enabled every file is submitted for approval instead.
+Existing announcements are never replaced.
+Importing from a path is only possible for the bot admin.
+"
+    .to_string()
+}
+
+/// Line of the manifest.
+struct ManifestEntry {
+    file: String,
+    user_id: UserId,
+    announcement: String,
+}
+
+#[doc = "Import announcements from an archive or a directory with a manifest."]
+#[poise::command(
+    category = "Admin Commands",
+    guild_only,
+    prefix_command,
+    slash_command,
+    required_bot_permissions = "SEND_MESSAGES",
+    help_text_fn = "import_help"
+)]
+pub async fn import(
+    ctx: PContext<'_>,
+    #[description = "Zip or tar archive with a manifest.csv."] archive: Option<Attachment>,
+    #[description = "Archive or directory under /config with a manifest.csv."] path: Option<String>,
+) -> Result<(), PError> {
+    if !is_admin(ctx).await {
+        let why = ctx.author().id;
+        let err_str = "You are not allowed to import announcements!".to_string();
+        return send_debug(ctx, err_str, why.to_string()).await;
+    }
+
+    let guild_id = match ctx.guild_id() {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+
+    let import_dir = format!("/config/processing/import-{}/", ctx.id());
+    if let Err(why) = fs::create_dir_all(&import_dir) {
+        let err_str = "Failed to create directory".to_string();
+        return send_error(ctx, err_str, why.to_string()).await;
+    }
+
+    let import_res = run_import(ctx, guild_id, &import_dir, archive, path).await;
+    let _ = fs::remove_dir_all(&import_dir);
+
+    let (source, report) = match import_res {
+        Ok(res) => res,
+        Err(why) => return send_debug(ctx, why.clone(), why).await,
+    };
+
+    let imported = report.iter().filter(|(_, res)| res.is_ok()).count();
+    let mut description = String::new();
+    for (index, (announcement, res)) in report.iter().enumerate() {
+        let line = match res {
+            Ok(name) => format!(":white_check_mark: `{}` for {}\n", announcement, name),
+            Err(why) => format!(":x: `{}`: {}\n", announcement, why),
+        };
+        if description.len() + line.len() > MAX_REPORT_LENGTH {
+            description.push_str(&format!("... and {} more", report.len() - index));
+            break;
+        }
+        description.push_str(&line);
+    }
+
+    let reply = CreateReply::default()
+        .embed(CreateEmbed::new()
+            .title(format!("Imported {} of {} announcements from {}", imported, report.len(), source))
+            .description(description)
+            .colour(Colour::from_rgb(128, 128, 128))
+        );
+
+    ctx.send(reply)
+        .await
+        .map(drop)
+        .map_err(Into::into)
+}
+
+/// Unpacks the source and imports every entry of its manifest.
+///
+/// Returns the name of the source and the result of every entry, the error is meant for the user.
+async fn run_import(
+    ctx: PContext<'_>,
+    guild_id: GuildId,
+    import_dir: &str,
+    archive: Option<Attachment>,
+    path: Option<String>,
+) -> Result<(String, Vec<(String, Result<String, String>)>), String> {
+    // checked once up front, every entry would be reported as submitted without being sent otherwise
+    if get_bool_setting(guild_id, MODERATION, false) && moderation_channel(guild_id).is_none() {
+        return Err("Moderation is enabled, but no moderation channel is set".to_string());
+    }
+
+    let (source, source_path) = match (archive, path) {
+        (Some(archive), None) => {
+            if archive.size > MAX_ATTACHMENT_SIZE {
+                return Err(format!("The archive is too large, archives can be at most {} MB", MAX_ATTACHMENT_SIZE / 1024 / 1024));
+            }
+            let content = archive.download().await.map_err(|why| format!("Error downloading attachment: {}", why))?;
+            let archive_path = PathBuf::from(format!("{}{}", import_dir, archive_filename(&archive.filename)?));
+            fs::write(&archive_path, content).map_err(|why| format!("Error writing file: {}", why))?;
+            (archive.filename, archive_path)
+        }
+        (None, Some(path)) => {
+            if ctx.author().id.get() != BOT_ADMIN_USER_ID {
+                return Err("Only the bot admin can import from a path".to_string());
+            }
+            (path.clone(), config_path(&path)?)
+        }
+        _ => return Err("Either an archive or a path is needed".to_string()),
+    };
+
+    let mut job = Job::start(ctx, "the import", &source).await.map_err(|why| why.to_string())?;
+    job.stage(JobStage::Downloading).await;
+
+    let content_dir = if source_path.is_dir() {
+        source_path
+    } else {
+        let content_dir = PathBuf::from(format!("{}content", import_dir));
+        if let Err(why) = unpack_archive(&job, &source_path, &content_dir).await {
+            job.fail(&why).await;
+            return Err(format!("Failed to unpack {}: {}", source, why));
+        }
+        content_dir
+    };
+
+    let entries = match fs::read_to_string(content_dir.join(MANIFEST_FILENAME)) {
+        Ok(manifest) => parse_manifest(&manifest),
+        Err(why) => {
+            job.fail(&why.to_string()).await;
+            return Err(format!("{} has no readable {}", source, MANIFEST_FILENAME));
+        }
+    };
+    if entries.len() > MAX_IMPORT_ENTRIES {
+        job.fail("Too many entries").await;
+        return Err(format!("At most {} announcements can be imported at once", MAX_IMPORT_ENTRIES));
+    }
+
+    job.stage(JobStage::Normalising).await;
+
+    let mut report = vec![];
+    for (line, entry) in entries {
+        let res = match &entry {
+            Ok(entry) => {
+                job.next_file().await.map_err(|why| why.to_string())?;
+                import_entry(ctx, &job, guild_id, &content_dir, entry).await
+            }
+            Err(why) => Err(format!("Line {}: {}", line, why)),
+        };
+
+        let announcement = match entry {
+            Ok(entry) => entry.announcement,
+            Err(_) => format!("line {}", line),
+        };
+        match &res {
+            Ok(name) => debug!("Imported {} for {}", &announcement, name),
+            Err(why) => error!("Failed to import {}: {}", &announcement, why),
+        }
+        report.push((announcement, res));
+    }
+
+    job.done().await;
+
+    Ok((source, report))
+}
+
+/// Processes a single file of the manifest and adds it, returns the name it was added for.
+async fn import_entry(
+    ctx: PContext<'_>,
+    job: &Job<'_>,
+    guild_id: GuildId,
+    content_dir: &Path,
+    entry: &ManifestEntry,
+) -> Result<String, String> {
+    validate_name(&entry.announcement)?;
+
+    let file_path = contained_path(content_dir, &entry.file)?;
+
+    let user = entry.user_id.to_user(ctx).await.map_err(|_| format!("Unknown user {}", entry.user_id))?;
+    let name = match user.nick_in(&ctx, guild_id).await {
+        Some(nick) => nick,
+        None => user.name.clone(),
+    };
+
+    if Path::new(&index_path(&name, &entry.announcement)).exists() {
+        return Err(format!("{} already has an announcement named {}", name, &entry.announcement));
+    }
+
+    // the entries of an import are processed one after another, so they can share the files of the job
+    let files = job.files();
+    let processing_path = "/config/processing/";
+    let filename = &files.filename;
+    let processed_filename = &files.processed_filename;
+    let original_path = format!("{}{}", processing_path, filename);
+
+    fs::copy(&file_path, &original_path).map_err(|why| format!("Failed to copy {}: {}", &entry.file, why))?;
+
+    let import_res = match probe_audio(&original_path) {
+        Ok(media_info) => check_media(&media_info),
+        Err(_) => Err("The file doesn't contain any audio that can be used".to_string()),
+    };
+
+    let options = ProcessOptions {
+        filters: None,
+        length_limit: Some(guild_clip_length(guild_id)),
+        auto_trim: true,
+        loudness: guild_loudness_target(guild_id),
+    };
+
+    let import_res = match import_res {
+        Ok(_) => process_file(job, processing_path, filename, processed_filename, &options, None).await,
+        Err(why) => Err(why),
+    };
+    let import_res = match import_res {
+        Ok(_) if get_bool_setting(guild_id, MODERATION, false) => {
+            submit_for_moderation(ctx, None, &name, &entry.announcement, &user, ctx.author().id, &files, false)
+                .await
+                .map(|_| format!("{} (submitted for approval)", name))
+                .map_err(|why| why.to_string())
+        }
+        Ok(_) => install_file(
+            &name,
+            &entry.announcement,
+            user.id.get(),
+            &format!("{}{}", processing_path, processed_filename),
+            Some(&original_path),
+        )
+        .map(|_| name),
+        Err(why) => Err(why),
+    };
+
+    delete_processing_files(processing_path, filename, processed_filename);
+
+    import_res
+}
+
+/// Reads the manifest, every line is `<file>,<user>,<announcement>` and lines starting with `#` are ignored.
+fn parse_manifest(manifest: &str) -> Vec<(usize, Result<ManifestEntry, String>)> {
+    manifest
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(line_number, line)| {
+            let fields = line.splitn(3, ',').map(str::trim).collect::<Vec<_>>();
+            let entry = match fields[..] {
+                [file, user, announcement] => match parse_user(user) {
+                    Some(user_id) => Ok(ManifestEntry {
+                        file: file.to_string(),
+                        user_id,
+                        announcement: announcement.to_string(),
+                    }),
+                    None => Err(format!("`{}` is not a user id or mention", user)),
+                },
+                _ => Err("Expected <file>,<user>,<announcement>".to_string()),
+            };
+            (line_number, entry)
+        })
+        .collect()
+}
+
+/// Reads a user id or a mention like `<@123>`.
+fn parse_user(user: &str) -> Option<UserId> {
+    let id = user
+        .strip_prefix("<@")
+        .and_then(|user| user.strip_suffix('>'))
+        .map(|user| user.trim_start_matches('!'))
+        .unwrap_or(user);
+
+    id.parse::<u64>().ok().filter(|id| *id != 0).map(UserId::new)
+}
+
+/// Resolves a path relative to the unpacked content, rejecting anything that points outside of it.
+fn contained_path(content_dir: &Path, file: &str) -> Result<PathBuf, String> {
+    let relative = Path::new(file);
+    if relative.components().any(|component| !matches!(component, Component::Normal(_) | Component::CurDir)) {
+        return Err(format!("`{}` has to be a path inside the import", file));
+    }
+
+    let root = content_dir.canonicalize().map_err(|why| why.to_string())?;
+    let path = root
+        .join(relative)
+        .canonicalize()
+        .map_err(|_| format!("`{}` doesn't exist", file))?;
+
+    // symlinks can still point outside
+    if !path.starts_with(&root) || !path.is_file() {
+        return Err(format!("`{}` has to be a file inside the import", file));
+    }
+
+    Ok(path)
+}
+
+/// Resolves a path of the admin, which has to be under /config.
+fn config_path(path: &str) -> Result<PathBuf, String> {
+    let path = Path::new(path).canonicalize().map_err(|_| format!("`{}` doesn't exist", path))?;
+
+    if !path.starts_with("/config") {
+        return Err("Only paths under /config can be imported".to_string());
+    }
+
+    Ok(path)
+}
+
+/// Name the archive is stored as, only the extension of the upload is kept.
+fn archive_filename(filename: &str) -> Result<&'static str, String> {
+    let filename = filename.to_lowercase();
+
+    if filename.ends_with(".zip") {
+        Ok("archive.zip")
+    } else if [".tar", ".tar.gz", ".tgz", ".tar.bz2", ".tar.xz"].iter().any(|extension| filename.ends_with(extension)) {
+        Ok("archive.tar")
+    } else {
+        Err("Only zip and tar archives can be imported".to_string())
+    }
+}
+
+/// Unpacks a zip or tar archive, both tools refuse to write outside of the target directory.
+///
+/// The unpacked content is measured while unpacking, so archives that unpack to far more than they
+/// claim can't fill the disk.
+async fn unpack_archive(job: &Job<'_>, archive_path: &Path, content_dir: &Path) -> Result<(), String> {
+    fs::create_dir_all(content_dir).map_err(|why| why.to_string())?;
+
+    let is_zip = archive_path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("zip"));
+
+    let mut command = if is_zip {
+        let mut command = Command::new("unzip");
+        command.arg("-q").arg("-o").arg(archive_path).arg("-d").arg(content_dir);
+        command
+    } else {
+        // tar detects the compression itself
+        let mut command = Command::new("tar");
+        command.arg("-xf").arg(archive_path).arg("-C").arg(content_dir).arg("--no-same-owner");
+        command
+    };
+
+    let unpack_status = job.run_watched(&mut command, || check_unpacked(content_dir)).await?;
+
+    if !unpack_status.success() {
+        return Err(format!("{} exited with {}", if is_zip { "unzip" } else { "tar" }, unpack_status));
+    }
+
+    check_unpacked(content_dir)
+}
+
+/// Fails once the unpacked content has more entries or bytes than an import may have.
+fn check_unpacked(content_dir: &Path) -> Result<(), String> {
+    let mut entries = 0;
+    let mut size = 0;
+    let mut dirs = vec![content_dir.to_path_buf()];
+
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir).into_iter().flatten().flatten() {
+            entries += 1;
+            // the metadata of a directory entry doesn't follow symlinks
+            match entry.metadata() {
+                Ok(metadata) if metadata.is_dir() => dirs.push(entry.path()),
+                Ok(metadata) => size += metadata.len(),
+                Err(_) => (),
+            }
+
+            if entries > MAX_UNPACKED_ENTRIES {
+                return Err(format!("The archive contains more than {} files", MAX_UNPACKED_ENTRIES));
+            }
+            if size > MAX_UNPACKED_SIZE {
+                return Err(format!("The archive unpacks to more than {} MB", MAX_UNPACKED_SIZE / 1024 / 1024));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("announcer-import-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn parses_manifests() {
+        let manifest = "# file, user, announcement\n\nsounds/noise.mp3,180995420196044809,funny noise\nhello.wav,<@!42>,hello, there\nbroken line\nx.wav,someone,x\n";
+        let entries = parse_manifest(manifest);
+
+        assert_eq!(entries.len(), 4);
+        let (line, entry) = &entries[0];
+        let entry = entry.as_ref().unwrap();
+        assert_eq!(*line, 3);
+        assert_eq!(entry.file, "sounds/noise.mp3");
+        assert_eq!(entry.user_id, UserId::new(180995420196044809));
+        assert_eq!(entry.announcement, "funny noise");
+
+        let entry = entries[1].1.as_ref().unwrap();
+        assert_eq!(entry.user_id, UserId::new(42));
+        assert_eq!(entry.announcement, "hello, there");
+
+        assert!(entries[2].1.is_err());
+        assert!(entries[3].1.is_err());
+    }
+
+    #[test]
+    fn parses_users() {
+        assert_eq!(parse_user("42"), Some(UserId::new(42)));
+        assert_eq!(parse_user("<@42>"), Some(UserId::new(42)));
+        assert_eq!(parse_user("<@!42>"), Some(UserId::new(42)));
+        assert_eq!(parse_user("0"), None);
+        assert_eq!(parse_user("<@abc>"), None);
+        assert_eq!(parse_user(""), None);
+    }
+
+    #[test]
+    fn rejects_paths_outside_of_the_import() {
+        let dir = temp_dir("paths");
+        fs::create_dir_all(dir.join("sounds")).unwrap();
+        fs::write(dir.join("sounds/noise.mp3"), b"noise").unwrap();
+
+        assert!(contained_path(&dir, "sounds/noise.mp3").is_ok());
+        assert!(contained_path(&dir, "./sounds/noise.mp3").is_ok());
+        assert!(contained_path(&dir, "../noise.mp3").is_err());
+        assert!(contained_path(&dir, "sounds/../../noise.mp3").is_err());
+        assert!(contained_path(&dir, "/etc/passwd").is_err());
+        assert!(contained_path(&dir, "sounds").is_err());
+        assert!(contained_path(&dir, "missing.mp3").is_err());
+
+        std::os::unix::fs::symlink("/etc/passwd", dir.join("link")).unwrap();
+        assert!(contained_path(&dir, "link").is_err());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn limits_unpacked_content() {
+        let dir = temp_dir("unpacked");
+        fs::create_dir_all(dir.join("sounds")).unwrap();
+        fs::write(dir.join("sounds/noise.mp3"), b"noise").unwrap();
+        assert!(check_unpacked(&dir).is_ok());
+
+        // sparse, so the test doesn't need the disk space
+        let large = fs::File::create(dir.join("sounds/large.wav")).unwrap();
+        large.set_len(MAX_UNPACKED_SIZE + 1).unwrap();
+        assert!(check_unpacked(&dir).is_err());
+        fs::remove_file(dir.join("sounds/large.wav")).unwrap();
+
+        for index in 0..MAX_UNPACKED_ENTRIES {
+            fs::write(dir.join(format!("{}.wav", index)), b"").unwrap();
+        }
+        assert!(check_unpacked(&dir).is_err());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn names_archives_by_extension() {
+        assert_eq!(archive_filename("Sounds.ZIP"), Ok("archive.zip"));
+        assert_eq!(archive_filename("sounds.tar.gz"), Ok("archive.tar"));
+        assert!(archive_filename("sounds.rar").is_err());
+    }
+}
@@ -0,0 +1,64 @@
+use poise::CreateReply;
+
+use serenity::{
+    all::CreateEmbed,
+    model::{
+        colour::Colour,
+        prelude::*,
+    }
+};
+
+use crate::{
+    util::{
+        consts::EMBED_DESCRIPTION_MAX_LENGTH,
+        util::send_debug
+    },
+    PContext, PError,
+};
+
+#[doc = "List the queued, running and recently finished processing jobs."]
+#[poise::command(
+    category = "Main Commands",
+    guild_only,
+    prefix_command,
+    slash_command,
+    required_bot_permissions = "SEND_MESSAGES"
+)]
+pub async fn jobs(ctx: PContext<'_>) -> Result<(), PError> {
+    let jobs = ctx.data().jobs.list();
+
+    if jobs.is_empty() {
+        let why = "";
+        let err_str = "There are no processing jobs".to_string();
+        return send_debug(ctx, err_str, why.to_string()).await;
+    }
+
+    let mut content = "".to_string();
+    for job in jobs {
+        let line_str = format!(
+            "• #{} `{}` for {} [{}] **{}** ({}s ago)\n",
+            job.id,
+            &job.announcement,
+            &job.name,
+            UserId::new(job.user_id).mention(),
+            &job.stage,
+            job.submitted.elapsed().as_secs()
+        );
+        if content.chars().count() + line_str.chars().count() > EMBED_DESCRIPTION_MAX_LENGTH {
+            break;
+        }
+        content.push_str(&line_str);
+    }
+
+    let reply = CreateReply::default()
+        .embed(CreateEmbed::new()
+            .title("Processing jobs")
+            .description(content)
+            .colour(Colour::from_rgb(128, 128, 128))
+        );
+
+    ctx.send(reply)
+        .await
+        .map(drop)
+        .map_err(Into::into)
+}
@@ -1,5 +1,5 @@
 use rusqlite::{params, Connection, OptionalExtension};
-use std::{ffi::OsStr, fs, path::Path, time::Duration};
+use std::{fs, path::Path, time::Duration};
 use tracing::debug;
 
 use poise::{futures_util::StreamExt, CreateReply};
@@ -17,7 +17,7 @@ use serenity::{
 
 use crate::{
     util::{
-        component_ids::{LIST_NEXT_BUTTON, LIST_PREV_BUTTON}, consts::EMBED_DESCRIPTION_MAX_LENGTH, messages::create_navigation_buttons, util::{send_debug, send_error, send_warning}
+        autocomplete::autocomplete_names, component_ids::{LIST_NEXT_BUTTON, LIST_PREV_BUTTON}, consts::EMBED_DESCRIPTION_MAX_LENGTH, messages::create_navigation_buttons, paths::{decode_file_stem, index_dir}, util::{send_debug, send_error, send_warning}
     },
     PContext, PError,
 };
@@ -35,7 +35,9 @@ const ELEMENTS_PER_PAGE: usize = 10;
 )]
 pub async fn list(
     ctx: PContext<'_>,
-    #[description = "The name for which to list announcements."] name: Option<String>,
+    #[description = "The name for which to list announcements."]
+    #[autocomplete = "autocomplete_names"]
+    name: Option<String>,
     #[description = "The page index."]
     #[min = 1]
     index: Option<usize>,
@@ -52,12 +54,12 @@ pub async fn list(
     };
     let mut index = index.unwrap_or(1);
 
-    let path_string = format!("/config/index/{}", &name);
+    let path_string = index_dir(&name);
     let path = Path::new(&path_string);
 
     let db_path = Path::new("/config/database/db.sqlite");
 
-    let db = match Connection::open(&db_path) {
+    let db = match Connection::open(db_path) {
         Ok(db) => db,
         Err(why) => {
             let err_str = "Failed to open database:".to_string();
@@ -156,7 +158,7 @@ pub async fn list(
         let err_str = "Timeout".to_string();
         debug!("{}", err_str);
         let _ = message.delete(&ctx).await;
-        return Ok(());
+        Ok(())
     } else {
         let why = name;
         let err_str = "This name doesn't exist".to_string();
@@ -203,12 +205,7 @@ async fn create_list(
         };
     }
 
-    let mut active_filename = None;
-    let active_filename_str;
-    if filename.is_some() {
-        active_filename_str = filename.as_ref().unwrap();
-        active_filename = Some(OsStr::new(active_filename_str));
-    }
+    let active_filename = filename.as_deref();
 
     let mut msg_len = 0;
     let mut msg_str = "".to_string();
@@ -232,14 +229,15 @@ async fn create_list(
         };
         let mut line_str = "".to_owned();
         if !entry_value.path().is_dir() {
-            if active_filename.is_some() && entry_value.path().file_stem().unwrap() == active_filename.unwrap() {
+            let announcement_name = decode_file_stem(&entry_value.path()).unwrap();
+            if active_filename == Some(announcement_name.as_str()) {
                 line_str = format!(
                     "• `{}` <=={:=>30}",
-                    entry_value.path().file_stem().unwrap().to_str().unwrap(),
+                    announcement_name,
                     format!(" {}", &ctx.author().mention())
                 );
             } else {
-                line_str = format!("• `{}`", entry_value.path().file_stem().unwrap().to_str().unwrap());
+                line_str = format!("• `{}`", announcement_name);
             }
         }
 
@@ -257,10 +255,10 @@ async fn create_list(
             };
         }
 
-        msg_str.push_str("\n");
+        msg_str.push('\n');
         msg_str.push_str(&line_str);
         msg_len += line_len + 1;
     }
 
-    return Ok((msg_str, last_page_index));
+    Ok((msg_str, last_page_index))
 }
@@ -1,5 +1,17 @@
+pub mod delete;
+pub mod edit;
+pub mod failures;
+pub mod history;
+pub mod import;
+pub mod jobs;
 pub mod list;
 pub mod names;
 pub mod new;
+pub mod presets;
 pub mod random;
+pub mod rename;
+pub mod reprocess;
+pub mod search;
 pub mod set;
+pub mod settings;
+pub mod stats;
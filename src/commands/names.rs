@@ -16,6 +16,7 @@ use crate::{
         component_ids::{LIST_NEXT_BUTTON, LIST_PREV_BUTTON}, 
         consts::EMBED_DESCRIPTION_MAX_LENGTH, 
         messages::create_navigation_buttons, 
+        paths::decode_segment,
         util::{send_debug, send_error, send_warning}
     },
     PContext, PError,
@@ -40,7 +41,7 @@ pub async fn names(
 ) -> Result<(), PError> {
     let mut index = index.unwrap_or(1);
 
-    let path_string = format!("/config/index/");
+    let path_string = "/config/index/".to_string();
     let path = Path::new(&path_string);
 
     if path.is_dir() {
@@ -56,7 +57,7 @@ pub async fn names(
 
         let reply = CreateReply::default()
             .embed(CreateEmbed::new()
-                .title(format!("List of all names that have announcements"))
+                .title("List of all names that have announcements")
                 .description(content)
                 .colour(Colour::from_rgb(128, 128, 128))
                 .footer(CreateEmbedFooter::new(format!("Page {}/{}", index, last_page_index)))
@@ -100,7 +101,7 @@ pub async fn names(
 
             let interaction_response = CreateInteractionResponseMessage::default()
                 .embed(CreateEmbed::new()
-                    .title(format!("List of all names that have announcements"))
+                    .title("List of all names that have announcements")
                     .description(content)
                     .colour(Colour::from_rgb(128, 128, 128))
                     .footer(CreateEmbedFooter::new(format!("Page {}/{}", index, last_page_index)))
@@ -121,7 +122,7 @@ pub async fn names(
         let err_str = "Timeout".to_string();
         debug!("{}", err_str);
         let _ = message.delete(&ctx).await;
-        return Ok(());
+        Ok(())
     } else {
         let why = path_string;
         let err_str = "Index directory doesn't exist".to_string();
@@ -187,12 +188,10 @@ async fn create_list(
                 };
             }
         };
-        let line_str;
-        if entry_value.path().is_dir() {
-            line_str = format!("• `{}`", entry_value.path().file_stem().unwrap().to_str().unwrap());
-        } else {
+        if !entry_value.path().is_dir() {
             continue;
         }
+        let line_str = format!("• `{}`", decode_segment(&entry_value.file_name().to_string_lossy()));
 
         let line_len = line_str.chars().count();
         count += 1;
@@ -208,10 +207,10 @@ async fn create_list(
             };
         }
 
-        msg_str.push_str("\n");
+        msg_str.push('\n');
         msg_str.push_str(&line_str);
         msg_len += line_len + 1;
     }
 
-    return Ok((msg_str, last_page_index));
+    Ok((msg_str, last_page_index))
 }
@@ -1,40 +1,167 @@
-use poise::CreateReply;
-use rusqlite::{params, Connection};
-use std::{fs::{self, File}, io::prelude::*, path::Path, process::Command, time::Duration};
+use poise::{futures_util::StreamExt, CreateReply};
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::prelude::*,
+    path::Path,
+    time::Duration,
+};
+use tokio::{process::Command, time};
 use tracing::debug;
 use url::Url;
 
-use serenity::{all::CreateEmbed, model::{
+use serenity::{
+    all::{
+        CreateAttachment,
+        CreateEmbed,
+        CreateEmbedFooter,
+        CreateInteractionResponse,
+        CreateInteractionResponseMessage
+    },
+    model::{
         colour::Colour,
         prelude::*,
-    }};
+    }
+};
 
 use crate::{
     util::{
-        consts::{BOT_ADMIN_USER_ID, ELEMENT_LABEL_LENGTH}, 
-        parse::parse_duration, 
-        util::{send_debug, send_error}
+        component_ids::{
+            DUPLICATE_USE_EXISTING_BUTTON,
+            PREVIEW_CONFIRM_BUTTON,
+            PREVIEW_DISCARD_BUTTON,
+            PREVIEW_PLAY_BUTTON,
+            PREVIEW_RETRIM_BUTTON
+        },
+        check::is_admin,
+        fingerprint::{find_duplicate, Duplicate},
+        jobs::{Job, JobStage},
+        limits::{author_clip_length_limit, too_long_message, DEFAULT_CLIP_LENGTH, TRIM_TOLERANCE},
+        loudness::{guild_loudness_target, LoudnessMeasurement, LoudnessTarget, DEFAULT_INTEGRATED_LOUDNESS, DEFAULT_TRUE_PEAK},
+        media::{check_media, check_public_url, is_direct_media, probe_audio, MAX_ATTACHMENT_SIZE},
+        messages::{create_duplicate_buttons, create_preview_buttons},
+        parse::{parse_duration, parse_url_timestamp},
+        paths::{index_path, validate_name, ProcessingFiles},
+        playback::{Announcement, PlaybackEvent},
+        presets::{autocomplete_presets, resolve_filters, FilterPreset},
+        recording::{start_recording, write_wav, MAX_RECORDING_DURATION, RECORDING_COUNTDOWN},
+        settings::{get_bool_setting, MODERATION},
+        submissions::{copy_to_processing, install_file, replace_file, submit_for_moderation},
+        util::{play_file, send_debug, send_error, send_error_to, send_reply, send_warning, user_voice_channel}
     },
     PContext, PError,
 };
 
+const PREVIEW_TIMEOUT_DURATION: Duration = Duration::from_secs(600);
+/// Length of the fade-out of clips that are cut at the length limit.
+const FADE_OUT_DURATION: f64 = 0.5;
+/// Removes silence at the start of a clip, reversing the clip around it removes silence at the end.
+const SILENCE_FILTER: &str = "silenceremove=start_periods=1:start_threshold=-50dB:start_silence=0.05";
+// tags of processed clips, the options are stored so the clip can be processed again from its original
+/// Lists the applied processing steps.
+pub const PROCESSING_TAG: &str = "processing";
+const FILTERS_TAG: &str = "filters";
+const LENGTH_LIMIT_TAG: &str = "length_limit";
+const AUTO_TRIM_TAG: &str = "auto_trim";
+const LOUDNESS_TARGET_TAG: &str = "loudness_target";
+const TRUE_PEAK_TARGET_TAG: &str = "true_peak_target";
+const TRIM_START_TAG: &str = "trim_start";
+const TRIM_END_TAG: &str = "trim_end";
+
+/// How a submitted clip is processed.
+#[derive(Clone, Debug)]
+pub struct ProcessOptions {
+    pub filters: Option<String>,
+    /// Length the clip is cut to, `None` if the limit is overridden.
+    pub length_limit: Option<f64>,
+    /// Remove leading silence, trailing silence of length limited clips, and fade out clips cut at the limit.
+    pub auto_trim: bool,
+    pub loudness: LoudnessTarget,
+}
+
+impl ProcessOptions {
+    /// Reads the options and the trim a clip was processed with from its tags.
+    pub fn from_tags(tags: &HashMap<String, String>) -> (ProcessOptions, Option<(String, String)>) {
+        let options = ProcessOptions {
+            filters: tags.get(FILTERS_TAG).cloned(),
+            length_limit: match tags.get(LENGTH_LIMIT_TAG) {
+                Some(value) => value.parse::<f64>().ok(),
+                None => Some(DEFAULT_CLIP_LENGTH),
+            },
+            auto_trim: tags.get(AUTO_TRIM_TAG).is_some_and(|value| value == "1"),
+            loudness: LoudnessTarget {
+                integrated: tags
+                    .get(LOUDNESS_TARGET_TAG)
+                    .and_then(|value| value.parse::<f64>().ok())
+                    .unwrap_or(DEFAULT_INTEGRATED_LOUDNESS),
+                true_peak: tags
+                    .get(TRUE_PEAK_TARGET_TAG)
+                    .and_then(|value| value.parse::<f64>().ok())
+                    .unwrap_or(DEFAULT_TRUE_PEAK),
+            },
+        };
+
+        let trim = match (tags.get(TRIM_START_TAG), tags.get(TRIM_END_TAG)) {
+            (Some(start), Some(end)) => Some((start.clone(), end.clone())),
+            _ => None,
+        };
+
+        (options, trim)
+    }
+}
+
+#[derive(Debug, poise::Modal)]
+#[name = "Re-trim announcement"]
+struct RetrimModal {
+    #[name = "Start time"]
+    #[placeholder = "00:05"]
+    start: String,
+    #[name = "End time"]
+    #[placeholder = "00:10"]
+    end: String,
+}
+
 fn new_help() -> String {
-    return "\
+    "\
 Submit a new announcement either as file or url.
 Usage:  
-!new file <discordname> <announcement-name> [<filters>]
-!new url <discordname> <announcement-name> <url> <start time> <end time> [<filters>]
+!new file <discordname> <announcement-name> [<preset>] [<presets>] [<filters>]
+!new url <discordname> <announcement-name> <url> [<start time>] [<end time>] [<duration>] [<preset>] [<presets>] [<filters>]
+!new record <discordname> <announcement-name> [<seconds>] [<preset>] [<presets>] [<filters>]
 
 Examples:
 !new file @Yzarul \"funny noise\"
-!new file @Yzarul \"funny noise\" \"vibrato\"
+!new file @Yzarul \"funny noise\" chipmunk
+!new file @Yzarul \"funny noise\" robot \"echo:500\"
 !new url @Yzarul \"funny noise\" \"https://www.youtube.com/watch?v=dQw4w9WgXcQ\" 20 25
 !new url @Yzarul \"funny noise\" \"https://www.youtube.com/watch?v=dQw4w9WgXcQ\" 02:20 02:25
-!new url @Yzarul \"funny noise\" \"https://www.youtube.com/watch?v=dQw4w9WgXcQ\" 02:20 02:25 vibrato=d=1.0
+!new url @Yzarul \"funny noise\" \"https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=2m20s\"
+!new url @Yzarul \"funny noise\" \"https://example.com/noise.mp3#t=80\"
+!new record @Yzarul \"funny noise\" 5
 
+Without a start time the timestamp in the url is used, e.g. ?t=1m20s or #t=80.
+Instead of an end time a duration can be given, without either the clip is as long as the length limit.
+The length limit is 6 seconds unless the server admins changed it for everyone or for your roles,
+the preview shows the limit that applies to you. Admins can override it with override_length_limit.
+Links to audio or video files are downloaded directly, everything else goes through youtube-dl.
+
+Files can be at most 25 MB, the audio of videos is extracted.
+To record, join a voice channel first. After a short countdown your own voice is recorded for at most 15 seconds.
+Submissions are processed in the background, their progress is shown in the reply and with !jobs.
+Silence at the start and end is removed and clips cut at the length limit fade out, unless auto_trim is off.
+Clips are then normalised in two passes to the loudness the server admins set with !settings loudness.
+If the clip sounds like an existing announcement, you are offered to use that one instead of a copy.
+The processed clip is sent back as a preview first. It only becomes active once you confirm it.
+
+Presets like chipmunk, deep, echo, robot, reverse and radio can be chained in order, e.g. \"chipmunk echo:500\".
+Some take a parameter after a colon, see all presets with !presets list.
+The preset, the preset chain and the filters are applied in that order.
+
+Filters are limited to a single chain of common audio filters like volume, atempo, aecho, vibrato,
+highpass, lowpass, equalizer or afade with bounded options.
 See all filters here https://ffmpeg.org/ffmpeg-filters.html
 "
-    .to_string();
+    .to_string()
 }
 
 #[doc = "Submit a new announcement either as file or url."]
@@ -44,11 +171,11 @@ See all filters here https://ffmpeg.org/ffmpeg-filters.html
     prefix_command,
     slash_command,
     required_bot_permissions = "SEND_MESSAGES",
-    subcommands("file", "url"),
+    subcommands("file", "url", "record"),
     help_text_fn = "new_help"
 )]
 pub async fn new(_ctx: PContext<'_>) -> Result<(), PError> {
-    return Ok(());
+    Ok(())
 }
 
 #[doc = "Add new announcement using a file."]
@@ -59,33 +186,46 @@ pub async fn new(_ctx: PContext<'_>) -> Result<(), PError> {
     slash_command,
     required_bot_permissions = "SEND_MESSAGES"
 )]
+#[allow(clippy::too_many_arguments)]
 pub async fn file(
     ctx: PContext<'_>,
     #[description = "The user for which to add an announcement."] user: User,
     #[description = "Name of the announcement."] announcement: String,
     #[description = "Audio file to be used as announcement."] file: Attachment,
+    #[description = "Filter preset to transform audio."] preset: Option<FilterPreset>,
+    #[description = "Presets applied in order, e.g. \"chipmunk echo:500\"."]
+    #[autocomplete = "autocomplete_presets"]
+    presets: Option<String>,
     #[description = "FFMPEG filters to transform audio."] filters: Option<String>,
     #[description = "Override the length limit"] override_length_limit: Option<bool>,
+    #[description = "Remove silence and fade out clips that are cut off, on by default."] auto_trim: Option<bool>,
 ) -> Result<(), PError> {
-    let mut override_length = false;
-    if override_length_limit.is_some() {
-        override_length = override_length_limit.unwrap();
-        if override_length {
-            if ctx.author().id.get() != BOT_ADMIN_USER_ID {
-                let why = "".to_string();
-                let err_str = "You are not allowed to use the length override!".to_string();
-                return send_debug(ctx, err_str, why).await;
-            }
-        }
+    let override_length = override_length_limit.unwrap_or(false);
+    if override_length && !is_admin(ctx).await {
+        let why = "".to_string();
+        let err_str = "You are not allowed to use the length override!".to_string();
+        return send_debug(ctx, err_str, why).await;
     }
+    let length_limit = if override_length { None } else { Some(author_clip_length_limit(ctx).await) };
 
-    let announcement_name_length = announcement.chars().count();
-    if announcement_name_length > ELEMENT_LABEL_LENGTH {
-        let why = announcement_name_length;
-        let err_str = "Announcement name is too long".to_string();
-        return send_debug(ctx, err_str, why.to_string()).await;
+    if let Err(err_str) = validate_name(&announcement) {
+        let why = announcement;
+        return send_debug(ctx, err_str, why).await;
     }
 
+    let guild_id = match ctx.guild_id() {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+
+    let filters = match resolve_filters(guild_id, preset, presets.as_deref(), filters.as_deref()) {
+        Ok(filters) => filters,
+        Err(why) => {
+            let err_str = format!("Invalid filters: {}", &why);
+            return send_debug(ctx, err_str, why).await;
+        }
+    };
+
     let discord_name = match ctx.guild_id() {
         Some(guild_id) => match user.nick_in(&ctx, guild_id).await {
             Some(nick) => nick,
@@ -94,31 +234,22 @@ pub async fn file(
         None => user.name.clone(),
     };
 
-    let filename = format!("{}.flac", &announcement);
-    let processing_path = "/config/processing/";
+    let job = Job::start(ctx, &discord_name, &announcement).await?;
+    job.stage(JobStage::Downloading).await;
 
-    let content = match file.download().await {
-        Ok(content) => content,
-        Err(why) => {
-            let err_str = "Error downloading attachment".to_string();
-            return send_error(ctx, err_str, why.to_string()).await;
-        }
-    };
+    if let Err(why) = download_attachment(ctx, &job, &file, &job.files().filename).await {
+        job.fail(&why.to_string()).await;
+        return Err(why);
+    }
 
-    let mut file = match File::create(format!("{}{}", processing_path, &filename)) {
-        Ok(file) => file,
-        Err(why) => {
-            let err_str = "Error creating file".to_string();
-            return send_error(ctx, err_str, why.to_string()).await;
-        }
+    let options = ProcessOptions {
+        filters,
+        length_limit,
+        auto_trim: auto_trim.unwrap_or(true),
+        loudness: guild_loudness_target(guild_id),
     };
 
-    if let Err(why) = file.write(&content) {
-        let err_str = "Error writing file".to_string();
-        return send_error(ctx, err_str, why.to_string()).await;
-    }
-
-    return add_new_file(ctx, &discord_name, &announcement, &user, filters.as_ref(), override_length).await;
+    return add_new_file(ctx, job, &discord_name, &announcement, &user, options).await;
 }
 
 #[doc = "Add new announcement using a url."]
@@ -129,27 +260,48 @@ pub async fn file(
     slash_command,
     required_bot_permissions = "SEND_MESSAGES"
 )]
+#[allow(clippy::too_many_arguments)]
 pub async fn url(
     ctx: PContext<'_>,
     #[description = "The user for which to add an announcement."] user: User,
     #[description = "Name of the announcement."] announcement: String,
     #[description = "URL for the announcement."] url: String,
-    #[description = "Start time."] start: String,
-    #[description = "End time."] end: String,
+    #[description = "Start time, defaults to the timestamp in the url."] start: Option<String>,
+    #[description = "End time."] end: Option<String>,
+    #[description = "Duration, instead of the end time."] duration: Option<String>,
+    #[description = "Filter preset to transform audio."] preset: Option<FilterPreset>,
+    #[description = "Presets applied in order, e.g. \"chipmunk echo:500\"."]
+    #[autocomplete = "autocomplete_presets"]
+    presets: Option<String>,
     #[description = "FFMPEG filters to transform audio."] filters: Option<String>,
     #[description = "Override the length limit"] override_length_limit: Option<bool>,
+    #[description = "Remove silence and fade out clips that are cut off, on by default."] auto_trim: Option<bool>,
 ) -> Result<(), PError> {
-    let mut override_length = false;
-    if override_length_limit.is_some() {
-        override_length = override_length_limit.unwrap();
-        if override_length {
-            if ctx.author().id.get() != BOT_ADMIN_USER_ID {
-                let why = "".to_string();
-                let err_str = "You are not allowed to use the length override!".to_string();
-                return send_debug(ctx, err_str, why).await;
-            }
-        }
+    let override_length = override_length_limit.unwrap_or(false);
+    if override_length && !is_admin(ctx).await {
+        let why = "".to_string();
+        let err_str = "You are not allowed to use the length override!".to_string();
+        return send_debug(ctx, err_str, why).await;
     }
+    let length_limit = if override_length { None } else { Some(author_clip_length_limit(ctx).await) };
+
+    if let Err(err_str) = validate_name(&announcement) {
+        let why = announcement;
+        return send_debug(ctx, err_str, why).await;
+    }
+
+    let guild_id = match ctx.guild_id() {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+
+    let filters = match resolve_filters(guild_id, preset, presets.as_deref(), filters.as_deref()) {
+        Ok(filters) => filters,
+        Err(why) => {
+            let err_str = format!("Invalid filters: {}", &why);
+            return send_debug(ctx, err_str, why).await;
+        }
+    };
 
     let discord_name = match ctx.guild_id() {
         Some(guild_id) => match user.nick_in(&ctx, guild_id).await {
@@ -159,219 +311,995 @@ pub async fn url(
         None => user.name.clone(),
     };
 
-    let filename = format!("{}.flac", &announcement);
     let processing_path = "/config/processing/";
 
-    let _ = match Url::parse(&url) {
-        Ok(url) => url,
+    let parsed_url = match Url::parse(&url) {
+        Ok(parsed_url) if ["http", "https"].contains(&parsed_url.scheme()) => parsed_url,
+        Ok(parsed_url) => {
+            let why = parsed_url.scheme().to_string();
+            let err_str = "Please provide a valid url".to_string();
+            return send_debug(ctx, err_str, why).await;
+        }
         Err(why) => {
             let err_str = "Please provide a valid url".to_string();
             return send_debug(ctx, err_str, why.to_string()).await;
         }
     };
 
-    let start_parsed = parse_duration(&start).unwrap();
-    let end_parsed = parse_duration(&end).unwrap();
-    let duration = end_parsed - start_parsed;
+    let start_time = match &start {
+        Some(start) => match parse_duration(start) {
+            Ok(start_time) => start_time,
+            Err(why) => {
+                let err_str = "Please provide a valid start time".to_string();
+                return send_debug(ctx, err_str, why.to_string()).await;
+            }
+        },
+        None => parse_url_timestamp(&parsed_url).unwrap_or_default(),
+    };
+
+    let end_time = match (&end, &duration) {
+        (Some(_), Some(_)) => {
+            let why = "";
+            let err_str = "Please provide either an end time or a duration".to_string();
+            return send_debug(ctx, err_str, why.to_string()).await;
+        }
+        (Some(time), None) | (None, Some(time)) => match parse_duration(time) {
+            Ok(time) if end.is_some() => Some(time),
+            Ok(time) => {
+                if let Some(limit) = length_limit {
+                    if time.as_secs_f64() > limit + TRIM_TOLERANCE {
+                        let why = time.as_secs_f64();
+                        let err_str = too_long_message(limit);
+                        return send_debug(ctx, err_str, why.to_string()).await;
+                    }
+                }
+                start_time.checked_add(time)
+            }
+            Err(why) => {
+                let err_str = "Please provide a valid end time or duration".to_string();
+                return send_debug(ctx, err_str, why.to_string()).await;
+            }
+        },
+        (None, None) => Duration::try_from_secs_f64(length_limit.unwrap_or(DEFAULT_CLIP_LENGTH))
+            .ok()
+            .and_then(|length| start_time.checked_add(length)),
+    };
+    let end_time = match end_time {
+        Some(end_time) => end_time,
+        None => {
+            let why = format!("{:?}", start_time);
+            let err_str = "Please provide a valid start time".to_string();
+            return send_debug(ctx, err_str, why).await;
+        }
+    };
 
-    if !override_length && duration > Duration::from_secs(7) {
-        let why = duration.as_secs_f64();
-        let err_str = "Duration is too long".to_string();
-        return send_debug(ctx, err_str, why.to_string()).await;
+    if end_time <= start_time {
+        let why = format!("{:?} - {:?}", start_time, end_time);
+        let err_str = "The end time has to be after the start time".to_string();
+        return send_debug(ctx, err_str, why).await;
     }
 
-    let youtube_url = Command::new("yt-dlp")
-        .arg("--no-playlist")
-        .arg("-g")
-        .arg(&url)
-        .output()
-        .expect("Failed to run youtube-dl");
+    let clip_duration = end_time - start_time;
+    if let Some(limit) = length_limit {
+        if clip_duration.as_secs_f64() > limit + TRIM_TOLERANCE {
+            let why = clip_duration.as_secs_f64();
+            let err_str = too_long_message(limit);
+            return send_debug(ctx, err_str, why.to_string()).await;
+        }
+    }
 
-    if !youtube_url.status.success() {
-        let why = youtube_url.status;
-        let errors = String::from_utf8(youtube_url.stderr).expect("Invalid error bytes");
-        let err_str = format!("Youtube-dl Error: It likely needs an update, url = {}\n{}\n", &url, &why);
-        return send_error(ctx, err_str, errors).await;
+    if let Err(err_str) = check_public_url(&parsed_url).await {
+        let why = url;
+        return send_debug(ctx, err_str, why).await;
     }
 
-    let youtube_dloutput = match String::from_utf8(youtube_url.stdout) {
-        Ok(res) => res,
+    let job = Job::start(ctx, &discord_name, &announcement).await?;
+    job.stage(JobStage::Downloading).await;
+    let filename = job.files().filename;
+
+    let audio_url = if is_direct_media(&parsed_url).await {
+        debug!("Downloading {} without youtube-dl", &url);
+        parsed_url
+    } else {
+        let youtube_url = match job.run(Command::new("yt-dlp").arg("--no-playlist").arg("-g").arg(&url)).await {
+            Ok(output) => output,
+            Err(why) => {
+                job.fail(&why).await;
+                let err_str = format!("Failed to run youtube-dl, url = {}", &url);
+                return send_error(ctx, err_str, why).await;
+            }
+        };
+
+        if !youtube_url.status.success() {
+            job.fail("youtube-dl failed").await;
+            let why = youtube_url.status;
+            let errors = String::from_utf8(youtube_url.stderr).expect("Invalid error bytes");
+            let err_str = format!("Youtube-dl Error: It likely needs an update, url = {}\n{}\n", &url, &why);
+            return send_error(ctx, err_str, errors).await;
+        }
+
+        let youtube_dloutput = match String::from_utf8(youtube_url.stdout) {
+            Ok(res) => res,
+            Err(why) => {
+                job.fail("Invalid youtube-dl output").await;
+                let err_str = "Failed to parse youtube-dl output".to_string();
+                return send_error(ctx, err_str, why.to_string()).await;
+            }
+        };
+        let lines = youtube_dloutput.lines();
+
+        let audio_url = match lines.last() {
+            Some(line) => line,
+            None => {
+                job.fail("Invalid youtube-dl output").await;
+                let why = url;
+                let err_str = "Youtube empty info".to_string();
+                return send_error(ctx, err_str, why.to_string()).await;
+            }
+        };
+
+        match Url::parse(audio_url) {
+            Ok(parsed_audio_url) => parsed_audio_url,
+            Err(why) => {
+                job.fail(&why.to_string()).await;
+                let err_str = "Youtube-dl returned an invalid link".to_string();
+                return send_error(ctx, err_str, why.to_string()).await;
+            }
+        }
+    };
+
+    // the extracted link is checked like the submitted one while downloading, ffmpeg only reads the local file
+    let download_path = format!("{}{}.download", processing_path, &filename);
+    if let Err(why) = job.download(&audio_url, &download_path).await {
+        job.fail(&why).await;
+        let err_str = why;
+        return send_debug(ctx, err_str, audio_url.to_string()).await;
+    }
+
+    job.stage(JobStage::Trimming).await;
+
+    let download_res = job
+        .run(Command::new("ffmpeg")
+            .arg("-y")
+            .arg("-protocol_whitelist")
+            .arg("file")
+            .arg("-ss")
+            .arg(format!("{:.3}", start_time.as_secs_f64()))
+            .arg("-to")
+            .arg(format!("{:.3}", end_time.as_secs_f64()))
+            .arg("-i")
+            .arg(format!("file:{}.download", &filename))
+            .arg("-vn")
+            .arg("-f")
+            .arg("flac")
+            .arg(format!("file:{}", &filename))
+            .current_dir(processing_path))
+        .await;
+    let _ = fs::remove_file(&download_path);
+
+    let download_status = match download_res {
+        Ok(output) => output.status,
         Err(why) => {
-            let err_str = "Failed to parse youtube-dl output".to_string();
-            return send_error(ctx, err_str, why.to_string()).await;
+            job.fail(&why).await;
+            let err_str = format!("Failed to run ffmpeg to download audio for file {}", &filename);
+            return send_error(ctx, err_str, why).await;
         }
     };
-    let lines = youtube_dloutput.lines();
 
-    let audio_url = match lines.last() {
-        Some(line) => line,
+    if !download_status.success() {
+        job.fail("ffmpeg failed").await;
+        // ffmpeg has no exit code if it was killed by a signal
+        let why = match download_status.code() {
+            Some(code) => code.to_string(),
+            None => download_status.to_string(),
+        };
+        let err_str = format!("Failed to run ffmpeg to download audio for file {}", &filename);
+        return send_error(ctx, err_str, why).await;
+    }
+
+    let options = ProcessOptions {
+        filters,
+        length_limit,
+        auto_trim: auto_trim.unwrap_or(true),
+        loudness: guild_loudness_target(guild_id),
+    };
+
+    return add_new_file(ctx, job, &discord_name, &announcement, &user, options).await;
+}
+
+#[doc = "Add new announcement by recording your voice."]
+#[poise::command(
+    category = "Main Commands",
+    guild_only,
+    prefix_command,
+    slash_command,
+    required_bot_permissions = "SEND_MESSAGES"
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn record(
+    ctx: PContext<'_>,
+    #[description = "The user for which to add an announcement."] user: User,
+    #[description = "Name of the announcement."] announcement: String,
+    #[description = "How long to record in seconds, the length limit by default."]
+    #[min = 1]
+    #[max = 15]
+    seconds: Option<u64>,
+    #[description = "Filter preset to transform audio."] preset: Option<FilterPreset>,
+    #[description = "Presets applied in order, e.g. \"chipmunk echo:500\"."]
+    #[autocomplete = "autocomplete_presets"]
+    presets: Option<String>,
+    #[description = "FFMPEG filters to transform audio."] filters: Option<String>,
+    #[description = "Remove silence and fade out clips that are cut off, on by default."] auto_trim: Option<bool>,
+) -> Result<(), PError> {
+    let length_limit = author_clip_length_limit(ctx).await;
+
+    if let Err(err_str) = validate_name(&announcement) {
+        let why = announcement;
+        return send_debug(ctx, err_str, why).await;
+    }
+
+    let guild_id = match ctx.guild_id() {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+
+    let filters = match resolve_filters(guild_id, preset, presets.as_deref(), filters.as_deref()) {
+        Ok(filters) => filters,
+        Err(why) => {
+            let err_str = format!("Invalid filters: {}", &why);
+            return send_debug(ctx, err_str, why).await;
+        }
+    };
+
+    let channel_id = match user_voice_channel(ctx.serenity_context(), guild_id, ctx.author().id) {
+        Some(channel_id) => channel_id,
         None => {
-            let why = url;
-            let err_str = "Youtube empty info".to_string();
-            return send_error(ctx, err_str, why.to_string()).await;
+            let why = ctx.author().id;
+            let err_str = "You have to be in a voice channel to record an announcement".to_string();
+            return send_debug(ctx, err_str, why.to_string()).await;
         }
     };
 
-    let download_status = Command::new("ffmpeg")
-        .arg("-y")
-        .arg("-ss")
-        .arg(start)
-        .arg("-to")
-        .arg(end)
-        .arg("-i")
-        .arg(audio_url)
-        .arg("-vn")
-        .arg("-f")
-        .arg("flac")
-        .arg(format!("file:{}", &filename))
-        .current_dir(&processing_path)
-        .output()
-        .expect("failed to run ffmpeg")
-        .status;
+    let discord_name = match user.nick_in(&ctx, guild_id).await {
+        Some(nick) => nick,
+        None => user.name.clone(),
+    };
+
+    let seconds = seconds
+        .unwrap_or((length_limit + TRIM_TOLERANCE).ceil() as u64)
+        .clamp(1, MAX_RECORDING_DURATION);
 
-    if !download_status.success() {
-        let why = download_status.code().expect("no exit code");
-        let err_str = format!("Failed to run ffmpeg to download audio for file {}", &filename);
-        return send_error(ctx, err_str, why.to_string()).await;
+    let recording_reply = |description: String| {
+        CreateReply::default()
+            .embed(CreateEmbed::new()
+                .title(format!("Recording `{}` for {}", &announcement, &discord_name))
+                .description(description)
+                .colour(Colour::from_rgb(128, 128, 128))
+            )
+    };
+
+    let handle = ctx.send(recording_reply("Joining your voice channel".to_string())).await?;
+
+    // joining takes a moment, the countdown only starts once the bot listens
+    let recorder = match start_recording(ctx.serenity_context(), guild_id, channel_id, ctx.author().id).await {
+        Ok(recorder) => recorder,
+        Err(why) => {
+            handle.edit(ctx, recording_reply("Recording failed".to_string())).await?;
+            return send_debug(ctx, why.clone(), why).await;
+        }
+    };
+
+    let countdown_res = async {
+        for remaining in (1..=RECORDING_COUNTDOWN).rev() {
+            handle.edit(ctx, recording_reply(format!("Starting in **{}**", remaining))).await?;
+            time::sleep(Duration::from_secs(1)).await;
+        }
+        recorder.restart();
+        handle.edit(ctx, recording_reply(format!("Recording for **{}** seconds, speak now", seconds))).await?;
+        time::sleep(Duration::from_secs(seconds)).await;
+        Ok::<_, PError>(())
+    }
+    .await;
+
+    // the decoder is switched off again even if updating the message failed
+    let record_res = recorder.stop().await;
+    countdown_res?;
+
+    let samples = match record_res {
+        Ok(samples) => samples,
+        Err(why) => {
+            handle.edit(ctx, recording_reply("Recording failed".to_string())).await?;
+            return send_debug(ctx, why.clone(), why).await;
+        }
+    };
+    handle.edit(ctx, recording_reply("Recording finished".to_string())).await?;
+
+    let job = Job::start(ctx, &discord_name, &announcement).await?;
+    let filename = job.files().filename;
+    let processing_path = "/config/processing/";
+
+    if let Err(why) = write_wav(&format!("{}{}", processing_path, &filename), &samples) {
+        job.fail(&why).await;
+        let err_str = "Error writing file".to_string();
+        return send_error(ctx, err_str, why).await;
+    }
+
+    let options = ProcessOptions {
+        filters,
+        length_limit: Some(length_limit),
+        auto_trim: auto_trim.unwrap_or(true),
+        loudness: guild_loudness_target(guild_id),
+    };
+
+    return add_new_file(ctx, job, &discord_name, &announcement, &user, options).await;
+}
+
+/// Downloads the attachment into the processing directory and checks that it contains usable audio.
+///
+/// The audio track of files symphonia can't read, like videos, is extracted with ffmpeg.
+pub async fn download_attachment(
+    ctx: PContext<'_>,
+    job: &Job<'_>,
+    attachment: &Attachment,
+    filename: &str,
+) -> Result<(), PError> {
+    let processing_path = "/config/processing/";
+    let upload_filename = format!("{}.upload", &filename);
+    let upload_path = format!("{}{}", processing_path, &upload_filename);
+    let path = format!("{}{}", processing_path, &filename);
+
+    if attachment.size > MAX_ATTACHMENT_SIZE {
+        let why = attachment.size;
+        let err_str = format!("The file is too large, files can be at most {} MB", MAX_ATTACHMENT_SIZE / 1024 / 1024);
+        return match send_debug(ctx, err_str.clone(), why.to_string()).await {
+            Ok(_) => Err(err_str.into()),
+            Err(why) => Err(why),
+        };
+    }
+
+    let content = match attachment.download().await {
+        Ok(content) => content,
+        Err(why) => {
+            let err_str = "Error downloading attachment".to_string();
+            return match send_error(ctx, err_str, why.to_string()).await {
+                Ok(_) => Err(Into::into(why)),
+                Err(why) => Err(why),
+            };
+        }
+    };
+
+    let mut file = match File::create(&upload_path) {
+        Ok(file) => file,
+        Err(why) => {
+            let err_str = "Error creating file".to_string();
+            return match send_error(ctx, err_str, why.to_string()).await {
+                Ok(_) => Err(Into::into(why)),
+                Err(why) => Err(why),
+            };
+        }
+    };
+
+    if let Err(why) = file.write_all(&content) {
+        let _ = fs::remove_file(&upload_path);
+        let err_str = "Error writing file".to_string();
+        return match send_error(ctx, err_str, why.to_string()).await {
+            Ok(_) => Err(Into::into(why)),
+            Err(why) => Err(why),
+        };
+    }
+
+    let is_video = attachment
+        .content_type
+        .as_ref()
+        .is_some_and(|content_type| content_type.starts_with("video/"));
+
+    let probe_res = if is_video {
+        Err("Video container".to_string())
+    } else {
+        probe_audio(&upload_path)
+    };
+
+    let media_info = match probe_res {
+        Ok(media_info) => fs::rename(&upload_path, &path).map(|_| media_info).map_err(|why| why.to_string()),
+        Err(why) => {
+            debug!("Extracting audio of {}: {}", &upload_filename, why);
+            let extract_res = extract_audio(job, processing_path, &upload_filename, filename).await;
+            let _ = fs::remove_file(&upload_path);
+            extract_res.and_then(|_| probe_audio(&path))
+        }
+    };
+
+    let check_res = match media_info {
+        Ok(media_info) => check_media(&media_info),
+        Err(why) => {
+            debug!("No usable audio in {}: {}", &filename, why);
+            Err("The file doesn't contain any audio that can be used".to_string())
+        }
+    };
+
+    if let Err(err_str) = check_res {
+        let _ = fs::remove_file(&path);
+        let why = attachment.filename.clone();
+        return match send_debug(ctx, err_str.clone(), why).await {
+            Ok(_) => Err(err_str.into()),
+            Err(why) => Err(why),
+        };
     }
 
-    return add_new_file(ctx, &discord_name, &announcement, &user, filters.as_ref(), override_length).await;
+    Ok(())
+}
+
+/// Copies the first audio track of a file into a flac file.
+async fn extract_audio(job: &Job<'_>, processing_path: &str, filename: &str, audio_filename: &str) -> Result<(), String> {
+    let extract_output = job
+        .run(Command::new("ffmpeg")
+            .arg("-y")
+            .arg("-i")
+            .arg(format!("file:{}", &filename))
+            .arg("-map")
+            .arg("0:a:0")
+            .arg("-vn")
+            .arg("-f")
+            .arg("flac")
+            .arg(format!("file:{}", &audio_filename))
+            .current_dir(processing_path))
+        .await?;
+
+    if !extract_output.status.success() {
+        return Err(format!("ffmpeg exited with {}", extract_output.status));
+    }
+
+    Ok(())
 }
 
 pub async fn add_new_file(
     ctx: PContext<'_>,
+    job: Job<'_>,
     name: &String,
     announcement_name: &String,
     user: &User,
-    filters: Option<&String>,
-    override_length: bool,
+    options: ProcessOptions,
 ) -> Result<(), PError> {
-    let filename = format!("{}.flac", &announcement_name);
-    let processed_filename = format!("{}{}", &announcement_name, ".processed.flac");
+    let files = job.files();
+    let ProcessingFiles { filename, processed_filename } = &files;
     let processing_path = "/config/processing/";
-    let indexed_path = "/config/index/";
-    let db_path = Path::new("/config/database/db.sqlite");
 
-    let normalize_and_filter_string;
-    if filters.is_some() {
-        normalize_and_filter_string = format!("{},loudnorm", filters.unwrap());
-    } else {
-        normalize_and_filter_string = "loudnorm".to_string();
+    job.stage(JobStage::Normalising).await;
+    let filter_res = process_file(&job, processing_path, filename, processed_filename, &options, None).await;
+
+    if let Err(why) = filter_res {
+        job.fail(&why).await;
+        delete_processing_files(processing_path, filename, processed_filename);
+        let err_str = format!("Failed to apply audio filter for file {}", &filename);
+        return send_error(ctx, err_str, why).await;
     }
+    job.done().await;
+
+    let processed_path = format!("{}{}", processing_path, &processed_filename);
+    if let Some(duplicate) = find_duplicate(&processed_path, name, announcement_name) {
+        let use_existing = match offer_duplicate(ctx, &duplicate).await {
+            Ok(use_existing) => use_existing,
+            Err(why) => {
+                delete_processing_files(processing_path, filename, processed_filename);
+                return Err(why);
+            }
+        };
+
+        if use_existing {
+            return link_duplicate(ctx, name, user, &files, &duplicate).await;
+        }
+    }
+
+    let confirmed = match preview_file(ctx, name, announcement_name, user, &files, &options).await {
+        Ok(confirmed) => confirmed,
+        Err(why) => {
+            delete_processing_files(processing_path, filename, processed_filename);
+            return Err(why);
+        }
+    };
+
+    if !confirmed {
+        delete_processing_files(processing_path, filename, processed_filename);
+        return Ok(());
+    }
+
+    commit_file(ctx, None, name, announcement_name, user, ctx.author().id, &files, false).await
+}
+
+/// Warns that the new clip sounds like an existing one and asks whether to use that one instead.
+async fn offer_duplicate(ctx: PContext<'_>, duplicate: &Duplicate) -> Result<bool, PError> {
+    let reply = CreateReply::default()
+        .embed(CreateEmbed::new()
+            .title("This sounds like an existing announcement")
+            .description(format!(
+                "`{}` of {} is {:.0}% similar. Do you want to use it instead of uploading a copy?",
+                &duplicate.announcement_name,
+                &duplicate.name,
+                duplicate.similarity * 100.0
+            ))
+            .colour(Colour::from_rgb(251, 192, 45))
+        )
+        .components(create_duplicate_buttons())
+        .ephemeral(true);
+
+    let handle = ctx.send(reply).await?;
+    let message = handle.message().await?.into_owned();
+
+    let interaction = message
+        .await_component_interaction(ctx)
+        .author_id(ctx.author().id)
+        .timeout(PREVIEW_TIMEOUT_DURATION)
+        .await;
+
+    let _ = handle.delete(ctx).await;
+
+    match interaction {
+        Some(interaction) => {
+            interaction
+                .create_response(&ctx, CreateInteractionResponse::Acknowledge)
+                .await?;
+            Ok(interaction.data.custom_id == DUPLICATE_USE_EXISTING_BUTTON)
+        }
+        None => Ok(false),
+    }
+}
+
+/// Commits a copy of the existing clip instead of the upload, moderated like any other submission.
+async fn link_duplicate(
+    ctx: PContext<'_>,
+    name: &String,
+    user: &User,
+    files: &ProcessingFiles,
+    duplicate: &Duplicate,
+) -> Result<(), PError> {
+    let processing_path = "/config/processing/";
+
+    if duplicate.name != *name && Path::new(&index_path(name, &duplicate.announcement_name)).exists() {
+        delete_processing_files(processing_path, &files.filename, &files.processed_filename);
+        let why = index_path(name, &duplicate.announcement_name);
+        let err_str = format!("{} already has an announcement named {}", name, &duplicate.announcement_name);
+        return send_debug(ctx, err_str, why).await;
+    }
+
+    if let Err(err_str) = copy_to_processing(files, &duplicate.name, &duplicate.announcement_name) {
+        delete_processing_files(processing_path, &files.filename, &files.processed_filename);
+        let why = name;
+        return send_error(ctx, err_str, why.to_string()).await;
+    }
+
+    commit_file(ctx, None, name, &duplicate.announcement_name, user, ctx.author().id, files, false).await
+}
+
+/// Runs ffmpeg on the downloaded file in the processing directory, optionally trimming it to `trim` first.
+///
+/// The applied steps are stored in the `processing` tag of the processed file.
+pub async fn process_file(
+    job: &Job<'_>,
+    processing_path: &str,
+    filename: &str,
+    processed_filename: &str,
+    options: &ProcessOptions,
+    trim: Option<(&str, &str)>,
+) -> Result<(), String> {
+    let mut steps = vec![];
+    let mut filter_chain = vec![];
+    let length_limit = options.length_limit.map(|limit| format!("{}", limit));
 
     let mut args = vec!["-y"];
-    if !override_length {
-        args.push("-t");
-        args.push("00:00:06");
-    }
-
-    let filter_output = Command::new("ffmpeg")
-        .args(args)
-        .arg("-i")
-        .arg(format!("file:{}", &filename))
-        .arg("-filter:a")
-        .arg(&normalize_and_filter_string)
-        .arg("-ar")
-        .arg("48000")
-        .arg("-f")
-        .arg("flac")
-        .arg(format!("file:{}", &processed_filename))
-        .current_dir(&processing_path)
-        .output()
-        .expect("Failed to run ffmpeg");
-
-    let mut ffmpeg_length_str = "";
-    if !override_length {
-        ffmpeg_length_str = " -t 00:00:06"
+    if let Some((start, end)) = trim {
+        args.push("-ss");
+        args.push(start);
+        args.push("-to");
+        args.push(end);
+        steps.push(format!("trimmed to {} - {}", start, end));
     }
+
+    if options.auto_trim {
+        filter_chain.push(SILENCE_FILTER.to_string());
+        steps.push("removed leading silence".to_string());
+    }
+    if let Some(filters) = &options.filters {
+        filter_chain.push(filters.clone());
+        steps.push(format!("filters {}", filters));
+    }
+    if let Some(length_limit) = &length_limit {
+        // without silence removal the input is cut, so the limit doesn't depend on the filters
+        if options.auto_trim {
+            filter_chain.push(format!("atrim=duration={}", length_limit));
+        } else {
+            args.push("-t");
+            args.push(length_limit);
+        }
+    }
+    // reversing buffers the whole clip, so trailing silence is only removed from bounded clips
+    if options.auto_trim && length_limit.is_some() {
+        filter_chain.push(format!("areverse,{},areverse", SILENCE_FILTER));
+        steps.push("removed trailing silence".to_string());
+    }
+
+    let measurement = measure_loudness(job, processing_path, filename, &args, &filter_chain, &options.loudness).await?;
+    filter_chain.push(options.loudness.apply_filter(&measurement));
+    steps.push(format!(
+        "normalised to {} LUFS and {} dBTP",
+        options.loudness.integrated, options.loudness.true_peak
+    ));
+
+    let normalize_and_filter_string = filter_chain.join(",");
+
+    let mut tags = vec![
+        format!("{}={}", PROCESSING_TAG, steps.join(", ")),
+        format!("{}={}", LENGTH_LIMIT_TAG, length_limit.as_deref().unwrap_or("none")),
+        format!("{}={}", AUTO_TRIM_TAG, options.auto_trim as u8),
+    ];
+    if let Some(filters) = &options.filters {
+        tags.push(format!("{}={}", FILTERS_TAG, filters));
+    }
+    tags.push(format!("{}={}", LOUDNESS_TARGET_TAG, options.loudness.integrated));
+    tags.push(format!("{}={}", TRUE_PEAK_TARGET_TAG, options.loudness.true_peak));
+    tags.extend(measurement.tags());
+    if let Some((start, end)) = trim {
+        tags.push(format!("{}={}", TRIM_START_TAG, start));
+        tags.push(format!("{}={}", TRIM_END_TAG, end));
+    }
+    let metadata_args = tags.iter().flat_map(|tag| ["-metadata", tag.as_str()]).collect::<Vec<_>>();
+
+    let filter_output = job
+        .run(Command::new("ffmpeg")
+            .args(&args)
+            .arg("-i")
+            .arg(format!("file:{}", &filename))
+            .arg("-filter:a")
+            .arg(&normalize_and_filter_string)
+            .arg("-ar")
+            .arg("48000")
+            .args(&metadata_args)
+            .arg("-f")
+            .arg("flac")
+            .arg(format!("file:{}", &processed_filename))
+            .current_dir(processing_path))
+        .await?;
+
     debug!(
-        "ffmpeg -y{} -i {} -filter:a {} -ar 48000 -f flac {}",
-        ffmpeg_length_str,
+        "ffmpeg {} -i {} -filter:a {} -ar 48000 {} -f flac {}",
+        args.join(" "),
         format!("file:{}", &filename),
         &normalize_and_filter_string,
+        metadata_args.join(" "),
         format!("file:{}", &processed_filename)
     );
 
     if !filter_output.status.success() {
-        let _ = delete_processing_files(&processing_path, &filename, &processed_filename);
-        let why = filter_output.status.code().expect("no exit code");
-        let err_str = format!("Failed to apply audio filter for file {}", &filename);
-        return send_error(ctx, err_str, why.to_string()).await;
+        return Err(format!("ffmpeg exited with {}", filter_output.status));
+    }
+
+    if let (true, Some(limit)) = (options.auto_trim, options.length_limit) {
+        let duration = probe_duration(job, processing_path, processed_filename).await?;
+        if duration >= limit - 0.01 {
+            steps.push("faded out".to_string());
+            fade_out(job, processing_path, processed_filename, duration, &steps).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs the filters with a measuring loudnorm pass without writing the result.
+async fn measure_loudness(
+    job: &Job<'_>,
+    processing_path: &str,
+    filename: &str,
+    args: &[&str],
+    filter_chain: &[String],
+    loudness: &LoudnessTarget,
+) -> Result<LoudnessMeasurement, String> {
+    let mut measure_chain = filter_chain.to_vec();
+    measure_chain.push(loudness.measure_filter());
+    let measure_string = measure_chain.join(",");
+
+    let measure_output = job
+        .run(Command::new("ffmpeg")
+            .args(args)
+            .arg("-i")
+            .arg(format!("file:{}", &filename))
+            .arg("-filter:a")
+            .arg(&measure_string)
+            .arg("-f")
+            .arg("null")
+            .arg("-")
+            .current_dir(processing_path))
+        .await?;
+
+    debug!("ffmpeg {} -i {} -filter:a {} -f null -", args.join(" "), format!("file:{}", &filename), &measure_string);
+
+    if !measure_output.status.success() {
+        return Err(format!("ffmpeg exited with {}", measure_output.status));
     }
 
-    let name_path = format!("{}{}", &indexed_path, &name);
+    LoudnessMeasurement::parse(&String::from_utf8_lossy(&measure_output.stderr))
+}
+
+/// Returns the duration of a file in seconds.
+async fn probe_duration(job: &Job<'_>, processing_path: &str, filename: &str) -> Result<f64, String> {
+    let probe_output = job
+        .run(Command::new("ffprobe")
+            .arg("-v")
+            .arg("error")
+            .arg("-show_entries")
+            .arg("format=duration")
+            .arg("-of")
+            .arg("default=noprint_wrappers=1:nokey=1")
+            .arg(format!("file:{}", &filename))
+            .current_dir(processing_path))
+        .await?;
 
-    if !Path::new(&name_path).exists() {
-        let _ = fs::create_dir(name_path).expect("Failed to create directory.");
+    if !probe_output.status.success() {
+        return Err(format!("ffprobe exited with {}", probe_output.status));
     }
 
-    let db = match Connection::open(&db_path) {
-        Ok(db) => db,
-        Err(why) => {
-            let err_str = "Failed to open database".to_string();
-            return send_error(ctx, err_str, why.to_string()).await;
-        }
-    };
+    String::from_utf8_lossy(&probe_output.stdout)
+        .trim()
+        .parse::<f64>()
+        .map_err(|why| format!("Invalid duration from ffprobe: {}", why))
+}
 
-    let user_id = user.id.get();
-    let insert_res = db.execute(
-        "INSERT OR IGNORE INTO names (name, user_id, active_file)
-              VALUES (?1, ?2, ?3) 
-              ON CONFLICT(name, user_id) DO UPDATE SET
-                active_file=excluded.active_file",
-        params![&name, user_id as i64, announcement_name],
-    );
-    if insert_res.is_err() {
-        let why = insert_res.err().unwrap();
-        let err_str = "Failed to insert new name".to_string();
-        return send_error(ctx, err_str, why.to_string()).await;
-    };
+/// Fades out the end of a clip that was cut at the length limit.
+async fn fade_out(
+    job: &Job<'_>,
+    processing_path: &str,
+    processed_filename: &str,
+    duration: f64,
+    steps: &[String],
+) -> Result<(), String> {
+    let faded_filename = format!("{}.faded.flac", processed_filename);
+    let fade_filter = format!("afade=t=out:st={:.3}:d={}", duration - FADE_OUT_DURATION, FADE_OUT_DURATION);
 
-    let _ = match fs::rename(
-        format!("{}{}", &processing_path, &processed_filename),
-        format!("{}{}/{}.flac", &indexed_path, &name, announcement_name),
-    ) {
-        Ok(res) => res,
-        Err(why) => {
-            let _ = delete_processing_files(&processing_path, &filename, &processed_filename);
-            let err_str = format!("Failed to rename file {}", &processed_filename);
-            return send_error(ctx, err_str, why.to_string()).await;
+    let fade_output = job
+        .run(Command::new("ffmpeg")
+            .arg("-y")
+            .arg("-i")
+            .arg(format!("file:{}", &processed_filename))
+            .arg("-filter:a")
+            .arg(&fade_filter)
+            .arg("-metadata")
+            .arg(format!("{}={}", PROCESSING_TAG, steps.join(", ")))
+            .arg("-f")
+            .arg("flac")
+            .arg(format!("file:{}", &faded_filename))
+            .current_dir(processing_path))
+        .await?;
+
+    if !fade_output.status.success() {
+        let _ = fs::remove_file(format!("{}{}", processing_path, &faded_filename));
+        return Err(format!("ffmpeg exited with {}", fade_output.status));
+    }
+
+    fs::rename(
+        format!("{}{}", processing_path, &faded_filename),
+        format!("{}{}", processing_path, &processed_filename),
+    )
+    .map_err(|why| why.to_string())
+}
+
+/// Shows the processed clip to the submitter and lets them play, re-trim, confirm or discard it.
+///
+/// Returns whether the clip was confirmed.
+pub async fn preview_file(
+    ctx: PContext<'_>,
+    name: &String,
+    announcement_name: &String,
+    user: &User,
+    files: &ProcessingFiles,
+    options: &ProcessOptions,
+) -> Result<bool, PError> {
+    let ProcessingFiles { filename, processed_filename } = files;
+    let processing_path = "/config/processing/";
+    let processed_path = format!("{}{}", &processing_path, &processed_filename);
+
+    loop {
+        let attachment = CreateAttachment::path(&processed_path).await?;
+        let length_limit = match options.length_limit {
+            Some(limit) => format!("Clips are cut to {} seconds for you", limit),
+            None => "Length limit overridden".to_string(),
+        };
+        let reply = CreateReply::default()
+            .embed(CreateEmbed::new()
+                .title(format!("Preview new file for {}", name))
+                .description(format!("`{}` [{}]", &announcement_name, &user.mention()))
+                .footer(CreateEmbedFooter::new(length_limit))
+                .colour(Colour::from_rgb(128, 128, 128))
+            )
+            .attachment(attachment)
+            .components(create_preview_buttons())
+            .ephemeral(true);
+
+        let handle = ctx.send(reply).await?;
+        let message = handle.message().await?.into_owned();
+
+        let mut collector = message
+            .await_component_interactions(ctx)
+            .author_id(ctx.author().id)
+            .timeout(PREVIEW_TIMEOUT_DURATION)
+            .stream();
+
+        let mut retrimmed = false;
+        while let Some(interaction) = collector.next().await {
+            match interaction.data.custom_id.as_str() {
+                PREVIEW_PLAY_BUTTON => {
+                    let guild_id = match ctx.guild_id() {
+                        Some(guild_id) => guild_id,
+                        None => continue,
+                    };
+
+                    let channel_id = match user_voice_channel(ctx.serenity_context(), guild_id, interaction.user.id) {
+                        Some(channel_id) => channel_id,
+                        None => {
+                            let interaction_response = CreateInteractionResponseMessage::default()
+                                .content("Join a voice channel to play the preview")
+                                .ephemeral(true);
+                            interaction
+                                .create_response(&ctx, CreateInteractionResponse::Message(interaction_response))
+                                .await?;
+                            continue;
+                        }
+                    };
+
+                    interaction
+                        .create_response(&ctx, CreateInteractionResponse::Acknowledge)
+                        .await?;
+                    let announcement = Announcement::new(name, user.id.get(), &processed_path);
+                    play_file(ctx.serenity_context(), channel_id, guild_id, &[announcement], PlaybackEvent::Preview).await;
+                }
+                PREVIEW_CONFIRM_BUTTON => {
+                    let interaction_response = CreateInteractionResponseMessage::default()
+                        .components(vec![]);
+                    interaction
+                        .create_response(&ctx, CreateInteractionResponse::UpdateMessage(interaction_response))
+                        .await?;
+                    return Ok(true);
+                }
+                PREVIEW_RETRIM_BUTTON => {
+                    let modal = match poise::execute_modal_on_component_interaction::<RetrimModal>(
+                        ctx,
+                        interaction.clone(),
+                        None,
+                        Some(PREVIEW_TIMEOUT_DURATION),
+                    )
+                    .await?
+                    {
+                        Some(modal) => modal,
+                        None => continue,
+                    };
+
+                    let (start, end) = match (parse_duration(&modal.start), parse_duration(&modal.end)) {
+                        (Ok(start), Ok(end)) if start < end => (start, end),
+                        _ => {
+                            let why = format!("{} - {}", modal.start, modal.end);
+                            let err_str = "Please provide a valid start and end time".to_string();
+                            send_debug(ctx, err_str, why).await?;
+                            continue;
+                        }
+                    };
+
+                    if let Some(limit) = options.length_limit {
+                        if (end - start).as_secs_f64() > limit + TRIM_TOLERANCE {
+                            let why = (end - start).as_secs_f64();
+                            let err_str = too_long_message(limit);
+                            send_debug(ctx, err_str, why.to_string()).await?;
+                            continue;
+                        }
+                    }
+
+                    let job = Job::start(ctx, name, announcement_name).await?;
+                    job.stage(JobStage::Trimming).await;
+
+                    let trim = (modal.start.as_str(), modal.end.as_str());
+                    let filter_res = process_file(
+                        &job,
+                        processing_path,
+                        filename,
+                        processed_filename,
+                        options,
+                        Some(trim),
+                    )
+                    .await;
+
+                    if let Err(why) = filter_res {
+                        job.fail(&why).await;
+                        let err_str = format!("Failed to re-trim file {}", &filename);
+                        send_error(ctx, err_str, why).await?;
+                        return Ok(false);
+                    }
+                    job.done().await;
+
+                    retrimmed = true;
+                    break;
+                }
+                PREVIEW_DISCARD_BUTTON => {
+                    let interaction_response = CreateInteractionResponseMessage::default()
+                        .content("Discarded")
+                        .embeds(vec![])
+                        .components(vec![]);
+                    interaction
+                        .create_response(&ctx, CreateInteractionResponse::UpdateMessage(interaction_response))
+                        .await?;
+                    return Ok(false);
+                }
+                _ => {
+                    let why = &interaction.data.custom_id;
+                    let err_str = "Unknown component interaction".to_string();
+                    send_warning(ctx, err_str, why.to_string()).await?;
+                    return Ok(false);
+                }
+            }
         }
-    };
 
-    let text_path = format!("/config/queue/{}", &name);
+        let _ = handle.delete(ctx).await;
+        if !retrimmed {
+            debug!("Timeout");
+            return Ok(false);
+        }
+    }
+}
 
-    let _ = match fs::remove_file(&text_path) {
-        Ok(res) => res,
-        Err(why) => {
-            debug!("Failed to remove queue file {} ERROR: {}", &text_path, why);
+/// Moves the processed clip into the index and makes it the active announcement.
+///
+/// Edits of an existing clip replace it without changing the active announcement.
+#[allow(clippy::too_many_arguments)]
+pub async fn commit_file(
+    ctx: PContext<'_>,
+    followup: Option<&ComponentInteraction>,
+    name: &String,
+    announcement_name: &String,
+    user: &User,
+    submitter_id: UserId,
+    files: &ProcessingFiles,
+    edit: bool,
+) -> Result<(), PError> {
+    let ProcessingFiles { filename, processed_filename } = files;
+    let processing_path = "/config/processing/";
+    let processed_path = format!("{}{}", &processing_path, &processed_filename);
+    let original_path = format!("{}{}", &processing_path, &filename);
+
+    if let Some(guild_id) = ctx.guild_id() {
+        if get_bool_setting(guild_id, MODERATION, false) {
+            let submit_res = submit_for_moderation(ctx, followup, name, announcement_name, user, submitter_id, files, edit).await;
+            delete_processing_files(processing_path, filename, processed_filename);
+            return submit_res;
         }
+    }
+
+    let install_res = if edit {
+        replace_file(name, announcement_name, &processed_path)
+    } else {
+        install_file(name, announcement_name, user.id.get(), &processed_path, Some(&original_path))
     };
+    delete_processing_files(processing_path, filename, processed_filename);
 
-    let _ = delete_processing_files(&processing_path, &filename, &processed_filename);
+    if let Err(err_str) = install_res {
+        let why = name;
+        return send_error_to(ctx, followup, err_str, why.to_string()).await;
+    }
 
+    let action = if edit { "edited" } else { "added new" };
     let reply = CreateReply::default()
         .embed(CreateEmbed::new()
-            .title(format!("Successfully added new file for {}", name))
+            .title(format!("Successfully {} file for {}", action, name))
             .description(format!("`{}` [{}]", &announcement_name, &user.mention()))
             .colour(Colour::from_rgb(128, 128, 128))
         );
 
-    ctx.send(reply)
-        .await
-        .map(drop)
-        .map_err(Into::into)
+    send_reply(ctx, followup, reply).await
 }
 
-fn delete_processing_files(processing_path: &str, filename: &str, processed_filename: &str) {
-    let _ = match fs::remove_file(format!("{}{}", &processing_path, &filename)) {
+pub fn delete_processing_files(processing_path: &str, filename: &str, processed_filename: &str) {
+    match fs::remove_file(format!("{}{}", &processing_path, &filename)) {
         Ok(res) => res,
         Err(why) => {
             debug!("Failed to remove queue file {}{} ERROR: {}", &processing_path, &filename, why);
         }
     };
 
-    let _ = match fs::remove_file(format!("{}{}", &processing_path, &processed_filename)) {
+    match fs::remove_file(format!("{}{}", &processing_path, &processed_filename)) {
         Ok(res) => res,
         Err(why) => {
             debug!("Failed to remove queue file {}{} ERROR: {}", &processing_path, &processed_filename, why);
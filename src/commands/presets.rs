@@ -0,0 +1,190 @@
+use poise::CreateReply;
+
+use serenity::{
+    all::CreateEmbed,
+    model::colour::Colour,
+};
+
+use crate::{
+    util::{
+        check::is_admin,
+        consts::{ELEMENT_LABEL_LENGTH, EMBED_DESCRIPTION_MAX_LENGTH},
+        filters::validate_filters,
+        presets::{get_guild_presets, remove_guild_preset, set_guild_preset, FilterPreset},
+        util::{send_debug, send_error}
+    },
+    PContext, PError,
+};
+
+#[doc = "List, add or remove audio filter presets."]
+#[poise::command(
+    category = "Main Commands",
+    guild_only,
+    prefix_command,
+    slash_command,
+    required_bot_permissions = "SEND_MESSAGES",
+    subcommands("list_presets", "add_preset", "remove_preset")
+)]
+pub async fn presets(_ctx: PContext<'_>) -> Result<(), PError> {
+    Ok(())
+}
+
+#[doc = "List the audio filter presets that can be used in this server."]
+#[poise::command(
+    rename = "list",
+    category = "Main Commands",
+    guild_only,
+    prefix_command,
+    slash_command,
+    required_bot_permissions = "SEND_MESSAGES"
+)]
+pub async fn list_presets(ctx: PContext<'_>) -> Result<(), PError> {
+    let guild_id = match ctx.guild_id() {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+
+    let guild_presets = match get_guild_presets(guild_id) {
+        Ok(guild_presets) => guild_presets,
+        Err(why) => {
+            let err_str = "Failed to query presets".to_string();
+            return send_error(ctx, err_str, why.to_string()).await;
+        }
+    };
+
+    let mut content = "".to_string();
+    for preset in FilterPreset::all() {
+        content.push_str(&format!("• `{}` {}\n", preset.usage(), preset.description()));
+    }
+    for (name, filters) in guild_presets {
+        let line_str = format!("• `{}` {}\n", name, filters);
+        if content.chars().count() + line_str.chars().count() > EMBED_DESCRIPTION_MAX_LENGTH {
+            break;
+        }
+        content.push_str(&line_str);
+    }
+
+    let reply = CreateReply::default()
+        .embed(CreateEmbed::new()
+            .title("Filter presets")
+            .description(content)
+            .colour(Colour::from_rgb(128, 128, 128))
+        );
+
+    ctx.send(reply)
+        .await
+        .map(drop)
+        .map_err(Into::into)
+}
+
+#[doc = "Define a new audio filter preset for this server."]
+#[poise::command(
+    rename = "add",
+    category = "Admin Commands",
+    guild_only,
+    prefix_command,
+    slash_command,
+    required_bot_permissions = "SEND_MESSAGES"
+)]
+pub async fn add_preset(
+    ctx: PContext<'_>,
+    #[description = "Name of the preset."] name: String,
+    #[description = "FFMPEG filters the preset applies."] filters: String,
+) -> Result<(), PError> {
+    if !is_admin(ctx).await {
+        let why = ctx.author().id;
+        let err_str = "You are not allowed to change the presets!".to_string();
+        return send_debug(ctx, err_str, why.to_string()).await;
+    }
+
+    let guild_id = match ctx.guild_id() {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+
+    if name.is_empty()
+        || name.chars().count() > ELEMENT_LABEL_LENGTH
+        || name.contains(|c: char| c.is_whitespace() || c == '+' || c == ':')
+    {
+        let why = name;
+        let err_str = "Preset names have to be a single word without `+` or `:`".to_string();
+        return send_debug(ctx, err_str, why).await;
+    }
+
+    if let Err(why) = validate_filters(&filters) {
+        let err_str = format!("Invalid filters: {}", &why);
+        return send_debug(ctx, err_str, why).await;
+    }
+
+    if FilterPreset::from_name(&name).is_some() {
+        let why = name;
+        let err_str = "There already is a built-in preset with this name".to_string();
+        return send_debug(ctx, err_str, why).await;
+    }
+
+    if let Err(why) = set_guild_preset(guild_id, &name, &filters) {
+        let err_str = "Failed to store preset".to_string();
+        return send_error(ctx, err_str, why.to_string()).await;
+    }
+
+    let reply = CreateReply::default()
+        .embed(CreateEmbed::new()
+            .title(format!("Added preset {}", name.to_lowercase()))
+            .description(format!("`{}`", filters))
+            .colour(Colour::from_rgb(128, 128, 128))
+        );
+
+    ctx.send(reply)
+        .await
+        .map(drop)
+        .map_err(Into::into)
+}
+
+#[doc = "Remove an audio filter preset of this server."]
+#[poise::command(
+    rename = "remove",
+    category = "Admin Commands",
+    guild_only,
+    prefix_command,
+    slash_command,
+    required_bot_permissions = "SEND_MESSAGES"
+)]
+pub async fn remove_preset(
+    ctx: PContext<'_>,
+    #[description = "Name of the preset."] name: String,
+) -> Result<(), PError> {
+    if !is_admin(ctx).await {
+        let why = ctx.author().id;
+        let err_str = "You are not allowed to change the presets!".to_string();
+        return send_debug(ctx, err_str, why.to_string()).await;
+    }
+
+    let guild_id = match ctx.guild_id() {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+
+    match remove_guild_preset(guild_id, &name) {
+        Ok(true) => (),
+        Ok(false) => {
+            let why = name;
+            let err_str = "There is no preset with this name".to_string();
+            return send_debug(ctx, err_str, why).await;
+        }
+        Err(why) => {
+            let err_str = "Failed to remove preset".to_string();
+            return send_error(ctx, err_str, why.to_string()).await;
+        }
+    }
+
+    let reply = CreateReply::default()
+        .embed(CreateEmbed::new()
+            .title(format!("Removed preset {}", name.to_lowercase()))
+            .colour(Colour::from_rgb(128, 128, 128))
+        );
+
+    ctx.send(reply)
+        .await
+        .map(drop)
+        .map_err(Into::into)
+}
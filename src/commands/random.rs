@@ -25,10 +25,10 @@ pub async fn random(ctx: PContext<'_>) -> Result<(), PError> {
 
     let db_path = Path::new("/config/database/db.sqlite");
 
-    let db = match Connection::open(&db_path) {
+    let db = match Connection::open(db_path) {
         Ok(db) => db,
         Err(why) => {
-            let err_str = format!("Failed to open database");
+            let err_str = "Failed to open database".to_string();
             return send_error(ctx, err_str, why.to_string()).await;
         }
     };
@@ -57,12 +57,11 @@ pub async fn random(ctx: PContext<'_>) -> Result<(), PError> {
             return send_error(ctx, err_str, why.to_string()).await;
         };
     
-        let status_string;
-        if random_status == 0 {
-            status_string = "**ON**";
+        let status_string = if random_status == 0 {
+            "**ON**"
         } else {
-            status_string = "**OFF**";
-        }
+            "**OFF**"
+        };
 
         let reply = CreateReply::default()
             .embed(CreateEmbed::new()
@@ -77,7 +76,7 @@ pub async fn random(ctx: PContext<'_>) -> Result<(), PError> {
             .map_err(Into::into)
     } else {
         let why = name;
-        let err_str = format!("Name doesn't exist");
+        let err_str = "Name doesn't exist".to_string();
         return send_debug(ctx, err_str, why.to_string()).await;
     }
 }
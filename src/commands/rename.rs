@@ -0,0 +1,102 @@
+use poise::CreateReply;
+use std::path::Path;
+
+use serenity::{
+    all::CreateEmbed,
+    model::{colour::Colour, prelude::*},
+};
+
+use crate::{
+    util::{
+        autocomplete::autocomplete_announcements,
+        check::is_admin,
+        paths::{index_path, validate_name},
+        submissions::rename_file,
+        util::{send_debug, send_error}
+    },
+    PContext, PError,
+};
+
+fn rename_help() -> String {
+    "\
+Rename an announcement, it stays active if it was.
+Usage:
+!rename <discordname> <announcement-name> <new-name>
+
+Examples:
+!rename @Yzarul \"funy noise\" \"funny noise\"
+
+Only admins may rename the announcements of other users.
+"
+    .to_string()
+}
+
+#[doc = "Rename an announcement."]
+#[poise::command(
+    category = "Main Commands",
+    guild_only,
+    prefix_command,
+    slash_command,
+    required_bot_permissions = "SEND_MESSAGES",
+    help_text_fn = "rename_help"
+)]
+pub async fn rename(
+    ctx: PContext<'_>,
+    #[description = "The user whose announcement to rename."] user: User,
+    #[description = "Current name of the announcement."]
+    #[autocomplete = "autocomplete_announcements"]
+    old: String,
+    #[description = "New name of the announcement."] new: String,
+) -> Result<(), PError> {
+    if user.id != ctx.author().id && !is_admin(ctx).await {
+        let why = ctx.author().id;
+        let err_str = "You may only rename your own announcements!".to_string();
+        return send_debug(ctx, err_str, why.to_string()).await;
+    }
+
+    if let Err(err_str) = validate_name(&new) {
+        let why = new;
+        return send_debug(ctx, err_str, why).await;
+    }
+
+    let guild_id = match ctx.guild_id() {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+
+    let discord_name = match user.nick_in(&ctx, guild_id).await {
+        Some(nick) => nick,
+        None => user.name.clone(),
+    };
+
+    let indexed_path = index_path(&discord_name, &old);
+    if !Path::new(&indexed_path).exists() {
+        let why = indexed_path;
+        let err_str = "Please choose a valid announcement".to_string();
+        return send_debug(ctx, err_str, why).await;
+    }
+
+    let new_indexed_path = index_path(&discord_name, &new);
+    if Path::new(&new_indexed_path).exists() {
+        let why = new_indexed_path;
+        let err_str = format!("An announcement named {} already exists", &new);
+        return send_debug(ctx, err_str, why).await;
+    }
+
+    if let Err(err_str) = rename_file(&discord_name, &old, &new) {
+        let why = indexed_path;
+        return send_error(ctx, err_str, why).await;
+    }
+
+    let reply = CreateReply::default()
+        .embed(CreateEmbed::new()
+            .title(format!("Successfully renamed {}", &old))
+            .description(format!("`{}` is now `{}` [{}]", &old, &new, user.mention()))
+            .colour(Colour::from_rgb(128, 128, 128))
+        );
+
+    ctx.send(reply)
+        .await
+        .map(drop)
+        .map_err(Into::into)
+}
@@ -0,0 +1,199 @@
+use poise::CreateReply;
+use rusqlite::{params, Connection};
+use std::{collections::HashSet, fs, path::Path};
+use tracing::{debug, error};
+
+use serenity::{
+    all::CreateEmbed,
+    model::colour::Colour,
+};
+
+use crate::{
+    commands::new::{delete_processing_files, process_file, ProcessOptions},
+    util::{
+        check::is_admin,
+        fingerprint::update_fingerprint,
+        jobs::{Job, JobStage},
+        loudness::guild_loudness_target,
+        media::read_tags,
+        originals::original_path,
+        paths::{decode_file_stem, index_dir},
+        util::{send_debug, send_error}
+    },
+    PContext, PError,
+};
+
+fn reprocess_help() -> String {
+    "\
+Normalise the announcements of this server's members again from their originals.
+Usage:
+!reprocess [loudness] [true-peak]
+
+Examples:
+!reprocess
+!reprocess -16 -1
+
+Without values this server's loudness target is used.
+Announcements are stored once for every server, a member's announcements play at the
+loudness they were last processed for, also in the other servers they are in.
+"
+    .to_string()
+}
+
+#[doc = "Normalise the announcements of this server's members again, by default to this server's target."]
+#[poise::command(
+    category = "Admin Commands",
+    guild_only,
+    prefix_command,
+    slash_command,
+    required_bot_permissions = "SEND_MESSAGES",
+    help_text_fn = "reprocess_help"
+)]
+pub async fn reprocess(
+    ctx: PContext<'_>,
+    #[description = "Integrated loudness in LUFS."]
+    #[min = -70.0]
+    #[max = -5.0]
+    loudness: Option<f64>,
+    #[description = "Maximum true peak in dBTP."]
+    #[min = -9.0]
+    #[max = 0.0]
+    true_peak: Option<f64>,
+) -> Result<(), PError> {
+    if !is_admin(ctx).await {
+        let why = ctx.author().id;
+        let err_str = "You are not allowed to reprocess the library!".to_string();
+        return send_debug(ctx, err_str, why.to_string()).await;
+    }
+
+    let (mut target, members) = match ctx.guild() {
+        Some(guild) => (
+            guild_loudness_target(guild.id),
+            guild.members.keys().map(|user_id| user_id.get()).collect::<HashSet<_>>(),
+        ),
+        None => return Ok(()),
+    };
+    if let Some(loudness) = loudness {
+        target.integrated = loudness;
+    }
+    if let Some(true_peak) = true_peak {
+        target.true_peak = true_peak;
+    }
+
+    let clips = match list_member_clips(&members) {
+        Ok(clips) => clips,
+        Err(why) => {
+            let err_str = "Failed to query names".to_string();
+            return send_error(ctx, err_str, why.to_string()).await;
+        }
+    };
+    let mut job = Job::start(ctx, "the server", &format!("{} announcements", clips.len())).await?;
+    job.stage(JobStage::Normalising).await;
+
+    // the clips are processed one after another, so they can share the files of the job
+    let files = job.files();
+    let processing_path = "/config/processing/";
+    let filename = &files.filename;
+    let processed_filename = &files.processed_filename;
+
+    let mut reprocessed = 0;
+    let mut failed = 0;
+    let mut without_original = 0;
+
+    for (name, announcement_name, indexed_path, kept_path) in clips {
+        if !Path::new(&kept_path).exists() {
+            without_original += 1;
+            continue;
+        }
+
+        if let Err(why) = fs::copy(&kept_path, format!("{}{}", processing_path, filename)) {
+            error!("Failed to copy original {}: {}", &kept_path, why);
+            failed += 1;
+            continue;
+        }
+
+        let (mut options, trim) = ProcessOptions::from_tags(&read_tags(&indexed_path));
+        options.loudness = target;
+        let trim_ref = trim.as_ref().map(|(start, end)| (start.as_str(), end.as_str()));
+
+        job.next_file().await?;
+        let reprocess_res = match process_file(&job, processing_path, filename, processed_filename, &options, trim_ref).await {
+            Ok(_) => fs::rename(format!("{}{}", processing_path, processed_filename), &indexed_path)
+                .map_err(|why| why.to_string()),
+            Err(why) => Err(why),
+        };
+
+        match reprocess_res {
+            Ok(_) => {
+                update_fingerprint(&name, &announcement_name);
+                debug!("Reprocessed {}", &indexed_path);
+                reprocessed += 1;
+            }
+            Err(why) => {
+                error!("Failed to reprocess {}: {}", &indexed_path, why);
+                failed += 1;
+            }
+        }
+        delete_processing_files(processing_path, filename, processed_filename);
+    }
+
+    job.done().await;
+
+    let reply = CreateReply::default()
+        .embed(CreateEmbed::new()
+            .title(format!("Normalised announcements to {} LUFS and {} dBTP", target.integrated, target.true_peak))
+            .description(format!(
+                "**{}** reprocessed\n**{}** failed\n**{}** without original",
+                reprocessed, failed, without_original
+            ))
+            .colour(Colour::from_rgb(128, 128, 128))
+        );
+
+    ctx.send(reply)
+        .await
+        .map(drop)
+        .map_err(Into::into)
+}
+
+/// Names, announcement names and paths of the indexed clips of the members and of their originals.
+///
+/// Other servers can have a different loudness target, so their clips are left alone.
+fn list_member_clips(members: &HashSet<u64>) -> Result<Vec<(String, String, String, String)>, rusqlite::Error> {
+    let db_path = Path::new("/config/database/db.sqlite");
+    let db = Connection::open(db_path)?;
+
+    let mut stmt = db.prepare("SELECT name, user_id FROM names")?;
+    let names = stmt
+        .query_map(params![], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as u64)))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut seen = HashSet::new();
+    let mut clips = vec![];
+    for (name, user_id) in names {
+        if !members.contains(&user_id) || !seen.insert(name.clone()) {
+            continue;
+        }
+
+        let entries = match fs::read_dir(index_dir(&name)) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let announcement_name = match decode_file_stem(&path) {
+                Some(stem) => stem,
+                None => continue,
+            };
+            let kept_path = original_path(&name, &announcement_name);
+            clips.push((
+                name.clone(),
+                announcement_name,
+                path.to_string_lossy().to_string(),
+                kept_path,
+            ));
+        }
+    }
+
+    Ok(clips)
+}
@@ -0,0 +1,337 @@
+use rusqlite::{params, Connection};
+use std::{collections::HashSet, fs, path::Path, time::Duration};
+use tracing::debug;
+
+use poise::{futures_util::StreamExt, CreateReply};
+use serenity::{
+    all::{
+        ComponentInteraction,
+        CreateActionRow,
+        CreateEmbed,
+        CreateEmbedFooter,
+        CreateInteractionResponse,
+        CreateInteractionResponseMessage
+    },
+    model::{
+        colour::Colour,
+        prelude::*,
+    },
+};
+
+use crate::{
+    commands::new::{commit_file, delete_processing_files},
+    util::{
+        autocomplete::fuzzy_score,
+        component_ids::{LIST_NEXT_BUTTON, LIST_PREV_BUTTON, SEARCH_COPY_BUTTON, SEARCH_PLAY_BUTTON},
+        media::read_tags,
+        messages::create_search_buttons,
+        paths::{decode_file_stem, index_dir, index_path, ProcessingFiles},
+        playback::{Announcement, PlaybackEvent},
+        submissions::copy_to_processing,
+        util::{play_file, send_debug, send_error, send_error_to, send_warning, user_voice_channel}
+    },
+    PContext, PError,
+};
+
+const TIMEOUT_DURATION: Duration = Duration::from_secs(600);
+const ELEMENTS_PER_PAGE: usize = 5;
+const MAX_QUERY_LENGTH: usize = 100;
+
+/// Clip that matched a search.
+struct SearchResult {
+    name: String,
+    announcement_name: String,
+    user_id: u64,
+    /// Key of the tag that matched, if the names didn't.
+    tag: Option<String>,
+    score: i64,
+}
+
+fn search_help() -> String {
+    "\
+Search the announcements of everyone in this server.
+Usage:
+!search <query>
+
+Examples:
+!search airhorn
+!search \"chipmunk\"
+
+Announcement names and owners are matched fuzzily, the typed letters have to appear in order.
+Tags like the applied filters are matched if they contain the query.
+Play plays a result in your voice channel, Copy makes a copy of it your active announcement.
+"
+    .to_string()
+}
+
+#[doc = "Search announcements by name, owner and tags."]
+#[poise::command(
+    category = "Main Commands",
+    guild_only,
+    prefix_command,
+    slash_command,
+    required_bot_permissions = "SEND_MESSAGES",
+    help_text_fn = "search_help"
+)]
+pub async fn search(
+    ctx: PContext<'_>,
+    #[description = "What to search for."] query: String,
+) -> Result<(), PError> {
+    if query.trim().is_empty() || query.chars().count() > MAX_QUERY_LENGTH {
+        let why = query;
+        let err_str = "Please provide a search query of at most 100 characters".to_string();
+        return send_debug(ctx, err_str, why).await;
+    }
+
+    let members = match ctx.guild() {
+        Some(guild) => guild.members.keys().map(|user_id| user_id.get()).collect::<HashSet<_>>(),
+        None => return Ok(()),
+    };
+
+    let search_query = query.clone();
+    let results = match tokio::task::spawn_blocking(move || find_results(&members, &search_query)).await {
+        Ok(Ok(results)) => results,
+        Ok(Err(why)) => {
+            let err_str = "Failed to query names".to_string();
+            return send_error(ctx, err_str, why.to_string()).await;
+        }
+        Err(why) => {
+            let err_str = "Failed to search announcements".to_string();
+            return send_error(ctx, err_str, why.to_string()).await;
+        }
+    };
+
+    if results.is_empty() {
+        let why = query;
+        let err_str = "No announcements found".to_string();
+        return send_debug(ctx, err_str, why).await;
+    }
+
+    let last_page_index = results.len().div_ceil(ELEMENTS_PER_PAGE);
+    let mut index = 1;
+
+    let reply = CreateReply::default()
+        .embed(create_page(&query, &results, index, last_page_index))
+        .components(create_page_buttons(&results, index, last_page_index));
+
+    let message = ctx
+        .send(reply)
+        .await?
+        .into_message()
+        .await?;
+
+    let mut collector = message
+        .await_component_interactions(ctx)
+        .timeout(TIMEOUT_DURATION)
+        .stream();
+    while let Some(interaction) = collector.next().await {
+        let custom_id = interaction.data.custom_id.clone();
+        let (action, result) = match custom_id.split_once(':') {
+            Some((action, result_index)) => (action, result_index.parse::<usize>().ok().and_then(|i| results.get(i))),
+            None => (custom_id.as_str(), None),
+        };
+
+        match (action, result) {
+            (LIST_PREV_BUTTON, _) => {
+                index = index.saturating_sub(1).max(1);
+            }
+            (LIST_NEXT_BUTTON, _) => {
+                index = (index + 1).min(last_page_index);
+            }
+            (SEARCH_PLAY_BUTTON, Some(result)) => {
+                play_result(ctx, &interaction, result).await?;
+                continue;
+            }
+            (SEARCH_COPY_BUTTON, Some(result)) => {
+                copy_result(ctx, &interaction, result).await?;
+                continue;
+            }
+            _ => {
+                let why = &interaction.data.custom_id;
+                let err_str = "Unknown component interaction".to_string();
+                return send_warning(ctx, err_str, why.to_string()).await;
+            }
+        }
+
+        let interaction_response = CreateInteractionResponseMessage::default()
+            .embed(create_page(&query, &results, index, last_page_index))
+            .components(create_page_buttons(&results, index, last_page_index));
+
+        interaction
+            .create_response(&ctx, CreateInteractionResponse::UpdateMessage(interaction_response))
+            .await?;
+    }
+
+    debug!("Timeout");
+    let _ = message.delete(&ctx).await;
+    Ok(())
+}
+
+/// Clips of the members of the guild that match the query, best matches first.
+fn find_results(members: &HashSet<u64>, query: &str) -> Result<Vec<SearchResult>, rusqlite::Error> {
+    let db_path = Path::new("/config/database/db.sqlite");
+    let db = Connection::open(db_path)?;
+
+    let mut stmt = db.prepare("SELECT name, user_id FROM names")?;
+    let names = stmt
+        .query_map(params![], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as u64)))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let query_lowercase = query.to_lowercase();
+    // a tag containing the query ranks below a name matching it closely, but above loose name matches
+    let tag_score = 3 * query.chars().count() as i64;
+
+    let mut seen = HashSet::new();
+    let mut results = vec![];
+    for (name, user_id) in names {
+        if !members.contains(&user_id) || !seen.insert(name.clone()) {
+            continue;
+        }
+
+        let entries = match fs::read_dir(index_dir(&name)) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        let name_score = fuzzy_score(&name, query);
+
+        for entry in entries.flatten() {
+            let announcement_name = match decode_file_stem(&entry.path()) {
+                Some(announcement_name) => announcement_name,
+                None => continue,
+            };
+
+            let mut score = fuzzy_score(&announcement_name, query).max(name_score);
+            let mut tag = None;
+            if score.is_none_or(|score| score < tag_score) {
+                let tags = read_tags(&index_path(&name, &announcement_name));
+                let mut matching_tags = tags
+                    .into_iter()
+                    .filter(|(_, value)| value.to_lowercase().contains(&query_lowercase))
+                    .map(|(key, _)| key)
+                    .collect::<Vec<_>>();
+                matching_tags.sort();
+
+                if let Some(key) = matching_tags.into_iter().next() {
+                    score = Some(tag_score);
+                    tag = Some(key);
+                }
+            }
+
+            if let Some(score) = score {
+                results.push(SearchResult { name: name.clone(), announcement_name, user_id, tag, score });
+            }
+        }
+    }
+
+    results.sort_by(|a, b| {
+        b.score
+            .cmp(&a.score)
+            .then_with(|| a.name.cmp(&b.name))
+            .then_with(|| a.announcement_name.cmp(&b.announcement_name))
+    });
+
+    Ok(results)
+}
+
+fn create_page(query: &str, results: &[SearchResult], index: usize, last_page_index: usize) -> CreateEmbed {
+    let first = (index - 1) * ELEMENTS_PER_PAGE;
+    let content = results
+        .iter()
+        .enumerate()
+        .skip(first)
+        .take(ELEMENTS_PER_PAGE)
+        .map(|(result_index, result)| {
+            let tag = match &result.tag {
+                Some(tag) => format!(" (tag `{}`)", tag),
+                None => "".to_string(),
+            };
+            format!(
+                "**{}.** `{}` of {} [{}]{}",
+                result_index + 1,
+                &result.announcement_name,
+                &result.name,
+                UserId::new(result.user_id).mention(),
+                tag
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    CreateEmbed::new()
+        .title(format!("Search results for \"{}\"", query))
+        .description(content)
+        .colour(Colour::from_rgb(128, 128, 128))
+        .footer(CreateEmbedFooter::new(format!("Page {}/{}", index, last_page_index)))
+}
+
+fn create_page_buttons(results: &[SearchResult], index: usize, last_page_index: usize) -> Vec<CreateActionRow> {
+    let first = (index - 1) * ELEMENTS_PER_PAGE;
+    let count = results.len().saturating_sub(first).min(ELEMENTS_PER_PAGE);
+
+    create_search_buttons(first, count, index != 1, index < last_page_index)
+}
+
+async fn respond_ephemeral(ctx: PContext<'_>, interaction: &ComponentInteraction, content: String) -> Result<(), PError> {
+    let interaction_response = CreateInteractionResponseMessage::default()
+        .content(content)
+        .ephemeral(true);
+
+    interaction
+        .create_response(&ctx, CreateInteractionResponse::Message(interaction_response))
+        .await
+        .map_err(Into::into)
+}
+
+/// Plays a result in the voice channel of whoever pressed the button.
+async fn play_result(ctx: PContext<'_>, interaction: &ComponentInteraction, result: &SearchResult) -> Result<(), PError> {
+    let guild_id = match ctx.guild_id() {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+
+    let channel_id = match user_voice_channel(ctx.serenity_context(), guild_id, interaction.user.id) {
+        Some(channel_id) => channel_id,
+        None => {
+            let content = "Join a voice channel to play the announcement".to_string();
+            return respond_ephemeral(ctx, interaction, content).await;
+        }
+    };
+
+    interaction
+        .create_response(&ctx, CreateInteractionResponse::Acknowledge)
+        .await?;
+
+    let path = index_path(&result.name, &result.announcement_name);
+    let announcement = Announcement::new(&result.name, result.user_id, &path);
+    play_file(ctx.serenity_context(), channel_id, guild_id, &[announcement], PlaybackEvent::Search).await;
+
+    Ok(())
+}
+
+/// Makes a copy of a result the active announcement of whoever pressed the button, moderated like an upload.
+async fn copy_result(ctx: PContext<'_>, interaction: &ComponentInteraction, result: &SearchResult) -> Result<(), PError> {
+    let discord_name = match interaction.member.as_ref().and_then(|member| member.nick.clone()) {
+        Some(nick) => nick,
+        None => interaction.user.name.clone(),
+    };
+
+    let is_own = discord_name == result.name;
+    if !is_own && Path::new(&index_path(&discord_name, &result.announcement_name)).exists() {
+        let content = format!("You already have an announcement named {}", &result.announcement_name);
+        return respond_ephemeral(ctx, interaction, content).await;
+    }
+
+    interaction
+        .create_response(&ctx, CreateInteractionResponse::Acknowledge)
+        .await?;
+
+    // copies don't run as jobs, the interaction keeps the files of concurrent copies apart
+    let files = ProcessingFiles::new(interaction.user.id.get(), interaction.id.get());
+    if let Err(err_str) = copy_to_processing(&files, &result.name, &result.announcement_name) {
+        delete_processing_files("/config/processing/", &files.filename, &files.processed_filename);
+        let why = index_path(&result.name, &result.announcement_name);
+        return send_error_to(ctx, Some(interaction), err_str, why).await;
+    }
+
+    commit_file(ctx, Some(interaction), &discord_name, &result.announcement_name, &interaction.user, interaction.user.id, &files, false).await
+}
@@ -21,6 +21,7 @@ use serenity::{
 
 use crate::{
     util::{ 
+        autocomplete::autocomplete_announcements,
         component_ids::{
             ANNOUNCEMENT_SELECTOR_DROPDOWN, 
             LIST_NEXT_BUTTON, 
@@ -32,6 +33,7 @@ use crate::{
             ELEMENT_LABEL_LENGTH
         }, 
         messages::create_navigation_buttons, 
+        paths::{decode_file_stem, index_dir, index_path},
         util::{send_debug, send_error, send_warning}
     },
     PContext, PError,
@@ -49,7 +51,9 @@ const TIMEOUT_DURATION: Duration = Duration::from_secs(300);
 )]
 pub async fn set(
     ctx: PContext<'_>,
-    #[description = "The name for which to set the announcement."] name: Option<String>,
+    #[description = "The name for which to set the announcement."]
+    #[autocomplete = "autocomplete_announcements"]
+    name: Option<String>,
     #[description = "The user for which to set the active announcement."] user: Option<User>,
 ) -> Result<(), PError> {
     let discord_user = match user {
@@ -69,7 +73,7 @@ pub async fn set(
             Ok(_) => {
                 let reply = CreateReply::default()
                     .embed(CreateEmbed::new()
-                        .title(format!("Set announcement"))
+                        .title("Set announcement")
                         .description(format!("`{}` [{}]", &announcement_name, &discord_user.mention()))
                         .colour(Colour::from_rgb(128, 128, 128))
                     );
@@ -126,7 +130,7 @@ pub async fn set(
                         Ok(_) => {
                             let interaction_response = CreateInteractionResponseMessage::default()
                                 .embed(CreateEmbed::new()
-                                    .title(format!("Set announcement"))
+                                    .title("Set announcement")
                                     .description(format!(
                                         "`{}` [{}]",
                                         &announcement_name,
@@ -183,7 +187,7 @@ pub async fn set(
         let err_str = "Timeout".to_string();
         debug!("{}", err_str);
         let _ = message.delete(&ctx).await;
-        return Ok(());
+        Ok(())
     }
 }
 
@@ -193,7 +197,7 @@ async fn set_fn(
     discord_user: &User,
     discord_name: &String,
 ) -> Result<(), PError> {
-    let path_string = format!("/config/index/{}/{}.flac", discord_name, announcement_name);
+    let path_string = index_path(discord_name, announcement_name);
     let path = Path::new(&path_string);
 
     if !path.exists() {
@@ -204,7 +208,7 @@ async fn set_fn(
 
     let db_path = Path::new("/config/database/db.sqlite");
 
-    let db = match Connection::open(&db_path) {
+    let db = match Connection::open(db_path) {
         Ok(db) => db,
         Err(why) => {
             let err_str = "Failed to open database".to_string();
@@ -230,13 +234,13 @@ async fn set_fn(
 
 async fn create_dropdown_options(
     ctx: PContext<'_>,
-    discord_name: &String,
+    discord_name: &str,
     index: usize,
 ) -> Result<(Vec<CreateSelectMenuOption>, bool), PError> {
     let mut options = vec![];
     let mut over_limit = false;
 
-    let path_string = format!("/config/index/{}", &discord_name);
+    let path_string = index_dir(discord_name);
     let path = Path::new(&path_string);
     if path.is_dir() {
         let dir_iterator = match fs::read_dir(path) {
@@ -269,7 +273,7 @@ async fn create_dropdown_options(
                     };
                 }
             };
-            let announcement_name = entry_value.path().file_stem().unwrap().to_string_lossy().to_string();
+            let announcement_name = decode_file_stem(&entry_value.path()).unwrap();
             // limit length
             if announcement_name.chars().count() > ELEMENT_LABEL_LENGTH {
                 let why = announcement_name.len();
@@ -292,7 +296,7 @@ async fn create_dropdown_options(
         }
     }
 
-    return Ok((options, over_limit));
+    Ok((options, over_limit))
 }
 
 fn create_dropdown(
@@ -315,5 +319,5 @@ fn create_dropdown(
         action_row.append(&mut navigation_buttons);
     }
 
-    return action_row;
+    action_row
 }
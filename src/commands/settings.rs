@@ -0,0 +1,366 @@
+use poise::{ChoiceParameter, CreateReply};
+use std::{fs, path::Path};
+use tracing::debug;
+
+use serenity::{
+    all::CreateEmbed,
+    model::{
+        colour::Colour,
+        prelude::*,
+    }
+};
+
+use crate::{
+    commands::new::{delete_processing_files, download_attachment, preview_file, process_file, ProcessOptions},
+    util::{
+        check::is_admin,
+        jobs::{Job, JobStage},
+        limits::{author_clip_length_limit, get_role_clip_lengths, guild_clip_length, remove_role_clip_length, set_role_clip_length},
+        loudness::{guild_loudness_target, DEFAULT_TRUE_PEAK},
+        paths::ProcessingFiles,
+        playback::JinglePosition,
+        presets::{autocomplete_presets, resolve_filters},
+        settings::{set_bool_setting, set_setting, BATCH_JOINS, CLIP_LENGTH, LOUDNESS_TARGET, MODERATION, MODERATION_CHANNEL, MODERATOR_ROLE, TRUE_PEAK_TARGET},
+        util::{send_debug, send_error}
+    },
+    PContext, PError,
+};
+
+#[doc = "Change the bot configuration of this server."]
+#[poise::command(
+    category = "Admin Commands",
+    guild_only,
+    prefix_command,
+    slash_command,
+    required_bot_permissions = "SEND_MESSAGES",
+    subcommands("batch_joins", "clip_length", "jingle", "loudness", "moderation")
+)]
+pub async fn settings(_ctx: PContext<'_>) -> Result<(), PError> {
+    Ok(())
+}
+
+#[doc = "Announce users joining at the same time together."]
+#[poise::command(
+    category = "Admin Commands",
+    guild_only,
+    prefix_command,
+    slash_command,
+    required_bot_permissions = "SEND_MESSAGES"
+)]
+pub async fn batch_joins(
+    ctx: PContext<'_>,
+    #[description = "Whether to combine announcements of users joining within a short time."] enabled: bool,
+) -> Result<(), PError> {
+    if !is_admin(ctx).await {
+        let why = ctx.author().id;
+        let err_str = "You are not allowed to change the settings!".to_string();
+        return send_debug(ctx, err_str, why.to_string()).await;
+    }
+
+    let guild_id = match ctx.guild_id() {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+
+    if let Err(why) = set_bool_setting(guild_id, BATCH_JOINS, enabled) {
+        let err_str = "Failed to update setting".to_string();
+        return send_error(ctx, err_str, why.to_string()).await;
+    }
+
+    send_setting_reply(ctx, "Batch joins", if enabled { "**ON**" } else { "**OFF**" }).await
+}
+
+#[doc = "Set how long new announcements may be, for everyone or for members of a role."]
+#[poise::command(
+    category = "Admin Commands",
+    guild_only,
+    prefix_command,
+    slash_command,
+    required_bot_permissions = "SEND_MESSAGES"
+)]
+pub async fn clip_length(
+    ctx: PContext<'_>,
+    #[description = "Maximum length in seconds, leave empty to show the limits or to remove the limit of a role."]
+    #[min = 1.0]
+    #[max = 60.0]
+    seconds: Option<f64>,
+    #[description = "Role the limit applies to instead of everyone, the longest limit of a member wins."] role: Option<Role>,
+) -> Result<(), PError> {
+    if !is_admin(ctx).await {
+        let why = ctx.author().id;
+        let err_str = "You are not allowed to change the settings!".to_string();
+        return send_debug(ctx, err_str, why.to_string()).await;
+    }
+
+    let guild_id = match ctx.guild_id() {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+
+    match (seconds, role) {
+        (Some(seconds), Some(role)) => {
+            if let Err(why) = set_role_clip_length(guild_id, role.id, seconds) {
+                let err_str = "Failed to update setting".to_string();
+                return send_error(ctx, err_str, why.to_string()).await;
+            }
+            send_setting_reply(ctx, "Clip length", &format!("**{}s** for {}", seconds, role.mention())).await
+        }
+        (None, Some(role)) => {
+            match remove_role_clip_length(guild_id, role.id) {
+                Ok(true) => send_setting_reply(ctx, "Clip length", &format!("Removed the limit of {}", role.mention())).await,
+                Ok(false) => {
+                    let why = role.id;
+                    let err_str = format!("{} has no clip length limit", role.name);
+                    send_debug(ctx, err_str, why.to_string()).await
+                }
+                Err(why) => {
+                    let err_str = "Failed to update setting".to_string();
+                    send_error(ctx, err_str, why.to_string()).await
+                }
+            }
+        }
+        (Some(seconds), None) => {
+            if let Err(why) = set_setting(guild_id, CLIP_LENGTH, &seconds.to_string()) {
+                let err_str = "Failed to update setting".to_string();
+                return send_error(ctx, err_str, why.to_string()).await;
+            }
+            send_setting_reply(ctx, "Clip length", &format!("**{}s** for everyone", seconds)).await
+        }
+        (None, None) => {
+            let role_limits = match get_role_clip_lengths(guild_id) {
+                Ok(role_limits) => role_limits,
+                Err(why) => {
+                    let err_str = "Failed to query clip lengths".to_string();
+                    return send_error(ctx, err_str, why.to_string()).await;
+                }
+            };
+
+            let mut description = format!("Everyone: **{}s**", guild_clip_length(guild_id));
+            for (role_id, seconds) in role_limits {
+                description.push_str(&format!("\n{}: **{}s**", role_id.mention(), seconds));
+            }
+            description.push_str(&format!("\n\nYour limit: **{}s**", author_clip_length_limit(ctx).await));
+
+            let reply = CreateReply::default()
+                .embed(CreateEmbed::new()
+                    .title("Clip length limits")
+                    .description(description)
+                    .colour(Colour::from_rgb(128, 128, 128))
+                );
+
+            ctx.send(reply)
+                .await
+                .map(drop)
+                .map_err(Into::into)
+        }
+    }
+}
+
+#[doc = "Set or remove the intro or outro that is played around every announcement."]
+#[poise::command(
+    category = "Admin Commands",
+    guild_only,
+    prefix_command,
+    slash_command,
+    required_bot_permissions = "SEND_MESSAGES"
+)]
+pub async fn jingle(
+    ctx: PContext<'_>,
+    #[description = "Whether to set the intro or the outro."] position: JinglePosition,
+    #[description = "Audio file to be used, leave empty to remove the jingle."] file: Option<Attachment>,
+    #[description = "Presets applied in order, e.g. \"chipmunk echo:500\"."]
+    #[autocomplete = "autocomplete_presets"]
+    presets: Option<String>,
+    #[description = "FFMPEG filters to transform audio."] filters: Option<String>,
+) -> Result<(), PError> {
+    if !is_admin(ctx).await {
+        let why = ctx.author().id;
+        let err_str = "You are not allowed to change the settings!".to_string();
+        return send_debug(ctx, err_str, why.to_string()).await;
+    }
+
+    let guild_id = match ctx.guild_id() {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+    let filters = match resolve_filters(guild_id, None, presets.as_deref(), filters.as_deref()) {
+        Ok(filters) => filters,
+        Err(why) => {
+            let err_str = format!("Invalid filters: {}", &why);
+            return send_debug(ctx, err_str, why).await;
+        }
+    };
+
+    let jingle_path = position.path(guild_id);
+    let name = position.name().to_string();
+
+    let file = match file {
+        Some(file) => file,
+        None => {
+            if let Err(why) = fs::remove_file(&jingle_path) {
+                let err_str = format!("There is no {} to remove", &name);
+                return send_debug(ctx, err_str, why.to_string()).await;
+            }
+            return send_setting_reply(ctx, &name, "**OFF**").await;
+        }
+    };
+
+    let announcement_name = format!("{}-{}", name.to_lowercase(), guild_id);
+    let job = Job::start(ctx, &name, &announcement_name).await?;
+    let files = job.files();
+    let ProcessingFiles { filename, processed_filename } = &files;
+    let processing_path = "/config/processing/";
+
+    job.stage(JobStage::Downloading).await;
+
+    if let Err(why) = download_attachment(ctx, &job, &file, filename).await {
+        job.fail(&why.to_string()).await;
+        return Err(why);
+    }
+
+    job.stage(JobStage::Normalising).await;
+    let options = ProcessOptions {
+        filters,
+        length_limit: Some(author_clip_length_limit(ctx).await),
+        auto_trim: true,
+        loudness: guild_loudness_target(guild_id),
+    };
+    let filter_res = process_file(&job, processing_path, filename, processed_filename, &options, None).await;
+
+    if let Err(why) = filter_res {
+        job.fail(&why).await;
+        delete_processing_files(processing_path, filename, processed_filename);
+        let err_str = format!("Failed to apply audio filter for file {}", &filename);
+        return send_error(ctx, err_str, why).await;
+    }
+    job.done().await;
+
+    let confirmed = preview_file(ctx, &name, &announcement_name, ctx.author(), &files, &options).await;
+    if !matches!(confirmed, Ok(true)) {
+        delete_processing_files(processing_path, filename, processed_filename);
+        return confirmed.map(drop);
+    }
+
+    if let Some(guild_path) = Path::new(&jingle_path).parent() {
+        if let Err(why) = fs::create_dir_all(guild_path) {
+            debug!("Failed to create directory {} ERROR: {}", guild_path.display(), why);
+        }
+    }
+
+    let rename_res = fs::rename(format!("{}{}", processing_path, &processed_filename), &jingle_path);
+    delete_processing_files(processing_path, filename, processed_filename);
+
+    if let Err(why) = rename_res {
+        let err_str = format!("Failed to rename file {}", &processed_filename);
+        return send_error(ctx, err_str, why.to_string()).await;
+    }
+
+    send_setting_reply(ctx, &name, "**ON**").await
+}
+
+#[doc = "Set the loudness new announcements are normalised to."]
+#[poise::command(
+    category = "Admin Commands",
+    guild_only,
+    prefix_command,
+    slash_command,
+    required_bot_permissions = "SEND_MESSAGES"
+)]
+pub async fn loudness(
+    ctx: PContext<'_>,
+    #[description = "Integrated loudness in LUFS, -24 by default."]
+    #[min = -70.0]
+    #[max = -5.0]
+    integrated: f64,
+    #[description = "Maximum true peak in dBTP, -2 by default."]
+    #[min = -9.0]
+    #[max = 0.0]
+    true_peak: Option<f64>,
+) -> Result<(), PError> {
+    if !is_admin(ctx).await {
+        let why = ctx.author().id;
+        let err_str = "You are not allowed to change the settings!".to_string();
+        return send_debug(ctx, err_str, why.to_string()).await;
+    }
+
+    let guild_id = match ctx.guild_id() {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+
+    let true_peak = true_peak.unwrap_or(DEFAULT_TRUE_PEAK);
+
+    if let Err(why) = set_setting(guild_id, LOUDNESS_TARGET, &integrated.to_string())
+        .and_then(|_| set_setting(guild_id, TRUE_PEAK_TARGET, &true_peak.to_string()))
+    {
+        let err_str = "Failed to update setting".to_string();
+        return send_error(ctx, err_str, why.to_string()).await;
+    }
+
+    send_setting_reply(ctx, "Loudness", &format!("**{} LUFS** with peaks up to **{} dBTP**", integrated, true_peak)).await
+}
+
+#[doc = "Require new announcements to be approved by a moderator before they go live."]
+#[poise::command(
+    category = "Admin Commands",
+    guild_only,
+    prefix_command,
+    slash_command,
+    required_bot_permissions = "SEND_MESSAGES"
+)]
+pub async fn moderation(
+    ctx: PContext<'_>,
+    #[description = "Whether submissions have to be approved."] enabled: bool,
+    #[description = "Channel the submissions are posted to."] channel: Option<GuildChannel>,
+    #[description = "Role that may approve submissions besides the server managers."] role: Option<Role>,
+) -> Result<(), PError> {
+    if !is_admin(ctx).await {
+        let why = ctx.author().id;
+        let err_str = "You are not allowed to change the settings!".to_string();
+        return send_debug(ctx, err_str, why.to_string()).await;
+    }
+
+    let guild_id = match ctx.guild_id() {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+
+    let mut value = if enabled { "**ON**".to_string() } else { "**OFF**".to_string() };
+
+    if let Some(channel) = &channel {
+        if let Err(why) = set_setting(guild_id, MODERATION_CHANNEL, &channel.id.to_string()) {
+            let err_str = "Failed to update setting".to_string();
+            return send_error(ctx, err_str, why.to_string()).await;
+        }
+        value = format!("{} in {}", value, channel.mention());
+    }
+
+    if let Some(role) = &role {
+        if let Err(why) = set_setting(guild_id, MODERATOR_ROLE, &role.id.to_string()) {
+            let err_str = "Failed to update setting".to_string();
+            return send_error(ctx, err_str, why.to_string()).await;
+        }
+        value = format!("{}, moderated by {}", value, role.mention());
+    }
+
+    if let Err(why) = set_bool_setting(guild_id, MODERATION, enabled) {
+        let err_str = "Failed to update setting".to_string();
+        return send_error(ctx, err_str, why.to_string()).await;
+    }
+
+    send_setting_reply(ctx, "Moderation", &value).await
+}
+
+async fn send_setting_reply(ctx: PContext<'_>, setting: &str, value: &str) -> Result<(), PError> {
+    let reply = CreateReply::default()
+        .embed(CreateEmbed::new()
+            .title(format!("Set {}", setting))
+            .description(value)
+            .colour(Colour::from_rgb(128, 128, 128))
+        );
+
+    ctx.send(reply)
+        .await
+        .map(drop)
+        .map_err(Into::into)
+}
@@ -0,0 +1,244 @@
+use poise::CreateReply;
+use rusqlite::{params, Connection};
+use std::{
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serenity::{
+    all::{CreateEmbed, CreateEmbedFooter},
+    model::{colour::Colour, prelude::*},
+};
+
+use crate::{
+    util::{
+        consts::EMBED_FIELD_MAX_LENGTH,
+        util::{send_debug, send_error}
+    },
+    PContext, PError,
+};
+
+const ELEMENTS_PER_LIST: usize = 10;
+
+/// How far back the statistics go.
+#[derive(Clone, Copy, Debug, PartialEq, poise::ChoiceParameter)]
+pub enum StatsWindow {
+    #[name = "day"]
+    Day,
+    #[name = "week"]
+    Week,
+    #[name = "month"]
+    Month,
+    #[name = "year"]
+    Year,
+    #[name = "all time"]
+    AllTime,
+}
+
+impl StatsWindow {
+    fn seconds(&self) -> Option<u64> {
+        match self {
+            StatsWindow::Day => Some(24 * 60 * 60),
+            StatsWindow::Week => Some(7 * 24 * 60 * 60),
+            StatsWindow::Month => Some(30 * 24 * 60 * 60),
+            StatsWindow::Year => Some(365 * 24 * 60 * 60),
+            StatsWindow::AllTime => None,
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            StatsWindow::Day => "the last day",
+            StatsWindow::Week => "the last week",
+            StatsWindow::Month => "the last 30 days",
+            StatsWindow::Year => "the last year",
+            StatsWindow::AllTime => "all time",
+        }
+    }
+}
+
+fn stats_help() -> String {
+    "\
+Show which announcements are played the most in this server.
+Usage:
+!stats [<window>] [<discordname>]
+
+Examples:
+!stats
+!stats week @Yzarul
+
+Without a user, the top announcements and the most announced users are shown.
+With a user, their playbacks are counted by announcement and by what caused them.
+Previews and jingles aren't counted.
+"
+    .to_string()
+}
+
+#[doc = "Show the most played announcements and users."]
+#[poise::command(
+    category = "Main Commands",
+    guild_only,
+    prefix_command,
+    slash_command,
+    required_bot_permissions = "SEND_MESSAGES",
+    help_text_fn = "stats_help"
+)]
+pub async fn stats(
+    ctx: PContext<'_>,
+    #[description = "How far back to count, the last week by default."] window: Option<StatsWindow>,
+    #[description = "Only count the playbacks of this user."] user: Option<User>,
+) -> Result<(), PError> {
+    let guild_id = match ctx.guild_id() {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+    let window = window.unwrap_or(StatsWindow::Week);
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+    let since = window.seconds().map(|seconds| now.saturating_sub(seconds)).unwrap_or(0);
+    let user_id = user.as_ref().map(|user| user.id.get());
+
+    let stats = match query_stats(guild_id, since, user_id) {
+        Ok(stats) => stats,
+        Err(why) => {
+            let err_str = "Failed to query playback statistics".to_string();
+            return send_error(ctx, err_str, why.to_string()).await;
+        }
+    };
+
+    if stats.total == 0 {
+        let why = guild_id;
+        let err_str = format!("No announcements were played in {}", window.description());
+        return send_debug(ctx, err_str, why.to_string()).await;
+    }
+
+    let top_announcements = stats
+        .announcements
+        .iter()
+        .enumerate()
+        .map(|(index, (name, announcement_name, count))| match announcement_name {
+            Some(announcement_name) => format!("**{}.** `{}` of {} — {}x", index + 1, announcement_name, name, count),
+            None => format!("**{}.** TTS of {} — {}x", index + 1, name, count),
+        })
+        .collect::<Vec<_>>();
+
+    let mut embed = CreateEmbed::new()
+        .colour(Colour::from_rgb(128, 128, 128))
+        .footer(CreateEmbedFooter::new(format!("{} playbacks in {}", stats.total, window.description())))
+        .field("Top announcements", join_lines(&top_announcements), false);
+
+    embed = match &user {
+        Some(user) => {
+            let events = stats
+                .events
+                .iter()
+                .map(|(event, count)| format!("{} — {}x", event.replace('_', " "), count))
+                .collect::<Vec<_>>();
+
+            embed
+                .title(format!("Playback statistics of {}", &user.name))
+                .description(format!("Announcements of {} were played {} times", user.mention(), stats.total))
+                .field("By event", join_lines(&events), false)
+        }
+        None => {
+            let users = stats
+                .users
+                .iter()
+                .enumerate()
+                .map(|(index, (user_id, count))| format!("**{}.** {} — {}x", index + 1, UserId::new(*user_id).mention(), count))
+                .collect::<Vec<_>>();
+
+            embed
+                .title("Playback statistics")
+                .field("Most announced users", join_lines(&users), false)
+        }
+    };
+
+    ctx.send(CreateReply::default().embed(embed))
+        .await
+        .map(drop)
+        .map_err(Into::into)
+}
+
+/// Joins as many lines as fit into an embed field.
+fn join_lines(lines: &[String]) -> String {
+    let mut content = "".to_string();
+    for line in lines {
+        if content.chars().count() + line.chars().count() + 1 > EMBED_FIELD_MAX_LENGTH {
+            break;
+        }
+        content.push_str(line);
+        content.push('\n');
+    }
+
+    content
+}
+
+/// Playback counts of a guild since a timestamp, optionally of a single user.
+struct PlaybackStats {
+    total: i64,
+    /// Name, announcement name or `None` for TTS, count.
+    announcements: Vec<(String, Option<String>, i64)>,
+    /// User id and count.
+    users: Vec<(u64, i64)>,
+    /// Event and count.
+    events: Vec<(String, i64)>,
+}
+
+fn query_stats(guild_id: GuildId, since: u64, user_id: Option<u64>) -> Result<PlaybackStats, rusqlite::Error> {
+    let db_path = Path::new("/config/database/db.sqlite");
+    let db = Connection::open(db_path)?;
+
+    let filter = "guild_id=?1 AND timestamp>=?2 AND (?3 IS NULL OR user_id=?3)";
+    let user_id = user_id.map(|user_id| user_id as i64);
+    let limit = ELEMENTS_PER_LIST as i64;
+
+    let total = db.query_row(
+        &format!("SELECT COUNT(*) FROM playbacks WHERE {}", filter),
+        params![guild_id.get() as i64, since as i64, user_id],
+        |row| row.get(0),
+    )?;
+
+    let mut statement = db.prepare(&format!(
+        "SELECT name, announcement, tts, COUNT(*) AS playbacks FROM playbacks WHERE {}
+            GROUP BY name, announcement, tts
+            ORDER BY playbacks DESC, name
+            LIMIT ?4",
+        filter
+    ))?;
+    let announcements = statement
+        .query_map(params![guild_id.get() as i64, since as i64, user_id, limit], |row| {
+            let tts: bool = row.get(2)?;
+            let announcement_name: String = row.get(1)?;
+            Ok((row.get(0)?, (!tts).then_some(announcement_name), row.get(3)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut statement = db.prepare(&format!(
+        "SELECT user_id, COUNT(*) AS playbacks FROM playbacks WHERE {}
+            GROUP BY user_id
+            ORDER BY playbacks DESC
+            LIMIT ?4",
+        filter
+    ))?;
+    let users = statement
+        .query_map(params![guild_id.get() as i64, since as i64, user_id, limit], |row| {
+            Ok((row.get::<_, i64>(0)? as u64, row.get(1)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut statement = db.prepare(&format!(
+        "SELECT event, COUNT(*) AS playbacks FROM playbacks WHERE {}
+            GROUP BY event
+            ORDER BY playbacks DESC",
+        filter
+    ))?;
+    let events = statement
+        .query_map(params![guild_id.get() as i64, since as i64, user_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(PlaybackStats { total, announcements, users, events })
+}
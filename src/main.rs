@@ -2,7 +2,7 @@ mod commands;
 mod util;
 
 use std::{
-    collections::HashSet, env, fs, path::Path, sync::Arc, time::Duration
+    collections::{HashMap, HashSet}, env, fs, path::Path, sync::Arc, time::Duration
 };
 use rustls::crypto::CryptoProvider;
 
@@ -18,20 +18,28 @@ use serenity::{
         id::UserId,
         prelude::*,
         voice::VoiceState,
-    }, prelude::TypeMapKey
+    }, prelude::{Mutex, TypeMapKey}
 };
 
 use rusqlite::{params, Connection};
 use tokio::{task, time};
 use tracing::{debug, error, info};
 
-use commands::{list::*, new::*, random::*, set::*, names::*};
+use commands::{delete::*, edit::*, failures::*, history::*, import::*, jobs::*, list::*, new::*, presets::*, random::*, rename::*, reprocess::*, search::*, set::*, settings::*, stats::*, names::*};
 
 use util::{check::can_connect, util::send_debug};
 
 use crate::util::{
+    batch::{queue_announcement, JoinBatches},
     consts::{BOT_ADMIN_USER_ID, CUZ_USER_ID}, 
-    util::{announce, bot_voice_channel_is_empty, leave_channel, play_file, print_type_of}
+    fingerprint::backfill_fingerprints,
+    jobs::JobQueue,
+    paths::migrate_legacy_paths,
+    playback::{Announcement, PlaybackEvent},
+    reconcile::reconcile_voice_connections,
+    settings::{get_bool_setting, BATCH_JOINS},
+    submissions::handle_submission_interaction,
+    util::{announce, bot_voice_channel_is_empty, leave_channel, play_file}
 };
 
 // Types used by all command functions
@@ -39,7 +47,9 @@ type PError = Box<dyn std::error::Error + Send + Sync>;
 type PContext<'a> = poise::Context<'a, Data, PError>;
 
 // Custom user data passed to all command functions
-pub struct Data {}
+pub struct Data {
+    pub jobs: JobQueue,
+}
 
 pub struct ShardManagerContainer;
 
@@ -55,8 +65,20 @@ impl EventHandler for Handler {
         info!("Connected as {}", ready.user.name);
     }
 
-    async fn resume(&self, _: Context, _: ResumedEvent) {
+    // Guilds are only cached after ready, so voice connections are reconciled once the cache is filled
+    async fn cache_ready(&self, ctx: Context, _guilds: Vec<GuildId>) {
+        reconcile_voice_connections(&ctx).await;
+    }
+
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        if let Interaction::Component(component) = interaction {
+            handle_submission_interaction(&ctx, &component).await;
+        }
+    }
+
+    async fn resume(&self, ctx: Context, _: ResumedEvent) {
         info!("Resumed");
+        reconcile_voice_connections(&ctx).await;
     }
 
     async fn voice_state_update(&self, ctx: Context, old_state_opt: Option<VoiceState>, new_state: VoiceState) {
@@ -92,10 +114,7 @@ impl EventHandler for Handler {
         let cant_connect = !can_connect(&ctx, maybe_guild_id, maybe_channel_id);
 
         if let Some(old_state) = &old_state_opt {
-            if !new_channel_exists ||
-               (new_channel_exists &&
-               (Some(guild_id) != old_state.guild_id ||
-                cant_connect)) {
+            if !new_channel_exists || Some(guild_id) != old_state.guild_id || cant_connect {
 
                 if let Some(prev_guild_id) = old_state.guild_id {
                     if bot_voice_channel_is_empty(&ctx, prev_guild_id).await {
@@ -118,7 +137,7 @@ impl EventHandler for Handler {
             return;
         }
 
-        if !(&old_state_opt).is_some() {
+        if old_state_opt.is_none() {
             let path = "/config/StGallerConnection.flac";
             if user_id == USER1_ID {
                 let user_check;
@@ -131,7 +150,8 @@ impl EventHandler for Handler {
                 }
 
                 if user_check {
-                    let _ = play_file(&ctx, channel_id, guild_id, &path).await;
+                    let announcement = Announcement::new(&user.name, user_id.get(), path);
+                    let _ = play_file(&ctx, channel_id, guild_id, &[announcement], PlaybackEvent::Greeting).await;
                 }
             }
 
@@ -146,22 +166,27 @@ impl EventHandler for Handler {
                 }
 
                 if user_check {
-                    let _ = play_file(&ctx, channel_id, guild_id, &path).await;
+                    let announcement = Announcement::new(&user.name, user_id.get(), path);
+                    let _ = play_file(&ctx, channel_id, guild_id, &[announcement], PlaybackEvent::Greeting).await;
                 }
             }
         }
 
-        if ((&old_state_opt).is_none() && !new_state.self_mute) || 
-           ((&old_state_opt).is_some() && 
+        if (old_state_opt.is_none() && !new_state.self_mute) || 
+           (old_state_opt.is_some() && 
             (old_state_opt.as_ref().unwrap().self_mute || old_state_opt.as_ref().unwrap().channel_id.unwrap() != channel_id) && 
              !new_state.self_mute) {
             info!("UNMUTE!");
 
             let member = guild_id.member(&ctx.http, user_id).await.unwrap();
 
-            let name = member.display_name().to_string().replace("/", "⁄");
+            let name = member.display_name().to_string();
 
-            let _ = announce(&ctx, channel_id, guild_id, &name, user_id.get()).await;
+            if get_bool_setting(guild_id, BATCH_JOINS, false) {
+                queue_announcement(&ctx, channel_id, guild_id, &name, user_id.get()).await;
+            } else {
+                let _ = announce(&ctx, channel_id, guild_id, &name, user_id.get()).await;
+            }
         }
     }
 }
@@ -206,11 +231,11 @@ async fn on_error(error: poise::FrameworkError<'_, Data, PError>) {
     match error {
         poise::FrameworkError::Setup { error, .. } => panic!("Failed to start bot: {:?}", error),
         poise::FrameworkError::Command { error, ctx, .. } => {
-            println!("Error in command `{}`: {:?}", ctx.command().name, error,);
+            error!("Error in command `{}`: {:?}", ctx.command().name, error);
         }
         error => {
             if let Err(e) = poise::builtins::on_error(error).await {
-                println!("Error while handling error: {}", e)
+                error!("Error while handling error: {}", e)
             }
         }
     }
@@ -262,9 +287,21 @@ async fn main() {
                 register(),
                 set(),
                 list(),
+                search(),
+                stats(),
+                history(),
                 new(),
                 random(),
                 names(),
+                failures(),
+                settings(),
+                jobs(),
+                presets(),
+                edit(),
+                delete(),
+                rename(),
+                reprocess(),
+                import(),
             ],
             prefix_options: poise::PrefixFrameworkOptions {
                 prefix: Some("!".into()),
@@ -284,12 +321,12 @@ async fn main() {
                     ]
                     .contains(&ctx.channel_id().get())
                     {
-                        return Ok(true);
+                        Ok(true)
                     } else {
                         let why = "Channel not in allowed list.";
-                        let err_string = format!("Use a valid channel to send commands");
+                        let err_string = "Use a valid channel to send commands".to_string();
                         let _ = send_debug(ctx, err_string, why.to_string()).await;
-                        return Ok(false);
+                        Ok(false)
                     }
                 })
             }),
@@ -306,7 +343,9 @@ async fn main() {
         })
         .setup(move |_ctx, _ready, _framework| {
             Box::pin(async move {
-                Ok(Data {})
+                Ok(Data {
+                    jobs: JobQueue::new(),
+                })
             })
         })
         .build();
@@ -315,6 +354,9 @@ async fn main() {
     let index = Path::new("/config/index");
     let queue = Path::new("/config/queue");
     let processing = Path::new("/config/processing/");
+    let guilds = Path::new("/config/guilds");
+    let pending = Path::new("/config/pending");
+    let originals = Path::new("/config/originals");
     let db_folder = Path::new("/config/database/");
     let db_path = Path::new("/config/database/db.sqlite");
 
@@ -334,11 +376,23 @@ async fn main() {
         let _ = fs::create_dir(processing);
     }
 
+    if !guilds.exists() {
+        let _ = fs::create_dir(guilds);
+    }
+
+    if !pending.exists() {
+        let _ = fs::create_dir(pending);
+    }
+
+    if !originals.exists() {
+        let _ = fs::create_dir(originals);
+    }
+
     if !db_folder.exists() {
         let _ = fs::create_dir(db_folder);
     }
 
-    let db = match Connection::open(&db_path) {
+    let db = match Connection::open(db_path) {
         Ok(db) => db,
         Err(err) => {
             error!("Failed to open database: {}", err);
@@ -346,7 +400,7 @@ async fn main() {
         }
     };
 
-    let _ = match db.execute(
+    match db.execute(
         "CREATE TABLE IF NOT EXISTS names (
             name            TEXT NOT NULL, 
             user_id         INTEGER NOT NULL,
@@ -358,12 +412,153 @@ async fn main() {
     ) {
         Ok(_) => (),
         Err(err) => {
-            print_type_of(&err);
             error!("Failed to create table, Error Code: {}", err);
             return;
         }
     };
 
+    match db.execute(
+        "CREATE TABLE IF NOT EXISTS playback_failures (
+            guild_id        INTEGER NOT NULL,
+            path            TEXT NOT NULL,
+            name            TEXT NOT NULL,
+            user_id         INTEGER NOT NULL,
+            failures        INTEGER NOT NULL DEFAULT 0,
+            last_error      TEXT NOT NULL DEFAULT '',
+            last_failure    INTEGER NOT NULL DEFAULT 0,
+            PRIMARY KEY ( guild_id, path )
+            )",
+        params![],
+    ) {
+        Ok(_) => (),
+        Err(err) => {
+            error!("Failed to create table, Error Code: {}", err);
+            return;
+        }
+    };
+
+    match db.execute(
+        "CREATE TABLE IF NOT EXISTS guild_settings (
+            guild_id        INTEGER NOT NULL,
+            key             TEXT NOT NULL,
+            value           TEXT NOT NULL,
+            PRIMARY KEY ( guild_id, key )
+            )",
+        params![],
+    ) {
+        Ok(_) => (),
+        Err(err) => {
+            error!("Failed to create table, Error Code: {}", err);
+            return;
+        }
+    };
+
+    match db.execute(
+        "CREATE TABLE IF NOT EXISTS filter_presets (
+            guild_id    INTEGER NOT NULL,
+            name        TEXT NOT NULL,
+            filters     TEXT NOT NULL,
+            PRIMARY KEY (guild_id, name)
+            )",
+        params![],
+    ) {
+        Ok(_) => (),
+        Err(err) => {
+            error!("Failed to create table, Error Code: {}", err);
+            return;
+        }
+    };
+
+    match db.execute(
+        "CREATE TABLE IF NOT EXISTS pending_submissions (
+            id              INTEGER PRIMARY KEY AUTOINCREMENT,
+            guild_id        INTEGER NOT NULL,
+            name            TEXT NOT NULL,
+            user_id         INTEGER NOT NULL,
+            announcement    TEXT NOT NULL,
+            submitter_id    INTEGER NOT NULL,
+            approved        INTEGER NOT NULL DEFAULT 0 CHECK(approved IN(0, 1)),
+            consented       INTEGER NOT NULL DEFAULT 0 CHECK(consented IN(0, 1)),
+            edit            INTEGER NOT NULL DEFAULT 0 CHECK(edit IN(0, 1)),
+            created         INTEGER NOT NULL
+            )",
+        params![],
+    ) {
+        Ok(_) => (),
+        Err(err) => {
+            error!("Failed to create table, Error Code: {}", err);
+            return;
+        }
+    };
+
+    match db.execute(
+        "CREATE TABLE IF NOT EXISTS fingerprints (
+            name            TEXT NOT NULL,
+            announcement    TEXT NOT NULL,
+            fingerprint     BLOB NOT NULL,
+            PRIMARY KEY (name, announcement)
+            )",
+        params![],
+    ) {
+        Ok(_) => (),
+        Err(err) => {
+            error!("Failed to create table, Error Code: {}", err);
+            return;
+        }
+    };
+
+    match db.execute(
+        "CREATE TABLE IF NOT EXISTS role_clip_lengths (
+            guild_id    INTEGER NOT NULL,
+            role_id     INTEGER NOT NULL,
+            seconds     REAL NOT NULL,
+            PRIMARY KEY (guild_id, role_id)
+            )",
+        params![],
+    ) {
+        Ok(_) => (),
+        Err(err) => {
+            error!("Failed to create table, Error Code: {}", err);
+            return;
+        }
+    };
+
+    match db.execute(
+        "CREATE TABLE IF NOT EXISTS playbacks (
+            id              INTEGER PRIMARY KEY AUTOINCREMENT,
+            guild_id        INTEGER NOT NULL,
+            channel_id      INTEGER NOT NULL,
+            user_id         INTEGER NOT NULL,
+            name            TEXT NOT NULL,
+            announcement    TEXT NOT NULL,
+            path            TEXT NOT NULL,
+            tts             INTEGER NOT NULL DEFAULT 0 CHECK(tts IN(0, 1)),
+            event           TEXT NOT NULL,
+            timestamp       INTEGER NOT NULL
+            )",
+        params![],
+    ) {
+        Ok(_) => (),
+        Err(err) => {
+            error!("Failed to create table, Error Code: {}", err);
+            return;
+        }
+    };
+
+    match db.execute(
+        "CREATE INDEX IF NOT EXISTS playbacks_guild_timestamp ON playbacks (guild_id, timestamp)",
+        params![],
+    ) {
+        Ok(_) => (),
+        Err(err) => {
+            error!("Failed to create index, Error Code: {}", err);
+            return;
+        }
+    };
+
+    migrate_legacy_paths();
+    task::spawn_blocking(backfill_fingerprints);
+
     let mut client = ClientBuilder::new(token, intents)
         .event_handler(Handler)
         .framework(framework)
@@ -375,6 +570,7 @@ async fn main() {
         let mut data = client.data.write().await;
         let shard_manager = client.shard_manager.clone().to_owned();
         data.insert::<ShardManagerContainer>(shard_manager);
+        data.insert::<JoinBatches>(Arc::new(Mutex::new(HashMap::new())));
     }
 
     let shard_manager = client.shard_manager.clone();
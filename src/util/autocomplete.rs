@@ -0,0 +1,157 @@
+use serenity::all::{CommandDataOptionValue, UserId};
+use std::fs;
+
+use crate::{util::paths::{decode_file_stem, decode_segment, index_dir}, PContext};
+
+/// Most choices Discord shows for an autocompleted option.
+const MAX_CHOICES: usize = 25;
+
+/// Scores how well a candidate matches what was typed so far, case insensitively.
+///
+/// The typed characters have to appear in order, matches at the start of words and runs of consecutive
+/// matches score higher, matches late in the candidate lower. Returns `None` if the candidate doesn't match.
+pub fn fuzzy_score(candidate: &str, partial: &str) -> Option<i64> {
+    let candidate = candidate.to_lowercase().chars().collect::<Vec<_>>();
+    let partial = partial.to_lowercase();
+    let mut partial = partial.chars().filter(|c| !c.is_whitespace()).peekable();
+
+    let mut score = 0;
+    let mut previous_match = None;
+    for (index, c) in candidate.iter().enumerate() {
+        let Some(wanted) = partial.peek() else { break };
+        if c != wanted {
+            continue;
+        }
+
+        score += 1;
+        if index == 0 || !candidate[index - 1].is_alphanumeric() {
+            score += 3;
+        }
+        match previous_match {
+            Some(previous) if previous + 1 == index => score += 5,
+            None => score -= index as i64,
+            _ => (),
+        }
+
+        previous_match = Some(index);
+        partial.next();
+    }
+
+    match partial.peek() {
+        Some(_) => None,
+        None => Some(score),
+    }
+}
+
+/// Candidates that fuzzily match what was typed so far, best matches first.
+fn fuzzy_filter(candidates: Vec<String>, partial: &str) -> Vec<String> {
+    let mut matches = candidates
+        .into_iter()
+        .filter_map(|candidate| fuzzy_score(&candidate, partial).map(|score| (score, candidate)))
+        .collect::<Vec<_>>();
+    matches.sort_by(|(score_a, a), (score_b, b)| score_b.cmp(score_a).then_with(|| a.cmp(b)));
+    matches.dedup_by(|(_, a), (_, b)| a == b);
+
+    matches
+        .into_iter()
+        .take(MAX_CHOICES)
+        .map(|(_, candidate)| candidate)
+        .collect()
+}
+
+/// User chosen in the `user` option of the command that is being completed, the author if none is chosen yet.
+fn chosen_user(ctx: PContext<'_>) -> UserId {
+    if let poise::Context::Application(app_ctx) = ctx {
+        for option in &app_ctx.interaction.data.options {
+            if let ("user", CommandDataOptionValue::User(user_id)) = (option.name.as_str(), &option.value) {
+                return *user_id;
+            }
+        }
+    }
+
+    ctx.author().id
+}
+
+/// Name the announcements of a member are stored under, the nickname or else the username.
+fn member_name(ctx: PContext<'_>, user_id: UserId) -> Option<String> {
+    let guild = ctx.guild()?;
+    let member = guild.members.get(&user_id)?;
+
+    Some(member.nick.clone().unwrap_or_else(|| member.user.name.clone()))
+}
+
+/// Announcements of the user chosen in the `user` option that match what was typed so far.
+pub async fn autocomplete_announcements(ctx: PContext<'_>, partial: &str) -> Vec<String> {
+    let user_id = chosen_user(ctx);
+    let name = match member_name(ctx, user_id) {
+        Some(name) => name,
+        None if user_id == ctx.author().id => ctx.author().name.clone(),
+        None => return vec![],
+    };
+
+    let announcements = match fs::read_dir(index_dir(&name)) {
+        Ok(entries) => entries
+            .flatten()
+            .filter_map(|entry| decode_file_stem(&entry.path()))
+            .collect(),
+        Err(_) => return vec![],
+    };
+
+    fuzzy_filter(announcements, partial)
+}
+
+/// Names that have announcements in the index and match what was typed so far.
+pub async fn autocomplete_names(_ctx: PContext<'_>, partial: &str) -> Vec<String> {
+    let names = match fs::read_dir("/config/index") {
+        Ok(entries) => entries
+            .flatten()
+            .map(|entry| decode_segment(&entry.file_name().to_string_lossy()))
+            .collect(),
+        Err(_) => return vec![],
+    };
+
+    fuzzy_filter(names, partial)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_characters_in_order() {
+        assert!(fuzzy_score("airhorn", "ahn").is_some());
+        assert!(fuzzy_score("Airhorn", "AIR HORN").is_some());
+        assert!(fuzzy_score("airhorn", "").is_some());
+        assert_eq!(fuzzy_score("airhorn", "nha"), None);
+        assert_eq!(fuzzy_score("airhorn", "airhorns"), None);
+    }
+
+    #[test]
+    fn prefers_word_starts_and_runs() {
+        let word_start = fuzzy_score("sad trombone", "tro").unwrap();
+        let inside_word = fuzzy_score("astrology", "tro").unwrap();
+        assert!(word_start > inside_word, "{} <= {}", word_start, inside_word);
+
+        let run = fuzzy_score("horn", "horn").unwrap();
+        let scattered = fuzzy_score("h-o-r-n", "horn").unwrap();
+        assert!(run > scattered, "{} <= {}", run, scattered);
+
+        let early = fuzzy_score("horn blast", "horn").unwrap();
+        let late = fuzzy_score("big loud horn", "horn").unwrap();
+        assert!(early > late, "{} <= {}", early, late);
+    }
+
+    #[test]
+    fn sorts_best_matches_first() {
+        let candidates = ["astrology", "sad trombone", "hello", "trombone"].map(String::from).to_vec();
+
+        assert_eq!(fuzzy_filter(candidates, "tro"), ["trombone", "sad trombone", "astrology"]);
+    }
+
+    #[test]
+    fn limits_choices() {
+        let candidates = (0..40).map(|index| format!("clip {}", index)).collect::<Vec<_>>();
+
+        assert_eq!(fuzzy_filter(candidates, "clip").len(), MAX_CHOICES);
+    }
+}
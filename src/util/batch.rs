@@ -0,0 +1,149 @@
+use serenity::{
+    client::Context,
+    model::id::{ChannelId, GuildId},
+    prelude::{Mutex, TypeMapKey},
+};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::{process::Command, time};
+use tracing::{debug, error};
+
+use crate::util::{
+    playback::{with_jingles, Announcement, AnnouncementKind, PlaybackEvent},
+    util::{announce, find_announcement, play_file, prepare_announcement},
+};
+
+/// How long to wait for further joins before announcing a batch.
+const JOIN_BATCH_WINDOW: Duration = Duration::from_millis(1500);
+
+/// Numbers the combined TTS files, so batches that overlap don't share a file.
+static NEXT_BATCH_ID: AtomicU64 = AtomicU64::new(1);
+
+/// A join that is waiting to be announced.
+pub struct PendingJoin {
+    pub name: String,
+    pub user_id: u64,
+}
+
+/// Joins per voice channel that are waiting for the batch window to end.
+pub struct JoinBatches;
+
+impl TypeMapKey for JoinBatches {
+    type Value = Arc<Mutex<HashMap<ChannelId, Vec<PendingJoin>>>>;
+}
+
+/// Adds a join to the batch of the channel, the first join of a batch announces all of them once the window ends.
+pub async fn queue_announcement(ctx: &Context, channel_id: ChannelId, guild_id: GuildId, name: &str, user_id: u64) {
+    let batches = {
+        let data = ctx.data.read().await;
+        data.get::<JoinBatches>().expect("Join batches placed in at initialisation.").clone()
+    };
+
+    {
+        let mut batches = batches.lock().await;
+        let batch = batches.entry(channel_id).or_default();
+        batch.push(PendingJoin {
+            name: name.to_owned(),
+            user_id,
+        });
+
+        if batch.len() > 1 {
+            debug!("Added {} to the join batch of channel {}", name, channel_id);
+            return;
+        }
+    }
+
+    time::sleep(JOIN_BATCH_WINDOW).await;
+
+    let joins = batches.lock().await.remove(&channel_id).unwrap_or_default();
+    announce_batch(ctx, channel_id, guild_id, joins).await;
+}
+
+/// Removes the joins waiting in the batch of the channel without announcing them.
+pub async fn take_join_batch(ctx: &Context, channel_id: ChannelId) -> Vec<PendingJoin> {
+    let batches = {
+        let data = ctx.data.read().await;
+        match data.get::<JoinBatches>() {
+            Some(batches) => batches.clone(),
+            None => return vec![],
+        }
+    };
+
+    let joins = batches.lock().await.remove(&channel_id);
+    joins.unwrap_or_default()
+}
+
+/// Announces the joins waiting in the batch of the channel right away, the waiting batch finds it empty then.
+pub async fn replay_join_batch(ctx: &Context, channel_id: ChannelId, guild_id: GuildId) {
+    let joins = take_join_batch(ctx, channel_id).await;
+    if !joins.is_empty() {
+        debug!("Replaying {} pending joins of channel {}", joins.len(), channel_id);
+    }
+
+    announce_batch(ctx, channel_id, guild_id, joins).await;
+}
+
+async fn announce_batch(ctx: &Context, channel_id: ChannelId, guild_id: GuildId, joins: Vec<PendingJoin>) {
+    if joins.is_empty() {
+        return;
+    }
+
+    if joins.len() == 1 {
+        let join = &joins[0];
+        announce(ctx, channel_id, guild_id, &join.name, join.user_id).await;
+        return;
+    }
+
+    let mut tts_announcements = vec![];
+    let mut announcements = vec![];
+    for join in &joins {
+        let announcement = match find_announcement(&join.name, join.user_id) {
+            Some(announcement) => Some(prepare_announcement(guild_id, announcement).await),
+            None => None,
+        };
+        match announcement {
+            Some(announcement) if announcement.kind == AnnouncementKind::Tts => tts_announcements.push(announcement),
+            Some(announcement) => announcements.push(announcement),
+            None => continue,
+        }
+    }
+
+    if tts_announcements.len() == 1 {
+        announcements.insert(0, tts_announcements.remove(0));
+    } else if tts_announcements.len() > 1 {
+        let names = tts_announcements
+            .iter()
+            .map(|announcement| announcement.name.clone())
+            .collect::<Vec<_>>();
+        let phrase = format!("{} joined", join_names(&names));
+        let batch_id = NEXT_BATCH_ID.fetch_add(1, Ordering::SeqCst);
+        let path = format!("/config/processing/joined-{}-{}.wav", channel_id, batch_id);
+
+        let espeak_output = Command::new("espeak").arg("-w").arg(&path).arg(&phrase).output().await;
+        match espeak_output {
+            Ok(output) if output.status.success() => {
+                announcements.insert(0, Announcement::combined(&phrase, &path, tts_announcements));
+            }
+            Ok(output) => error!("Failed to run espeak for \"{}\": {}", &phrase, output.status),
+            Err(why) => error!("Failed to run espeak for \"{}\": {}", &phrase, why),
+        }
+    }
+
+    let segments = with_jingles(guild_id, announcements);
+    play_file(ctx, channel_id, guild_id, &segments, PlaybackEvent::BatchJoin).await;
+}
+
+/// Joins names into a sentence, e.g. "Alice, Bob and Carol".
+fn join_names(names: &[String]) -> String {
+    match names.split_last() {
+        Some((last, [])) => last.clone(),
+        Some((last, rest)) => format!("{} and {}", rest.join(", "), last),
+        None => "".to_string(),
+    }
+}
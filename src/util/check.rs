@@ -5,6 +5,14 @@ use serenity::{
     },
 };
 
+use crate::{
+    util::{
+        consts::BOT_ADMIN_USER_ID,
+        settings::{get_setting, MODERATOR_ROLE}
+    },
+    PContext,
+};
+
 /// Checks if user can connect to a voice channel
 pub fn can_connect(ctx: &Context, guild_id_opt: Option<GuildId>, channel_id_opt: Option<ChannelId>) -> bool {
     let guild_id = match guild_id_opt {
@@ -39,5 +47,45 @@ pub fn can_connect(ctx: &Context, guild_id_opt: Option<GuildId>, channel_id_opt:
         return true;
     }
 
-    return false;
-}
\ No newline at end of file
+    false
+}
+
+/// Checks if the author of a command may change the bot configuration of the guild
+pub async fn is_admin(ctx: PContext<'_>) -> bool {
+    if ctx.author().id.get() == BOT_ADMIN_USER_ID {
+        return true;
+    }
+
+    let member = match ctx.author_member().await {
+        Some(member) => member.into_owned(),
+        None => return false,
+    };
+
+    let permissions = match member.permissions {
+        Some(permissions) => permissions,
+        None => match ctx.guild() {
+            Some(guild) => guild.member_permissions(&member),
+            None => return false,
+        },
+    };
+
+    permissions.administrator() || permissions.manage_guild()
+}
+
+/// Checks if a member may approve or reject submissions in the guild
+pub fn is_moderator(guild_id: GuildId, member: &Member) -> bool {
+    if member.user.id.get() == BOT_ADMIN_USER_ID {
+        return true;
+    }
+
+    if let Some(permissions) = member.permissions {
+        if permissions.administrator() || permissions.manage_guild() {
+            return true;
+        }
+    }
+
+    match get_setting(guild_id, MODERATOR_ROLE).and_then(|id| id.parse::<u64>().ok()) {
+        Some(role_id) => member.roles.contains(&RoleId::new(role_id)),
+        None => false,
+    }
+}
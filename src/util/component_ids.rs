@@ -3,3 +3,25 @@ pub const ANNOUNCEMENT_SELECTOR_DROPDOWN: &str = "announcement_selector_dropdown
 
 pub const LIST_PREV_BUTTON: &str = "list_button_0";
 pub const LIST_NEXT_BUTTON: &str = "list_button_1";
+
+pub const PREVIEW_PLAY_BUTTON: &str = "preview_button_0";
+pub const PREVIEW_CONFIRM_BUTTON: &str = "preview_button_1";
+pub const PREVIEW_RETRIM_BUTTON: &str = "preview_button_2";
+pub const PREVIEW_DISCARD_BUTTON: &str = "preview_button_3";
+
+pub const MODERATION_APPROVE_BUTTON: &str = "moderation_button_0";
+pub const MODERATION_REJECT_BUTTON: &str = "moderation_button_1";
+
+pub const CONSENT_ACCEPT_BUTTON: &str = "consent_button_0";
+pub const CONSENT_DECLINE_BUTTON: &str = "consent_button_1";
+
+pub const DUPLICATE_USE_EXISTING_BUTTON: &str = "duplicate_button_0";
+pub const DUPLICATE_UPLOAD_ANYWAY_BUTTON: &str = "duplicate_button_1";
+
+pub const DELETE_CONFIRM_BUTTON: &str = "delete_button_0";
+pub const DELETE_CANCEL_BUTTON: &str = "delete_button_1";
+
+pub const SEARCH_PLAY_BUTTON: &str = "search_button_0";
+pub const SEARCH_COPY_BUTTON: &str = "search_button_1";
+
+pub const HISTORY_REPLAY_BUTTON: &str = "history_button_0";
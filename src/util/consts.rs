@@ -3,5 +3,6 @@
 pub const ELEMENT_LABEL_LENGTH: usize = 100;
 pub const ELEMENTS_PER_MENU: usize = 25;
 pub const EMBED_DESCRIPTION_MAX_LENGTH: usize = 4096;
+pub const EMBED_FIELD_MAX_LENGTH: usize = 1024;
 pub const BOT_ADMIN_USER_ID: u64 = 180995420196044809;
 pub const CUZ_USER_ID: u64 = 239705630913331201;
\ No newline at end of file
@@ -0,0 +1,394 @@
+/// Maximum number of filters in a filter chain.
+const MAX_FILTERS: usize = 10;
+/// Maximum length of a filter chain.
+const MAX_FILTERS_LENGTH: usize = 500;
+/// Maximum number of values in a `|` separated list, e.g. the delays of aecho.
+const MAX_LIST_VALUES: usize = 8;
+
+/// Accepted values of a filter option.
+enum Value {
+    Number(f64, f64),
+    Numbers(f64, f64),
+    Word(&'static [&'static str]),
+}
+
+/// Option of a filter, positional arguments are matched against the options in this order.
+struct FilterOption {
+    names: &'static [&'static str],
+    value: Value,
+}
+
+struct AllowedFilter {
+    name: &'static str,
+    options: &'static [FilterOption],
+}
+
+const fn option(names: &'static [&'static str], value: Value) -> FilterOption {
+    FilterOption { names, value }
+}
+
+const FREQUENCY: Value = Value::Number(1.0, 24000.0);
+const GAIN: Value = Value::Number(0.0, 1.0);
+const WIDTH_TYPE: Value = Value::Word(&["h", "q", "o", "s", "k"]);
+
+/// Audio filters that neither read nor write files nor generate audio, with bounds that keep them cheap.
+const ALLOWED_FILTERS: &[AllowedFilter] = &[
+    AllowedFilter { name: "acompressor", options: &[
+        option(&["level_in"], Value::Number(0.015625, 64.0)),
+        option(&["mode"], Value::Word(&["downward", "upward"])),
+        option(&["threshold"], Value::Number(0.000976563, 1.0)),
+        option(&["ratio"], Value::Number(1.0, 20.0)),
+        option(&["attack"], Value::Number(0.01, 2000.0)),
+        option(&["release"], Value::Number(0.01, 9000.0)),
+        option(&["makeup"], Value::Number(1.0, 64.0)),
+    ] },
+    AllowedFilter { name: "aecho", options: &[
+        option(&["in_gain"], GAIN),
+        option(&["out_gain"], GAIN),
+        option(&["delays"], Value::Numbers(1.0, 5000.0)),
+        option(&["decays"], Value::Numbers(0.0, 1.0)),
+    ] },
+    AllowedFilter { name: "afade", options: &[
+        option(&["type", "t"], Value::Word(&["in", "out"])),
+        option(&["start_sample", "ss"], Value::Number(0.0, 28800000.0)),
+        option(&["nb_samples", "ns"], Value::Number(1.0, 28800000.0)),
+        option(&["start_time", "st"], Value::Number(0.0, 600.0)),
+        option(&["duration", "d"], Value::Number(0.0, 600.0)),
+    ] },
+    AllowedFilter { name: "aphaser", options: &[
+        option(&["in_gain"], GAIN),
+        option(&["out_gain"], GAIN),
+        option(&["delay"], Value::Number(0.0, 5.0)),
+        option(&["decay"], Value::Number(0.0, 0.99)),
+        option(&["speed"], Value::Number(0.1, 2.0)),
+    ] },
+    AllowedFilter { name: "aresample", options: &[
+        option(&["sample_rate"], Value::Number(8000.0, 192000.0)),
+    ] },
+    AllowedFilter { name: "areverse", options: &[] },
+    AllowedFilter { name: "asetrate", options: &[
+        option(&["sample_rate", "r"], Value::Number(8000.0, 192000.0)),
+    ] },
+    AllowedFilter { name: "atempo", options: &[
+        option(&["tempo"], Value::Number(0.5, 2.0)),
+    ] },
+    AllowedFilter { name: "atrim", options: &[
+        option(&["start"], Value::Number(0.0, 600.0)),
+        option(&["end"], Value::Number(0.0, 600.0)),
+        option(&["duration"], Value::Number(0.0, 600.0)),
+    ] },
+    AllowedFilter { name: "bandpass", options: &[
+        option(&["frequency", "f"], FREQUENCY),
+        option(&["width_type", "t"], WIDTH_TYPE),
+        option(&["width", "w"], FREQUENCY),
+    ] },
+    AllowedFilter { name: "bandreject", options: &[
+        option(&["frequency", "f"], FREQUENCY),
+        option(&["width_type", "t"], WIDTH_TYPE),
+        option(&["width", "w"], FREQUENCY),
+    ] },
+    AllowedFilter { name: "bass", options: &[
+        option(&["frequency", "f"], FREQUENCY),
+        option(&["width_type", "t"], WIDTH_TYPE),
+        option(&["width", "w"], FREQUENCY),
+        option(&["gain", "g"], Value::Number(-30.0, 30.0)),
+    ] },
+    AllowedFilter { name: "chorus", options: &[
+        option(&["in_gain"], GAIN),
+        option(&["out_gain"], GAIN),
+        option(&["delays"], Value::Numbers(0.0, 100.0)),
+        option(&["decays"], Value::Numbers(0.0, 1.0)),
+        option(&["speeds"], Value::Numbers(0.0, 10.0)),
+        option(&["depths"], Value::Numbers(0.0, 10.0)),
+    ] },
+    AllowedFilter { name: "crystalizer", options: &[
+        option(&["i"], Value::Number(-10.0, 10.0)),
+    ] },
+    AllowedFilter { name: "equalizer", options: &[
+        option(&["frequency", "f"], FREQUENCY),
+        option(&["width_type", "t"], WIDTH_TYPE),
+        option(&["width", "w"], FREQUENCY),
+        option(&["gain", "g"], Value::Number(-30.0, 30.0)),
+    ] },
+    AllowedFilter { name: "extrastereo", options: &[
+        option(&["m"], Value::Number(-10.0, 10.0)),
+    ] },
+    AllowedFilter { name: "flanger", options: &[
+        option(&["delay"], Value::Number(0.0, 30.0)),
+        option(&["depth"], Value::Number(0.0, 10.0)),
+        option(&["regen"], Value::Number(-95.0, 95.0)),
+        option(&["width"], Value::Number(0.0, 100.0)),
+        option(&["speed"], Value::Number(0.1, 10.0)),
+    ] },
+    AllowedFilter { name: "highpass", options: &[
+        option(&["frequency", "f"], FREQUENCY),
+        option(&["width_type", "t"], WIDTH_TYPE),
+        option(&["width", "w"], FREQUENCY),
+        option(&["poles", "p"], Value::Number(1.0, 2.0)),
+    ] },
+    AllowedFilter { name: "lowpass", options: &[
+        option(&["frequency", "f"], FREQUENCY),
+        option(&["width_type", "t"], WIDTH_TYPE),
+        option(&["width", "w"], FREQUENCY),
+        option(&["poles", "p"], Value::Number(1.0, 2.0)),
+    ] },
+    AllowedFilter { name: "treble", options: &[
+        option(&["frequency", "f"], FREQUENCY),
+        option(&["width_type", "t"], WIDTH_TYPE),
+        option(&["width", "w"], FREQUENCY),
+        option(&["gain", "g"], Value::Number(-30.0, 30.0)),
+    ] },
+    AllowedFilter { name: "tremolo", options: &[
+        option(&["f"], Value::Number(0.1, 20000.0)),
+        option(&["d"], Value::Number(0.0, 1.0)),
+    ] },
+    AllowedFilter { name: "vibrato", options: &[
+        option(&["f"], Value::Number(0.1, 20000.0)),
+        option(&["d"], Value::Number(0.0, 1.0)),
+    ] },
+    AllowedFilter { name: "volume", options: &[
+        option(&["volume"], Value::Number(0.0, 10.0)),
+    ] },
+];
+
+/// Checks that user supplied ffmpeg filters are a single chain of allowed audio filters with bounded options.
+///
+/// Quoting, escaping, labels and multiple chains are rejected, as they are only needed to connect
+/// other inputs or to smuggle in options.
+pub fn validate_filters(filters: &str) -> Result<(), String> {
+    if filters.chars().count() > MAX_FILTERS_LENGTH {
+        return Err(format!("Filters may be at most {} characters long", MAX_FILTERS_LENGTH));
+    }
+
+    if let Some(c) = filters.chars().find(|c| ['\'', '"', '\\', '[', ']', ';'].contains(c) || c.is_control()) {
+        return Err(format!("`{}` is not allowed in filters", c));
+    }
+
+    let chain = filters.split(',').map(str::trim).collect::<Vec<_>>();
+    if chain.len() > MAX_FILTERS {
+        return Err(format!("At most {} filters can be applied", MAX_FILTERS));
+    }
+
+    for filter in chain {
+        validate_filter(filter)?;
+    }
+
+    Ok(())
+}
+
+/// Checks the size of a complete filter chain, which also contains the filters of built-in presets.
+///
+/// Presets are trusted to use other filters than users, but chaining them still has to stay within the limits.
+pub fn validate_chain_size(filters: &str) -> Result<(), String> {
+    if filters.chars().count() > MAX_FILTERS_LENGTH {
+        return Err(format!("The filters of all presets may be at most {} characters long", MAX_FILTERS_LENGTH));
+    }
+
+    if count_filters(filters) > MAX_FILTERS {
+        return Err(format!("At most {} filters can be applied, including the filters of presets", MAX_FILTERS));
+    }
+
+    Ok(())
+}
+
+/// Counts the filters of a chain, commas within quoted options don't separate filters.
+fn count_filters(filters: &str) -> usize {
+    let mut quoted = false;
+    let mut count = 1;
+    for c in filters.chars() {
+        match c {
+            '\'' => quoted = !quoted,
+            ',' if !quoted => count += 1,
+            _ => {}
+        }
+    }
+
+    count
+}
+
+fn validate_filter(filter: &str) -> Result<(), String> {
+    if filter.is_empty() {
+        return Err("Filters can't be empty".to_string());
+    }
+
+    let (name, args) = match filter.split_once('=') {
+        Some((name, args)) => (name.trim(), Some(args)),
+        None => (filter, None),
+    };
+
+    let allowed_filter = match ALLOWED_FILTERS.iter().find(|allowed_filter| allowed_filter.name == name) {
+        Some(allowed_filter) => allowed_filter,
+        None => return Err(format!("The filter `{}` is not allowed", name)),
+    };
+
+    let args = match args {
+        Some(args) => args.split(':').collect::<Vec<_>>(),
+        None => return Ok(()),
+    };
+
+    let mut named = false;
+    for (position, arg) in args.into_iter().enumerate() {
+        let (filter_option, value) = match arg.split_once('=') {
+            Some((option_name, value)) => {
+                named = true;
+                match allowed_filter.options.iter().find(|filter_option| filter_option.names.contains(&option_name)) {
+                    Some(filter_option) => (filter_option, value),
+                    None => return Err(format!("`{}` has no allowed option `{}`", name, option_name)),
+                }
+            }
+            None if named => return Err(format!("`{}` has a positional option after a named one", name)),
+            None => match allowed_filter.options.get(position) {
+                Some(filter_option) => (filter_option, arg),
+                None => return Err(format!("`{}` has too many options", name)),
+            },
+        };
+
+        if let Err(why) = validate_value(&filter_option.value, value) {
+            return Err(format!("Invalid value for `{}` of `{}`: {}", filter_option.names[0], name, why));
+        }
+    }
+
+    Ok(())
+}
+
+fn validate_value(allowed: &Value, value: &str) -> Result<(), String> {
+    match allowed {
+        Value::Number(min, max) => validate_number(value, *min, *max),
+        Value::Numbers(min, max) => {
+            let values = value.split('|').collect::<Vec<_>>();
+            if values.len() > MAX_LIST_VALUES {
+                return Err(format!("at most {} values are allowed", MAX_LIST_VALUES));
+            }
+            values.into_iter().try_for_each(|value| validate_number(value, *min, *max))
+        }
+        Value::Word(words) => match words.contains(&value) {
+            true => Ok(()),
+            false => Err(format!("has to be one of {}", words.join(", "))),
+        },
+    }
+}
+
+fn validate_number(value: &str, min: f64, max: f64) -> Result<(), String> {
+    match value.trim().parse::<f64>() {
+        Ok(number) if number.is_finite() && number >= min && number <= max => Ok(()),
+        Ok(_) => Err(format!("has to be between {} and {}", min, max)),
+        Err(_) => Err(format!("`{}` is not a number", value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_allowed_filters() {
+        for filters in [
+            "volume=2",
+            "areverse",
+            "asetrate=r=55125",
+            "aecho=0.8:0.9:1000|500:0.3|0.25",
+            "bass=g=10:f=110, treble=g=-5",
+            "afade=t=in:d=2",
+            "atempo=1.5,volume=volume=0.5",
+        ] {
+            assert!(validate_filters(filters).is_ok(), "{}", filters);
+        }
+    }
+
+    #[test]
+    fn rejects_filters_that_read_files() {
+        for filters in [
+            "amovie=/etc/passwd",
+            "movie=/config/database/db.sqlite",
+            "volume=2,amovie=file.wav",
+            "sendcmd=f=commands.txt",
+            "ladspa=file=/tmp/plugin.so",
+            "aevalsrc=sin(440*2*PI*t)",
+            "anullsrc",
+        ] {
+            assert!(validate_filters(filters).is_err(), "{}", filters);
+        }
+    }
+
+    #[test]
+    fn rejects_quotes_escapes_and_labels() {
+        for filters in [
+            "volume='2'",
+            "volume=\"2\"",
+            "volume=2\\,amovie=x",
+            "volume=2;amovie=x",
+            "[in]volume=2[out]",
+            "volume=2[a];[a]areverse",
+            "amovie=x [extra]; [extra][0:a] amix",
+            "volume=2\n",
+            "volume=2\0",
+        ] {
+            assert!(validate_filters(filters).is_err(), "{:?}", filters);
+        }
+    }
+
+    #[test]
+    fn rejects_out_of_range_values() {
+        for filters in [
+            "volume=11",
+            "volume=-1",
+            "volume=nan",
+            "volume=NaN",
+            "volume=inf",
+            "volume=-inf",
+            "volume=infinity",
+            "volume=1e400",
+            "volume=",
+            "volume=loud",
+            "atempo=100",
+            "atrim=end=100000",
+            "bass=t=x",
+            "bass=f=110:g=10:t=q:w=1:extra=1",
+            "areverse=1",
+            "volume=volume=2:2",
+            "volume=precision=double",
+            "asetrate=r=44100*1.25",
+        ] {
+            assert!(validate_filters(filters).is_err(), "{}", filters);
+        }
+    }
+
+    #[test]
+    fn rejects_too_long_lists() {
+        let delays = ["10"; MAX_LIST_VALUES].join("|");
+        assert!(validate_filters(&format!("aecho=0.8:0.9:{}:0.3", delays)).is_ok());
+
+        let delays = ["10"; MAX_LIST_VALUES + 1].join("|");
+        assert!(validate_filters(&format!("aecho=0.8:0.9:{}:0.3", delays)).is_err());
+
+        let chain = ["areverse"; MAX_FILTERS].join(",");
+        assert!(validate_filters(&chain).is_ok());
+
+        let chain = ["areverse"; MAX_FILTERS + 1].join(",");
+        assert!(validate_filters(&chain).is_err());
+
+        let long = format!("volume={}", "0".repeat(MAX_FILTERS_LENGTH));
+        assert!(validate_filters(&long).is_err());
+    }
+
+    #[test]
+    fn rejects_empty_filters() {
+        for filters in ["", ",", "volume=2,", ",volume=2", "volume=2,,areverse"] {
+            assert!(validate_filters(filters).is_err(), "{:?}", filters);
+        }
+    }
+
+    #[test]
+    fn counts_quoted_filters_once() {
+        assert_eq!(count_filters("areverse"), 1);
+        assert_eq!(count_filters("highpass=f=300,lowpass=f=3000,acompressor"), 3);
+        assert_eq!(count_filters("afftfilt=real='hypot(re,im)':imag='hypot(re,im)',areverse"), 2);
+    }
+
+    #[test]
+    fn bounds_chain_size() {
+        assert!(validate_chain_size(&["areverse"; MAX_FILTERS].join(",")).is_ok());
+        assert!(validate_chain_size(&["areverse"; MAX_FILTERS + 1].join(",")).is_err());
+        assert!(validate_chain_size(&format!("volume={}", "0".repeat(MAX_FILTERS_LENGTH))).is_err());
+    }
+}
@@ -0,0 +1,422 @@
+use rusqlite::{params, Connection};
+use std::{fs::File, path::Path};
+use symphonia::core::{
+    audio::SampleBuffer,
+    codecs::{DecoderOptions, CODEC_TYPE_NULL},
+    errors::Error as SymphoniaError,
+    formats::FormatOptions,
+    io::MediaSourceStream,
+    meta::MetadataOptions,
+    probe::Hint,
+};
+use tracing::{debug, error, info};
+
+use crate::util::paths::{decode_file_stem, decode_segment, index_path};
+
+/// Length of a fingerprint frame in seconds.
+const FRAME_DURATION: f64 = 0.05;
+/// Cutoffs in Hz of the one-pole lowpasses that split the audio into bands.
+const BAND_CUTOFFS: [f64; 3] = [300.0, 1000.0, 3000.0];
+const BANDS: usize = BAND_CUTOFFS.len() + 1;
+/// Bits per frame: energy change of every band and change of the balance between neighbouring bands.
+const FRAME_BITS: usize = BANDS + BANDS - 1;
+/// Most frames the clips may be shifted against each other when comparing, to allow for different trims.
+const MAX_OFFSET: usize = 20;
+/// Share of matching bits from which clips count as the same, unrelated clips match about half of them.
+pub const DUPLICATE_SIMILARITY: f64 = 0.85;
+
+/// Computes a compact fingerprint of a clip, one byte per frame with the bits described at [`FRAME_BITS`].
+pub fn compute_fingerprint(path: &str) -> Result<Vec<u8>, String> {
+    let file = File::open(path).map_err(|why| why.to_string())?;
+    let source = MediaSourceStream::new(Box::new(file), Default::default());
+
+    let mut probed = symphonia::default::get_probe()
+        .format(&Hint::new(), source, &FormatOptions::default(), &MetadataOptions::default())
+        .map_err(|why| why.to_string())?;
+
+    let track = match probed.format.tracks().iter().find(|track| track.codec_params.codec != CODEC_TYPE_NULL) {
+        Some(track) => track.clone(),
+        None => return Err("No audio track".to_string()),
+    };
+    let sample_rate = match track.codec_params.sample_rate {
+        Some(sample_rate) => sample_rate as f64,
+        None => return Err("Unknown sample rate".to_string()),
+    };
+
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())
+        .map_err(|why| why.to_string())?;
+
+    let mut fingerprinter = Fingerprinter::new(sample_rate);
+
+    loop {
+        let packet = match probed.format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(_)) => break,
+            Err(why) => return Err(why.to_string()),
+        };
+        if packet.track_id() != track.id {
+            continue;
+        }
+
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            Err(SymphoniaError::DecodeError(why)) => {
+                debug!("Skipping undecodable packet of {}: {}", path, why);
+                continue;
+            }
+            Err(why) => return Err(why.to_string()),
+        };
+
+        let channels = decoded.spec().channels.count().max(1);
+        let mut samples = SampleBuffer::<f32>::new(decoded.capacity() as u64, *decoded.spec());
+        samples.copy_interleaved_ref(decoded);
+
+        for frame in samples.samples().chunks(channels) {
+            fingerprinter.push(frame.iter().map(|sample| *sample as f64).sum::<f64>() / channels as f64);
+        }
+    }
+
+    Ok(fingerprinter.finish())
+}
+
+/// Splits mono samples into bands and frames and turns the energy changes between frames into bits.
+struct Fingerprinter {
+    alphas: [f64; BAND_CUTOFFS.len()],
+    frame_samples: usize,
+    lowpasses: [f64; BAND_CUTOFFS.len()],
+    energies: [f64; BANDS],
+    frame_len: usize,
+    frames: Vec<[f64; BANDS]>,
+}
+
+impl Fingerprinter {
+    fn new(sample_rate: f64) -> Fingerprinter {
+        Fingerprinter {
+            alphas: BAND_CUTOFFS.map(|cutoff| 1.0 - (-2.0 * std::f64::consts::PI * cutoff / sample_rate).exp()),
+            frame_samples: (sample_rate * FRAME_DURATION) as usize,
+            lowpasses: [0.0; BAND_CUTOFFS.len()],
+            energies: [0.0; BANDS],
+            frame_len: 0,
+            frames: vec![],
+        }
+    }
+
+    fn push(&mut self, sample: f64) {
+        let mut previous = sample;
+        for (band, (lowpass, alpha)) in self.lowpasses.iter_mut().zip(self.alphas).enumerate().rev() {
+            *lowpass += alpha * (sample - *lowpass);
+            self.energies[band + 1] += (previous - *lowpass).powi(2);
+            previous = *lowpass;
+        }
+        self.energies[0] += previous.powi(2);
+
+        self.frame_len += 1;
+        if self.frame_len == self.frame_samples {
+            let frame_samples = self.frame_samples as f64;
+            self.frames.push(self.energies.map(|energy| (energy / frame_samples + 1e-10).ln()));
+            self.energies = [0.0; BANDS];
+            self.frame_len = 0;
+        }
+    }
+
+    fn finish(self) -> Vec<u8> {
+        self.frames
+            .windows(2)
+            .map(|pair| {
+                let (previous, current) = (&pair[0], &pair[1]);
+                let mut bits = 0u8;
+                for band in 0..BANDS {
+                    bits = bits << 1 | (current[band] > previous[band]) as u8;
+                }
+                for band in 0..BANDS - 1 {
+                    let balance = current[band] - current[band + 1];
+                    let previous_balance = previous[band] - previous[band + 1];
+                    bits = bits << 1 | (balance > previous_balance) as u8;
+                }
+                bits
+            })
+            .collect()
+    }
+}
+
+/// Share of matching bits of two fingerprints at the best alignment, 0 if they are too different in length.
+pub fn similarity(a: &[u8], b: &[u8]) -> f64 {
+    let (shorter, longer) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+    if shorter.is_empty() || (shorter.len() as f64) < longer.len() as f64 * 0.7 {
+        return 0.0;
+    }
+
+    let min_overlap = (shorter.len() * 4 / 5).max(1);
+    let mut best = 0.0;
+
+    for offset in 0..=MAX_OFFSET.min(longer.len() - min_overlap) {
+        for (first, second) in [(shorter, &longer[offset..]), (&shorter[offset.min(shorter.len())..], longer)] {
+            let overlap = first.len().min(second.len());
+            if overlap < min_overlap {
+                continue;
+            }
+
+            let differing = first
+                .iter()
+                .zip(second.iter())
+                .map(|(x, y)| (x ^ y).count_ones() as usize)
+                .sum::<usize>();
+            let matching = 1.0 - differing as f64 / (overlap * FRAME_BITS) as f64;
+            if matching > best {
+                best = matching;
+            }
+        }
+    }
+
+    best
+}
+
+/// Existing clip that sounds like a new one.
+#[derive(Clone, Debug)]
+pub struct Duplicate {
+    pub name: String,
+    pub announcement_name: String,
+    pub similarity: f64,
+}
+
+/// Finds the clip that is most similar to the given file, ignoring the clip it replaces.
+pub fn find_duplicate(path: &str, name: &str, announcement_name: &str) -> Option<Duplicate> {
+    let fingerprint = match compute_fingerprint(path) {
+        Ok(fingerprint) => fingerprint,
+        Err(why) => {
+            error!("Failed to compute fingerprint of {}: {}", path, why);
+            return None;
+        }
+    };
+
+    let stored = match get_fingerprints() {
+        Ok(stored) => stored,
+        Err(why) => {
+            error!("Failed to query fingerprints: {}", why);
+            return None;
+        }
+    };
+
+    stored
+        .into_iter()
+        .filter(|(stored_name, stored_announcement, _)| stored_name != name || stored_announcement != announcement_name)
+        .map(|(name, announcement_name, stored_fingerprint)| Duplicate {
+            name,
+            announcement_name,
+            similarity: similarity(&fingerprint, &stored_fingerprint),
+        })
+        .filter(|duplicate| duplicate.similarity >= DUPLICATE_SIMILARITY)
+        .max_by(|a, b| a.similarity.total_cmp(&b.similarity))
+}
+
+/// Computes and stores the fingerprint of an indexed clip.
+pub fn update_fingerprint(name: &str, announcement_name: &str) {
+    let path = index_path(name, announcement_name);
+    let fingerprint = match compute_fingerprint(&path) {
+        Ok(fingerprint) => fingerprint,
+        Err(why) => {
+            error!("Failed to compute fingerprint of {}: {}", &path, why);
+            return;
+        }
+    };
+
+    if let Err(why) = set_fingerprint(name, announcement_name, &fingerprint) {
+        error!("Failed to store fingerprint of {}: {}", &path, why);
+    }
+}
+
+/// Computes the fingerprints of all indexed clips that don't have one yet.
+pub fn backfill_fingerprints() {
+    let stored = match get_fingerprints() {
+        Ok(stored) => stored,
+        Err(why) => {
+            error!("Failed to query fingerprints: {}", why);
+            return;
+        }
+    };
+
+    let name_dirs = match std::fs::read_dir("/config/index") {
+        Ok(name_dirs) => name_dirs,
+        Err(why) => {
+            error!("Failed to read index: {}", why);
+            return;
+        }
+    };
+
+    let mut count = 0;
+    for name_dir in name_dirs.flatten() {
+        let name = decode_segment(&name_dir.file_name().to_string_lossy());
+        let entries = match std::fs::read_dir(name_dir.path()) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+
+        for entry in entries.flatten() {
+            let announcement_name = match decode_file_stem(&entry.path()) {
+                Some(announcement_name) => announcement_name,
+                None => continue,
+            };
+            if stored.iter().any(|(stored_name, stored_announcement, _)| stored_name == &name && stored_announcement == &announcement_name) {
+                continue;
+            }
+
+            update_fingerprint(&name, &announcement_name);
+            count += 1;
+        }
+    }
+
+    if count > 0 {
+        info!("Computed {} missing fingerprints", count);
+    }
+}
+
+fn get_fingerprints() -> Result<Vec<(String, String, Vec<u8>)>, rusqlite::Error> {
+    let db_path = Path::new("/config/database/db.sqlite");
+    let db = Connection::open(db_path)?;
+
+    let mut stmt = db.prepare("SELECT name, announcement, fingerprint FROM fingerprints")?;
+    let fingerprints = stmt
+        .query_map(params![], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+        .collect::<Result<Vec<(String, String, Vec<u8>)>, _>>()?;
+
+    Ok(fingerprints)
+}
+
+fn set_fingerprint(name: &str, announcement_name: &str, fingerprint: &[u8]) -> Result<(), rusqlite::Error> {
+    let db_path = Path::new("/config/database/db.sqlite");
+    let db = Connection::open(db_path)?;
+
+    db.execute(
+        "INSERT OR REPLACE INTO fingerprints (name, announcement, fingerprint)
+            VALUES (?1, ?2, ?3)",
+        params![name, announcement_name, fingerprint],
+    )?;
+
+    Ok(())
+}
+
+/// Removes the stored fingerprint of a clip that was deleted.
+pub fn remove_fingerprint(name: &str, announcement_name: &str) -> Result<(), rusqlite::Error> {
+    let db_path = Path::new("/config/database/db.sqlite");
+    let db = Connection::open(db_path)?;
+
+    db.execute(
+        "DELETE FROM fingerprints WHERE name=?1 AND announcement=?2",
+        params![name, announcement_name],
+    )?;
+
+    Ok(())
+}
+
+/// Moves the stored fingerprint of a clip to its new announcement name.
+pub fn rename_fingerprint(name: &str, announcement_name: &str, new_announcement_name: &str) -> Result<(), rusqlite::Error> {
+    let db_path = Path::new("/config/database/db.sqlite");
+    let db = Connection::open(db_path)?;
+
+    db.execute(
+        "UPDATE OR REPLACE fingerprints SET announcement=?3 WHERE name=?1 AND announcement=?2",
+        params![name, announcement_name, new_announcement_name],
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::recording::write_wav;
+    use std::fs;
+
+    const SAMPLE_RATE: f64 = 48000.0;
+
+    /// A clip whose tones and loudness change every 50ms, like speech does, determined by the seed.
+    fn synthetic_clip(seed: u64, seconds: f64) -> Vec<f64> {
+        let mut state = seed;
+        let mut random = move || {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (state >> 33) as f64 / (1u64 << 31) as f64
+        };
+
+        let segment_samples = (SAMPLE_RATE * 0.05) as usize;
+        let mut samples = vec![];
+        let mut phases = [0.0; 3];
+        while samples.len() < (SAMPLE_RATE * seconds) as usize {
+            let frequencies = [100.0 + 400.0 * random(), 500.0 + 1500.0 * random(), 2000.0 + 4000.0 * random()];
+            let amplitudes = [random(), random(), random()];
+            for _ in 0..segment_samples {
+                let mut sample = 0.0;
+                for ((phase, frequency), amplitude) in phases.iter_mut().zip(frequencies).zip(amplitudes) {
+                    *phase += 2.0 * std::f64::consts::PI * frequency / SAMPLE_RATE;
+                    sample += 0.2 * amplitude * phase.sin();
+                }
+                samples.push(sample);
+            }
+        }
+
+        samples
+    }
+
+    fn fingerprint(samples: &[f64]) -> Vec<u8> {
+        let mut fingerprinter = Fingerprinter::new(SAMPLE_RATE);
+        samples.iter().for_each(|sample| fingerprinter.push(*sample));
+        fingerprinter.finish()
+    }
+
+    #[test]
+    fn matches_the_same_clip_at_another_gain() {
+        let clip = synthetic_clip(1, 3.0);
+        let quieter = clip.iter().map(|sample| sample * 0.3).collect::<Vec<_>>();
+
+        let score = similarity(&fingerprint(&clip), &fingerprint(&quieter));
+        assert!(score >= DUPLICATE_SIMILARITY, "{}", score);
+    }
+
+    #[test]
+    fn matches_the_same_clip_with_an_offset() {
+        let clip = synthetic_clip(1, 3.0);
+        // trimmed differently, by a bit more than 6 frames
+        let trimmed = &clip[(SAMPLE_RATE * 0.31) as usize..];
+
+        let score = similarity(&fingerprint(&clip), &fingerprint(trimmed));
+        assert!(score >= DUPLICATE_SIMILARITY, "{}", score);
+        assert!(similarity(&fingerprint(trimmed), &fingerprint(&clip)) >= DUPLICATE_SIMILARITY);
+    }
+
+    #[test]
+    fn tells_unrelated_clips_apart() {
+        let clip = fingerprint(&synthetic_clip(1, 3.0));
+        for seed in 2..6 {
+            let score = similarity(&clip, &fingerprint(&synthetic_clip(seed, 3.0)));
+            assert!(score < DUPLICATE_SIMILARITY, "{}: {}", seed, score);
+        }
+    }
+
+    #[test]
+    fn ignores_clips_of_very_different_length() {
+        let clip = fingerprint(&synthetic_clip(1, 3.0));
+        assert_eq!(similarity(&clip, &clip[..clip.len() / 2]), 0.0);
+        assert_eq!(similarity(&clip, &[]), 0.0);
+        assert_eq!(similarity(&clip, &clip), 1.0);
+    }
+
+    #[test]
+    fn fingerprints_files() {
+        let path = std::env::temp_dir().join(format!("announcer-fingerprint-{}.wav", std::process::id()));
+        let clip = synthetic_clip(1, 2.0);
+        let samples = clip
+            .iter()
+            .flat_map(|sample| {
+                let sample = (sample * i16::MAX as f64) as i16;
+                [sample, sample]
+            })
+            .collect::<Vec<_>>();
+        write_wav(path.to_str().unwrap(), &samples).unwrap();
+
+        let fingerprint_res = compute_fingerprint(path.to_str().unwrap());
+        let _ = fs::remove_file(&path);
+
+        let score = similarity(&fingerprint_res.unwrap(), &fingerprint(&clip));
+        assert!(score >= DUPLICATE_SIMILARITY, "{}", score);
+    }
+}
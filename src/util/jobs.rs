@@ -0,0 +1,286 @@
+use poise::{CreateReply, ReplyHandle};
+use serenity::{all::CreateEmbed, model::colour::Colour};
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    fs,
+    process::{ExitStatus, Output, Stdio},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+use tokio::{
+    process::Command,
+    sync::{Semaphore, SemaphorePermit},
+    time::{self, Instant},
+};
+use tracing::{debug, error};
+use url::Url;
+
+use crate::{
+    util::{media::download_public_url, paths::ProcessingFiles},
+    PContext, PError,
+};
+
+/// Number of jobs that are processed at the same time.
+const MAX_CONCURRENT_JOBS: usize = 2;
+/// Time a job may run for, once it has left the queue.
+const JOB_TIMEOUT: Duration = Duration::from_secs(180);
+/// Number of finished jobs that are kept for `/jobs`.
+const FINISHED_JOBS_KEPT: usize = 10;
+/// How often watched commands are checked while they run.
+const WATCH_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum JobStage {
+    Queued,
+    Downloading,
+    Trimming,
+    Normalising,
+    Done,
+    Failed(String),
+}
+
+impl fmt::Display for JobStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JobStage::Queued => write!(f, "Queued"),
+            JobStage::Downloading => write!(f, "Downloading"),
+            JobStage::Trimming => write!(f, "Trimming"),
+            JobStage::Normalising => write!(f, "Normalising"),
+            JobStage::Done => write!(f, "Done"),
+            JobStage::Failed(why) => write!(f, "Failed: {}", why),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct JobInfo {
+    pub id: u64,
+    pub user_id: u64,
+    pub name: String,
+    pub announcement: String,
+    pub stage: JobStage,
+    pub submitted: Instant,
+}
+
+/// Processing jobs that are queued, running or finished recently.
+pub struct JobQueue {
+    workers: Semaphore,
+    next_id: AtomicU64,
+    active: Mutex<HashMap<u64, JobInfo>>,
+    finished: Mutex<VecDeque<JobInfo>>,
+}
+
+impl JobQueue {
+    pub fn new() -> JobQueue {
+        JobQueue {
+            workers: Semaphore::new(MAX_CONCURRENT_JOBS),
+            next_id: AtomicU64::new(1),
+            active: Mutex::new(HashMap::new()),
+            finished: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// All active jobs in submission order, followed by the most recently finished ones.
+    pub fn list(&self) -> Vec<JobInfo> {
+        let mut jobs = self.active.lock().unwrap().values().cloned().collect::<Vec<_>>();
+        jobs.sort_by_key(|job| job.id);
+        jobs.extend(self.finished.lock().unwrap().iter().rev().cloned());
+
+        jobs
+    }
+
+    fn add(&self, user_id: u64, name: &str, announcement: &str) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let job = JobInfo {
+            id,
+            user_id,
+            name: name.to_owned(),
+            announcement: announcement.to_owned(),
+            stage: JobStage::Queued,
+            submitted: Instant::now(),
+        };
+        self.active.lock().unwrap().insert(id, job);
+
+        id
+    }
+
+    fn set_stage(&self, id: u64, stage: JobStage) {
+        let mut active = self.active.lock().unwrap();
+        let done = matches!(stage, JobStage::Done | JobStage::Failed(_));
+
+        if let Some(job) = active.get_mut(&id) {
+            job.stage = stage;
+        }
+
+        if done {
+            if let Some(job) = active.remove(&id) {
+                let mut finished = self.finished.lock().unwrap();
+                finished.push_back(job);
+                while finished.len() > FINISHED_JOBS_KEPT {
+                    finished.pop_front();
+                }
+            }
+        }
+    }
+}
+
+impl Default for JobQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A running processing job, its progress is shown in a reply to the submitter.
+pub struct Job<'a> {
+    ctx: PContext<'a>,
+    id: u64,
+    name: String,
+    announcement: String,
+    handle: Option<ReplyHandle<'a>>,
+    deadline: Instant,
+    permit: Option<SemaphorePermit<'a>>,
+    finished: bool,
+}
+
+impl<'a> Job<'a> {
+    /// Queues a job and waits until a worker is free to process it.
+    pub async fn start(ctx: PContext<'a>, name: &str, announcement: &str) -> Result<Job<'a>, PError> {
+        let queue = &ctx.data().jobs;
+        let id = queue.add(ctx.author().id.get(), name, announcement);
+
+        let mut job = Job {
+            ctx,
+            id,
+            name: name.to_owned(),
+            announcement: announcement.to_owned(),
+            handle: None,
+            deadline: Instant::now() + JOB_TIMEOUT,
+            permit: None,
+            finished: false,
+        };
+
+        job.handle = Some(ctx.send(job.create_reply(&JobStage::Queued)).await?);
+
+        job.permit = Some(queue.workers.acquire().await?);
+        job.deadline = Instant::now() + JOB_TIMEOUT;
+        debug!("Started job {} for {}", id, announcement);
+
+        Ok(job)
+    }
+
+    /// Temporary files of the job in the processing directory.
+    pub fn files(&self) -> ProcessingFiles {
+        ProcessingFiles::new(self.ctx.author().id.get(), self.id)
+    }
+
+    pub async fn stage(&self, stage: JobStage) {
+        self.ctx.data().jobs.set_stage(self.id, stage.clone());
+        self.update_reply(&stage).await;
+    }
+
+    /// Marks the job as done and frees its worker.
+    pub async fn done(mut self) {
+        self.finished = true;
+        self.permit = None;
+        self.stage(JobStage::Done).await;
+    }
+
+    /// Marks the job as failed and frees its worker.
+    pub async fn fail(mut self, why: &str) {
+        self.finished = true;
+        self.permit = None;
+        self.stage(JobStage::Failed(why.to_owned())).await;
+    }
+
+    /// Frees the worker between the files of jobs that process many files one after another, so other jobs
+    /// don't wait for the whole run, then waits for a worker again and gives the job the full time for the next file.
+    pub async fn next_file(&mut self) -> Result<(), PError> {
+        self.permit = None;
+        self.permit = Some(self.ctx.data().jobs.workers.acquire().await?);
+        self.deadline = Instant::now() + JOB_TIMEOUT;
+
+        Ok(())
+    }
+
+    /// Runs a command of the job, killing it if the job takes too long.
+    pub async fn run(&self, command: &mut Command) -> Result<Output, String> {
+        run_command(command, self.deadline).await
+    }
+
+    /// Downloads a link for the job, giving up if the job takes too long.
+    pub async fn download(&self, url: &Url, path: &str) -> Result<(), String> {
+        match time::timeout_at(self.deadline, download_public_url(url, path)).await {
+            Ok(download_res) => download_res,
+            Err(_) => {
+                let _ = fs::remove_file(path);
+                Err("Timed out".to_string())
+            }
+        }
+    }
+
+    /// Runs a command of the job like `run`, but polls `check` while it runs and kills it once `check` fails.
+    ///
+    /// The output of the command is discarded.
+    pub async fn run_watched<F>(&self, command: &mut Command, mut check: F) -> Result<ExitStatus, String>
+    where
+        F: FnMut() -> Result<(), String>,
+    {
+        command.kill_on_drop(true).stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null());
+        let mut child = command.spawn().map_err(|why| why.to_string())?;
+
+        let mut interval = time::interval(WATCH_INTERVAL);
+        loop {
+            tokio::select! {
+                status = child.wait() => return status.map_err(|why| why.to_string()),
+                _ = time::sleep_until(self.deadline) => return Err("Timed out".to_string()),
+                _ = interval.tick() => check()?,
+            }
+        }
+    }
+
+    async fn update_reply(&self, stage: &JobStage) {
+        if let Some(handle) = &self.handle {
+            if let Err(why) = handle.edit(self.ctx, self.create_reply(stage)).await {
+                error!("Failed to update progress of job {}: {}", self.id, why);
+            }
+        }
+    }
+
+    fn create_reply(&self, stage: &JobStage) -> CreateReply {
+        let colour = match stage {
+            JobStage::Done => Colour::from_rgb(67, 160, 71),
+            JobStage::Failed(_) => Colour::from_rgb(229, 57, 53),
+            _ => Colour::from_rgb(128, 128, 128),
+        };
+
+        CreateReply::default()
+            .embed(CreateEmbed::new()
+                .title(format!("Processing `{}` for {}", &self.announcement, &self.name))
+                .description(format!("Job #{}: **{}**", self.id, stage))
+                .colour(colour)
+            )
+    }
+}
+
+impl Drop for Job<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.ctx.data().jobs.set_stage(self.id, JobStage::Failed("Cancelled".to_string()));
+        }
+    }
+}
+
+/// Runs a command without blocking, killing it once the deadline has passed.
+async fn run_command(command: &mut Command, deadline: Instant) -> Result<Output, String> {
+    command.kill_on_drop(true);
+
+    match time::timeout_at(deadline, command.output()).await {
+        Ok(Ok(output)) => Ok(output),
+        Ok(Err(why)) => Err(why.to_string()),
+        Err(_) => Err("Timed out".to_string()),
+    }
+}
@@ -0,0 +1,96 @@
+use rusqlite::{params, Connection};
+use serenity::model::id::{GuildId, RoleId};
+use std::path::Path;
+use tracing::error;
+
+use crate::{
+    util::settings::{get_setting, CLIP_LENGTH},
+    PContext,
+};
+
+/// Length new clips are cut to, if the guild didn't set a limit.
+pub const DEFAULT_CLIP_LENGTH: f64 = 6.0;
+/// How much longer than the limit a trimmed range may be, as silence is removed and the clip is cut anyway.
+pub const TRIM_TOLERANCE: f64 = 1.0;
+
+/// Maximum clip length of the guild, without role overrides.
+pub fn guild_clip_length(guild_id: GuildId) -> f64 {
+    get_setting(guild_id, CLIP_LENGTH)
+        .and_then(|value| value.parse::<f64>().ok())
+        .unwrap_or(DEFAULT_CLIP_LENGTH)
+}
+
+/// Maximum clip length for a member with the given roles, the longest of the guild limit and the role overrides.
+pub fn clip_length_limit(guild_id: GuildId, roles: &[RoleId]) -> f64 {
+    let role_limits = match get_role_clip_lengths(guild_id) {
+        Ok(role_limits) => role_limits,
+        Err(why) => {
+            error!("Failed to query role clip lengths for guild {}: {}", guild_id, why);
+            vec![]
+        }
+    };
+
+    role_limits
+        .into_iter()
+        .filter(|(role_id, _)| roles.contains(role_id))
+        .map(|(_, seconds)| seconds)
+        .fold(guild_clip_length(guild_id), f64::max)
+}
+
+/// Maximum clip length for the author of a command.
+pub async fn author_clip_length_limit(ctx: PContext<'_>) -> f64 {
+    let guild_id = match ctx.guild_id() {
+        Some(guild_id) => guild_id,
+        None => return DEFAULT_CLIP_LENGTH,
+    };
+
+    match ctx.author_member().await {
+        Some(member) => clip_length_limit(guild_id, &member.roles),
+        None => guild_clip_length(guild_id),
+    }
+}
+
+pub fn get_role_clip_lengths(guild_id: GuildId) -> Result<Vec<(RoleId, f64)>, rusqlite::Error> {
+    let db_path = Path::new("/config/database/db.sqlite");
+    let db = Connection::open(db_path)?;
+
+    let mut stmt = db.prepare("SELECT role_id, seconds FROM role_clip_lengths WHERE guild_id=?1")?;
+    let role_limits = stmt
+        .query_map(params![guild_id.get() as i64], |row| {
+            Ok((RoleId::new(row.get::<_, i64>(0)? as u64), row.get(1)?))
+        })?
+        .collect::<Result<Vec<(RoleId, f64)>, _>>()?;
+
+    Ok(role_limits)
+}
+
+pub fn set_role_clip_length(guild_id: GuildId, role_id: RoleId, seconds: f64) -> Result<(), rusqlite::Error> {
+    let db_path = Path::new("/config/database/db.sqlite");
+    let db = Connection::open(db_path)?;
+
+    db.execute(
+        "INSERT OR REPLACE INTO role_clip_lengths (guild_id, role_id, seconds)
+            VALUES (?1, ?2, ?3)",
+        params![guild_id.get() as i64, role_id.get() as i64, seconds],
+    )?;
+
+    Ok(())
+}
+
+/// Removes the clip length of a role, returns whether it had one.
+pub fn remove_role_clip_length(guild_id: GuildId, role_id: RoleId) -> Result<bool, rusqlite::Error> {
+    let db_path = Path::new("/config/database/db.sqlite");
+    let db = Connection::open(db_path)?;
+
+    let removed = db.execute(
+        "DELETE FROM role_clip_lengths WHERE guild_id=?1 AND role_id=?2",
+        params![guild_id.get() as i64, role_id.get() as i64],
+    )?;
+
+    Ok(removed > 0)
+}
+
+/// Message for submitters whose clip is longer than the limit that applies to them.
+pub fn too_long_message(limit: f64) -> String {
+    format!("Duration is too long, clips can be at most {} seconds long for you", limit)
+}
@@ -0,0 +1,194 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+use serenity::model::id::GuildId;
+use std::collections::HashMap;
+
+use crate::util::settings::{get_setting, LOUDNESS_TARGET, TRUE_PEAK_TARGET};
+
+/// Integrated loudness in LUFS clips are normalised to, the loudnorm default.
+pub const DEFAULT_INTEGRATED_LOUDNESS: f64 = -24.0;
+/// Maximum true peak in dBTP, the loudnorm default.
+pub const DEFAULT_TRUE_PEAK: f64 = -2.0;
+/// Loudness range in LU, short clips barely have one so it is not configurable.
+const LOUDNESS_RANGE: f64 = 11.0;
+
+// tags of processed clips with the values measured in the first pass
+const MEASURED_I_TAG: &str = "loudness_measured_i";
+const MEASURED_TP_TAG: &str = "loudness_measured_tp";
+const MEASURED_LRA_TAG: &str = "loudness_measured_lra";
+const MEASURED_THRESH_TAG: &str = "loudness_measured_thresh";
+
+/// Loudness a clip is normalised to.
+#[derive(Clone, Copy, Debug)]
+pub struct LoudnessTarget {
+    /// Integrated loudness in LUFS.
+    pub integrated: f64,
+    /// Maximum true peak in dBTP.
+    pub true_peak: f64,
+}
+
+impl Default for LoudnessTarget {
+    fn default() -> Self {
+        LoudnessTarget {
+            integrated: DEFAULT_INTEGRATED_LOUDNESS,
+            true_peak: DEFAULT_TRUE_PEAK,
+        }
+    }
+}
+
+impl LoudnessTarget {
+    /// Filter of the first pass, prints the measured loudness as json to stderr.
+    pub fn measure_filter(&self) -> String {
+        format!(
+            "loudnorm=I={}:TP={}:LRA={}:print_format=json",
+            self.integrated, self.true_peak, LOUDNESS_RANGE
+        )
+    }
+
+    /// Filter of the second pass, normalises linearly with the measured values so short clips don't pump.
+    pub fn apply_filter(&self, measurement: &LoudnessMeasurement) -> String {
+        format!(
+            "loudnorm=I={}:TP={}:LRA={}:measured_I={}:measured_TP={}:measured_LRA={}:measured_thresh={}:offset={}:linear=true",
+            self.integrated,
+            self.true_peak,
+            LOUDNESS_RANGE,
+            measurement.integrated,
+            measurement.true_peak,
+            measurement.range,
+            measurement.threshold,
+            measurement.offset
+        )
+    }
+}
+
+/// Loudness of a clip as measured by the first loudnorm pass.
+#[derive(Clone, Copy, Debug)]
+pub struct LoudnessMeasurement {
+    pub integrated: f64,
+    pub true_peak: f64,
+    pub range: f64,
+    pub threshold: f64,
+    pub offset: f64,
+}
+
+impl LoudnessMeasurement {
+    /// Reads the json loudnorm prints at the end of the ffmpeg output.
+    pub fn parse(output: &str) -> Result<LoudnessMeasurement, String> {
+        lazy_static! {
+            static ref RE: Regex = Regex::new(r#""(?P<key>\w+)"\s*:\s*"(?P<value>[^"]*)""#).unwrap();
+        }
+
+        let json_start = match output.rfind('{') {
+            Some(json_start) => json_start,
+            None => return Err("No loudness measurement in the ffmpeg output".to_string()),
+        };
+
+        let values = RE
+            .captures_iter(&output[json_start..])
+            .map(|captures| (captures["key"].to_string(), captures["value"].to_string()))
+            .collect::<HashMap<_, _>>();
+
+        let value = |key: &str| -> Result<f64, String> {
+            match values.get(key).map(|value| value.parse::<f64>()) {
+                Some(Ok(value)) if value.is_finite() => Ok(value),
+                // silence measures as -inf, which the second pass can't use
+                Some(_) => Err(format!("Invalid loudness measurement {}, the clip might be silent", key)),
+                None => Err(format!("Missing loudness measurement {}", key)),
+            }
+        };
+
+        Ok(LoudnessMeasurement {
+            integrated: value("input_i")?,
+            true_peak: value("input_tp")?,
+            range: value("input_lra")?,
+            threshold: value("input_thresh")?,
+            offset: value("target_offset")?,
+        })
+    }
+
+    /// Tags the measured values are stored in.
+    pub fn tags(&self) -> Vec<String> {
+        vec![
+            format!("{}={}", MEASURED_I_TAG, self.integrated),
+            format!("{}={}", MEASURED_TP_TAG, self.true_peak),
+            format!("{}={}", MEASURED_LRA_TAG, self.range),
+            format!("{}={}", MEASURED_THRESH_TAG, self.threshold),
+        ]
+    }
+}
+
+/// Loudness new clips of a guild are normalised to.
+///
+/// Clips are stored once for all guilds, so a clip keeps the target of the guild it was processed in last.
+pub fn guild_loudness_target(guild_id: GuildId) -> LoudnessTarget {
+    let setting = |key: &str, default: f64| {
+        get_setting(guild_id, key)
+            .and_then(|value| value.parse::<f64>().ok())
+            .unwrap_or(default)
+    };
+
+    LoudnessTarget {
+        integrated: setting(LOUDNESS_TARGET, DEFAULT_INTEGRATED_LOUDNESS),
+        true_peak: setting(TRUE_PEAK_TARGET, DEFAULT_TRUE_PEAK),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OUTPUT: &str = r#"
+[Parsed_loudnorm_0 @ 0x5581c0f3c9c0]
+{
+	"input_i" : "-27.61",
+	"input_tp" : "-4.47",
+	"input_lra" : "18.06",
+	"input_thresh" : "-39.20",
+	"output_i" : "-24.58",
+	"output_tp" : "-2.00",
+	"output_lra" : "7.70",
+	"output_thresh" : "-35.03",
+	"normalization_type" : "dynamic",
+	"target_offset" : "0.58"
+}
+"#;
+
+    #[test]
+    fn parses_loudnorm_output() {
+        let output = format!("size=N/A time=00:00:03.52 bitrate=N/A speed= 112x{{ignored}}\n{}", OUTPUT);
+        let measurement = LoudnessMeasurement::parse(&output).unwrap();
+        assert_eq!(measurement.integrated, -27.61);
+        assert_eq!(measurement.true_peak, -4.47);
+        assert_eq!(measurement.range, 18.06);
+        assert_eq!(measurement.threshold, -39.2);
+        assert_eq!(measurement.offset, 0.58);
+
+        assert_eq!(
+            LoudnessTarget::default().apply_filter(&measurement),
+            "loudnorm=I=-24:TP=-2:LRA=11:measured_I=-27.61:measured_TP=-4.47:measured_LRA=18.06:measured_thresh=-39.2:offset=0.58:linear=true"
+        );
+    }
+
+    #[test]
+    fn rejects_silent_clips() {
+        let silent = OUTPUT
+            .replace("\"-27.61\"", "\"-inf\"")
+            .replace("\"-4.47\"", "\"-inf\"")
+            .replace("\"-39.20\"", "\"-70.00\"");
+        let why = LoudnessMeasurement::parse(&silent).unwrap_err();
+        assert!(why.contains("silent"), "{}", why);
+
+        let nan = OUTPUT.replace("\"0.58\"", "\"nan\"");
+        assert!(LoudnessMeasurement::parse(&nan).is_err());
+    }
+
+    #[test]
+    fn rejects_incomplete_output() {
+        assert!(LoudnessMeasurement::parse("").is_err());
+        assert!(LoudnessMeasurement::parse("Conversion failed!").is_err());
+
+        let missing = OUTPUT.replace("\"target_offset\" : \"0.58\"", "");
+        let why = LoudnessMeasurement::parse(&missing).unwrap_err();
+        assert!(why.contains("target_offset"), "{}", why);
+    }
+}
@@ -0,0 +1,460 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    future::Future,
+    io::Write,
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+use symphonia::core::{
+    codecs::CODEC_TYPE_NULL,
+    formats::FormatOptions,
+    io::MediaSourceStream,
+    meta::MetadataOptions,
+    probe::Hint,
+};
+use tracing::debug;
+use url::{Host, Url};
+
+/// Largest attachment that is downloaded, in bytes.
+pub const MAX_ATTACHMENT_SIZE: u32 = 25 * 1024 * 1024;
+/// Longest audio that is accepted for processing, in seconds.
+const MAX_MEDIA_DURATION: f64 = 600.0;
+/// Most audio channels that are accepted for processing.
+const MAX_MEDIA_CHANNELS: usize = 8;
+/// File extensions of links that ffmpeg can download directly.
+const DIRECT_MEDIA_EXTENSIONS: &[&str] = &[
+    "aac", "flac", "m4a", "mkv", "mov", "mp3", "mp4", "oga", "ogg", "opus", "wav", "weba", "webm",
+];
+/// Time to wait for the content type of a link.
+const DIRECT_MEDIA_TIMEOUT: Duration = Duration::from_secs(10);
+/// Largest file that is downloaded from a link, in bytes.
+const MAX_DOWNLOAD_SIZE: u64 = 200 * 1024 * 1024;
+/// Most redirects that are followed when downloading a link.
+const MAX_REDIRECTS: usize = 5;
+
+/// Properties of the audio track of a file, as far as the container tells them without decoding.
+#[derive(Clone, Copy, Debug)]
+pub struct MediaInfo {
+    pub duration: Option<f64>,
+    pub channels: Option<usize>,
+}
+
+/// Sniffs the content of a file and returns the first audio track symphonia can decode.
+pub fn probe_audio(path: &str) -> Result<MediaInfo, String> {
+    let file = File::open(path).map_err(|why| why.to_string())?;
+    let source = MediaSourceStream::new(Box::new(file), Default::default());
+
+    let probed = symphonia::default::get_probe()
+        .format(&Hint::new(), source, &FormatOptions::default(), &MetadataOptions::default())
+        .map_err(|why| why.to_string())?;
+
+    let codecs = symphonia::default::get_codecs();
+    let track = match probed
+        .format
+        .tracks()
+        .iter()
+        .find(|track| track.codec_params.codec != CODEC_TYPE_NULL && codecs.get_codec(track.codec_params.codec).is_some())
+    {
+        Some(track) => track,
+        None => return Err("No supported audio track".to_string()),
+    };
+
+    let params = &track.codec_params;
+    let duration = match (params.time_base, params.n_frames, params.sample_rate) {
+        (Some(time_base), Some(n_frames), _) => {
+            let time = time_base.calc_time(n_frames);
+            Some(time.seconds as f64 + time.frac)
+        }
+        (None, Some(n_frames), Some(sample_rate)) => Some(n_frames as f64 / sample_rate as f64),
+        _ => None,
+    };
+
+    Ok(MediaInfo {
+        duration,
+        channels: params.channels.map(|channels| channels.count()),
+    })
+}
+
+/// Reads the tags of a file, keys are lower case.
+pub fn read_tags(path: &str) -> HashMap<String, String> {
+    let mut tags = HashMap::new();
+
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(why) => {
+            debug!("Failed to open {} to read tags: {}", path, why);
+            return tags;
+        }
+    };
+    let source = MediaSourceStream::new(Box::new(file), Default::default());
+
+    let mut probed = match symphonia::default::get_probe()
+        .format(&Hint::new(), source, &FormatOptions::default(), &MetadataOptions::default())
+    {
+        Ok(probed) => probed,
+        Err(why) => {
+            debug!("Failed to probe {} to read tags: {}", path, why);
+            return tags;
+        }
+    };
+
+    if let Some(revision) = probed.format.metadata().skip_to_latest() {
+        for tag in revision.tags() {
+            tags.insert(tag.key.to_lowercase(), tag.value.to_string());
+        }
+    }
+
+    tags
+}
+
+/// Checks that the audio can be processed, returns a message for the submitter otherwise.
+pub fn check_media(info: &MediaInfo) -> Result<(), String> {
+    if info.duration.is_some_and(|duration| duration > MAX_MEDIA_DURATION) {
+        return Err(format!(
+            "The file is too long, files can be at most {} minutes long",
+            MAX_MEDIA_DURATION / 60.0
+        ));
+    }
+
+    if info.duration.is_some_and(|duration| duration <= 0.0) {
+        return Err("The file doesn't contain any audio".to_string());
+    }
+
+    if info.channels.is_some_and(|channels| channels > MAX_MEDIA_CHANNELS) {
+        return Err(format!(
+            "The file has too many audio channels, files can have at most {}",
+            MAX_MEDIA_CHANNELS
+        ));
+    }
+
+    Ok(())
+}
+
+/// Checks if a url links to an audio or video file instead of a page youtube-dl has to extract it from.
+///
+/// Only call this for urls that passed [`check_public_url`], the content type is requested from the host.
+pub async fn is_direct_media(url: &Url) -> bool {
+    let extension = url
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .and_then(|filename| filename.rsplit_once('.'))
+        .map(|(_, extension)| extension.to_lowercase());
+
+    if extension.is_some_and(|extension| DIRECT_MEDIA_EXTENSIONS.contains(&extension.as_str())) {
+        return true;
+    }
+
+    has_media_content_type(url).await
+}
+
+/// Asks the host for the content type of a url, redirects aren't followed as they could lead to internal hosts.
+async fn has_media_content_type(url: &Url) -> bool {
+    let client = match reqwest::Client::builder()
+        .timeout(DIRECT_MEDIA_TIMEOUT)
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+    {
+        Ok(client) => client,
+        Err(why) => {
+            debug!("Failed to create http client: {}", why);
+            return false;
+        }
+    };
+
+    match client.head(url.as_str()).send().await {
+        Ok(response) => response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok())
+            .is_some_and(|content_type| {
+                content_type.starts_with("audio/")
+                    || content_type.starts_with("video/")
+                    || content_type.starts_with("application/ogg")
+            }),
+        Err(why) => {
+            debug!("Failed to check content type of {}: {}", url, why);
+            false
+        }
+    }
+}
+
+/// Checks that a url only resolves to public addresses, so submissions can't make the bot reach internal services.
+pub async fn check_public_url(url: &Url) -> Result<(), String> {
+    resolve_public_url(url).await.map(|_| ())
+}
+
+/// Resolves the host of a url like [`check_public_url`], returns the addresses so connections only go to checked ones.
+async fn resolve_public_url(url: &Url) -> Result<Vec<SocketAddr>, String> {
+    let port = url.port_or_known_default().unwrap_or(80);
+    let addresses = match url.host() {
+        Some(Host::Ipv4(ip)) => vec![IpAddr::V4(ip)],
+        Some(Host::Ipv6(ip)) => vec![IpAddr::V6(ip)],
+        Some(Host::Domain(domain)) => match tokio::net::lookup_host((domain, port)).await {
+            Ok(addresses) => addresses.map(|address| address.ip()).collect(),
+            Err(why) => {
+                debug!("Failed to resolve {}: {}", domain, why);
+                return Err(format!("Failed to resolve {}", domain));
+            }
+        },
+        None => return Err("The url has no host".to_string()),
+    };
+
+    if addresses.is_empty() {
+        return Err("The host of the url has no address".to_string());
+    }
+    if let Some(address) = addresses.iter().find(|address| !is_public_ip(**address)) {
+        debug!("Rejected {} resolving to {}", url, address);
+        return Err("Links to local or private addresses aren't allowed".to_string());
+    }
+
+    Ok(addresses.into_iter().map(|address| SocketAddr::new(address, port)).collect())
+}
+
+/// Downloads a link to a file, so ffmpeg never has to fetch anything from the internet itself.
+///
+/// Every redirect is checked with [`check_public_url`] and connections only go to the addresses that were checked,
+/// a host can't answer the check with a public address and the download with an internal one.
+pub async fn download_public_url(url: &Url, path: &str) -> Result<(), String> {
+    download_checked(url, path, |url| async move { resolve_public_url(&url).await }).await
+}
+
+async fn download_checked<F, Fut>(url: &Url, path: &str, resolve: F) -> Result<(), String>
+where
+    F: Fn(Url) -> Fut,
+    Fut: Future<Output = Result<Vec<SocketAddr>, String>>,
+{
+    let mut url = url.clone();
+
+    for _ in 0..=MAX_REDIRECTS {
+        if !["http", "https"].contains(&url.scheme()) {
+            return Err(format!("The link leads to an unsupported {} url", url.scheme()));
+        }
+
+        let addresses = resolve(url.clone()).await?;
+        let mut client = reqwest::Client::builder().redirect(reqwest::redirect::Policy::none()).no_proxy();
+        if let Some(Host::Domain(domain)) = url.host() {
+            client = client.resolve_to_addrs(domain, &addresses);
+        }
+        let client = client.build().map_err(|why| why.to_string())?;
+
+        let mut response = match client.get(url.as_str()).send().await {
+            Ok(response) => response,
+            Err(why) => {
+                debug!("Failed to download {}: {}", url, why);
+                return Err("Failed to download the link".to_string());
+            }
+        };
+
+        if response.status().is_redirection() {
+            let location = response
+                .headers()
+                .get(reqwest::header::LOCATION)
+                .and_then(|location| location.to_str().ok())
+                .ok_or("The link redirects without a location")?;
+            url = url.join(location).map_err(|why| why.to_string())?;
+            debug!("Following redirect to {}", url);
+            continue;
+        }
+
+        if !response.status().is_success() {
+            return Err(format!("The link returned {}", response.status()));
+        }
+
+        let too_large = format!("The file is too large, files can be at most {} MB", MAX_DOWNLOAD_SIZE / 1024 / 1024);
+        if response.content_length().is_some_and(|length| length > MAX_DOWNLOAD_SIZE) {
+            return Err(too_large);
+        }
+
+        let mut file = File::create(path).map_err(|why| why.to_string())?;
+        let mut size = 0;
+        let download_res = loop {
+            match response.chunk().await {
+                Ok(Some(chunk)) => {
+                    size += chunk.len() as u64;
+                    if size > MAX_DOWNLOAD_SIZE {
+                        break Err(too_large);
+                    }
+                    if let Err(why) = file.write_all(&chunk) {
+                        break Err(why.to_string());
+                    }
+                }
+                Ok(None) => break Ok(()),
+                Err(why) => {
+                    debug!("Failed to download {}: {}", url, why);
+                    break Err("Failed to download the link".to_string());
+                }
+            }
+        };
+
+        if download_res.is_err() {
+            let _ = fs::remove_file(path);
+        }
+        return download_res;
+    }
+
+    Err("The link redirects too often".to_string())
+}
+
+/// Whether an address is reachable on the internet, loopback, private, link-local and similar ranges are not.
+fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [first, second, ..] = ip.octets();
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                || first == 0
+                // shared address space of carrier-grade NAT
+                || (first == 100 && (second & 0b1100_0000) == 64))
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_ip(IpAddr::V4(ip)),
+            None => !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_multicast()
+                || ip.is_unique_local()
+                || ip.is_unicast_link_local()),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        thread,
+    };
+
+    /// Serves a single request with the given content type on a local port, returns the url to request.
+    fn serve_once(content_type: &'static str) -> Url {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request);
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                content_type
+            );
+            let _ = stream.write_all(response.as_bytes());
+        });
+
+        Url::parse(&format!("http://{}/download", address)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn detects_media_by_content_type() {
+        assert!(has_media_content_type(&serve_once("audio/mpeg")).await);
+        assert!(has_media_content_type(&serve_once("video/mp4")).await);
+        assert!(has_media_content_type(&serve_once("application/ogg")).await);
+        assert!(!has_media_content_type(&serve_once("text/html; charset=utf-8")).await);
+    }
+
+    #[tokio::test]
+    async fn detects_media_by_extension() {
+        assert!(is_direct_media(&Url::parse("https://example.com/sounds/horn.MP3?x=1").unwrap()).await);
+        assert!(is_direct_media(&Url::parse("https://example.com/a/b/clip.ogg").unwrap()).await);
+    }
+
+    #[tokio::test]
+    async fn rejects_local_and_private_hosts() {
+        for url in [
+            "http://127.0.0.1/a.mp3",
+            "http://localhost:8080/a.mp3",
+            "http://10.1.2.3/a.mp3",
+            "http://172.16.0.1/a.mp3",
+            "http://192.168.1.1/a.mp3",
+            "http://169.254.169.254/latest/meta-data",
+            "http://100.64.0.1/a.mp3",
+            "http://0.0.0.0/a.mp3",
+            "http://[::1]/a.mp3",
+            "http://[fe80::1]/a.mp3",
+            "http://[fd00::1]/a.mp3",
+            "http://[::ffff:127.0.0.1]/a.mp3",
+        ] {
+            assert!(check_public_url(&Url::parse(url).unwrap()).await.is_err(), "{} was allowed", url);
+        }
+    }
+
+    /// Answers every request on a local port with a redirect to `location`, returns the url to request.
+    fn redirect_forever(location: String) -> Url {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request = [0; 1024];
+                let _ = stream.read(&mut request);
+                let response = format!(
+                    "HTTP/1.1 302 Found\r\nLocation: {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    location
+                );
+                let _ = stream.write_all(response.as_bytes());
+            }
+        });
+
+        Url::parse(&format!("http://{}/clip.mp3", address)).unwrap()
+    }
+
+    /// Treats the local test server as public, every other host is checked like a real download.
+    async fn resolve_test_server(url: Url, server: &Url) -> Result<Vec<SocketAddr>, String> {
+        if url.port() == server.port() {
+            return Ok(vec![SocketAddr::new(IpAddr::from([127, 0, 0, 1]), url.port().unwrap())]);
+        }
+        resolve_public_url(&url).await
+    }
+
+    #[tokio::test]
+    async fn rejects_redirects_to_private_hosts() {
+        let internal = TcpListener::bind("127.0.0.1:0").unwrap();
+        internal.set_nonblocking(true).unwrap();
+        let server = redirect_forever(format!("http://{}/secret", internal.local_addr().unwrap()));
+        let path = std::env::temp_dir().join(format!("announcer-redirect-{}.download", std::process::id()));
+
+        let download_res = download_checked(&server, path.to_str().unwrap(), |url| resolve_test_server(url, &server)).await;
+
+        assert_eq!(download_res, Err("Links to local or private addresses aren't allowed".to_string()));
+        assert!(internal.accept().is_err(), "the private host was contacted");
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn limits_redirects() {
+        let server = redirect_forever("/again".to_string());
+        let path = std::env::temp_dir().join(format!("announcer-redirects-{}.download", std::process::id()));
+
+        let download_res = download_checked(&server, path.to_str().unwrap(), |url| resolve_test_server(url, &server)).await;
+
+        assert_eq!(download_res, Err("The link redirects too often".to_string()));
+    }
+
+    #[tokio::test]
+    async fn downloads_public_links() {
+        let server = serve_once("audio/mpeg");
+        let path = std::env::temp_dir().join(format!("announcer-download-{}.download", std::process::id()));
+
+        let download_res = download_checked(&server, path.to_str().unwrap(), |url| resolve_test_server(url, &server)).await;
+        let exists = path.exists();
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(download_res, Ok(()));
+        assert!(exists);
+    }
+
+    #[tokio::test]
+    async fn allows_public_addresses() {
+        for url in ["http://93.184.215.14/a.mp3", "https://[2606:4700:4700::1111]/a.mp3"] {
+            assert!(check_public_url(&Url::parse(url).unwrap()).await.is_ok(), "{} was rejected", url);
+        }
+    }
+}
@@ -1,14 +1,26 @@
 use serenity::all::{ButtonStyle, CreateActionRow, CreateButton};
 
-use crate::util::component_ids::{LIST_NEXT_BUTTON, LIST_PREV_BUTTON};
+use crate::util::component_ids::{
+    DELETE_CANCEL_BUTTON,
+    DELETE_CONFIRM_BUTTON,
+    DUPLICATE_UPLOAD_ANYWAY_BUTTON,
+    DUPLICATE_USE_EXISTING_BUTTON,
+    HISTORY_REPLAY_BUTTON,
+    LIST_NEXT_BUTTON,
+    LIST_PREV_BUTTON,
+    PREVIEW_CONFIRM_BUTTON,
+    PREVIEW_DISCARD_BUTTON,
+    PREVIEW_PLAY_BUTTON,
+    PREVIEW_RETRIM_BUTTON,
+    SEARCH_COPY_BUTTON,
+    SEARCH_PLAY_BUTTON
+};
 
 pub fn create_button(id: &str, label: &str, enabled: bool) -> CreateButton {
-    let button = CreateButton::new(id)
+    CreateButton::new(id)
         .label(label)
         .style(ButtonStyle::Secondary)
-        .disabled(!enabled);
-
-    return button;
+        .disabled(!enabled)
 }
 
 pub fn create_navigation_buttons(prev: bool, next: bool) -> Vec<CreateActionRow> {
@@ -16,4 +28,56 @@ pub fn create_navigation_buttons(prev: bool, next: bool) -> Vec<CreateActionRow>
         create_button(LIST_PREV_BUTTON, "Previous", prev),
         create_button(LIST_NEXT_BUTTON, "Next", next)
     ])]
-}
\ No newline at end of file
+}
+
+pub fn create_preview_buttons() -> Vec<CreateActionRow> {
+    vec![CreateActionRow::Buttons(vec![
+        create_button(PREVIEW_PLAY_BUTTON, "Play", true).style(ButtonStyle::Primary),
+        create_button(PREVIEW_CONFIRM_BUTTON, "Confirm", true).style(ButtonStyle::Success),
+        create_button(PREVIEW_RETRIM_BUTTON, "Re-trim", true),
+        create_button(PREVIEW_DISCARD_BUTTON, "Discard", true).style(ButtonStyle::Danger)
+    ])]
+}
+
+pub fn create_duplicate_buttons() -> Vec<CreateActionRow> {
+    vec![CreateActionRow::Buttons(vec![
+        create_button(DUPLICATE_USE_EXISTING_BUTTON, "Use existing", true).style(ButtonStyle::Primary),
+        create_button(DUPLICATE_UPLOAD_ANYWAY_BUTTON, "Upload anyway", true)
+    ])]
+}
+
+pub fn create_delete_buttons() -> Vec<CreateActionRow> {
+    vec![CreateActionRow::Buttons(vec![
+        create_button(DELETE_CONFIRM_BUTTON, "Delete", true).style(ButtonStyle::Danger),
+        create_button(DELETE_CANCEL_BUTTON, "Cancel", true)
+    ])]
+}
+
+/// Navigation plus a play and a copy button for each result on the page, the ids end with `:<result index>`.
+pub fn create_search_buttons(first: usize, count: usize, prev: bool, next: bool) -> Vec<CreateActionRow> {
+    let play_buttons = (first..first + count)
+        .map(|index| create_button(&format!("{}:{}", SEARCH_PLAY_BUTTON, index), &format!("Play {}", index + 1), true)
+            .style(ButtonStyle::Primary))
+        .collect();
+    let copy_buttons = (first..first + count)
+        .map(|index| create_button(&format!("{}:{}", SEARCH_COPY_BUTTON, index), &format!("Copy {}", index + 1), true))
+        .collect();
+
+    let mut rows = vec![CreateActionRow::Buttons(play_buttons), CreateActionRow::Buttons(copy_buttons)];
+    rows.extend(create_navigation_buttons(prev, next));
+    rows
+}
+
+/// A play-again button for each history entry, five per row, the ids end with `:<entry index>`.
+pub fn create_history_buttons(count: usize) -> Vec<CreateActionRow> {
+    (0..count)
+        .collect::<Vec<_>>()
+        .chunks(5)
+        .map(|indices| {
+            CreateActionRow::Buttons(indices
+                .iter()
+                .map(|index| create_button(&format!("{}:{}", HISTORY_REPLAY_BUTTON, index), &format!("Replay {}", index + 1), true))
+                .collect())
+        })
+        .collect()
+}
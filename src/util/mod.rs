@@ -1,6 +1,23 @@
+pub mod autocomplete;
+pub mod batch;
 pub mod check;
 pub mod component_ids;
 pub mod consts;
+pub mod filters;
+pub mod fingerprint;
+pub mod jobs;
+pub mod limits;
+pub mod loudness;
+pub mod media;
 pub mod messages;
+pub mod originals;
 pub mod parse;
+pub mod paths;
+pub mod playback;
+pub mod presets;
+pub mod reconcile;
+pub mod recording;
+pub mod settings;
+pub mod submissions;
+#[allow(clippy::module_inception)]
 pub mod util;
@@ -0,0 +1,131 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+use tracing::{error, info};
+
+use crate::util::paths::encode_segment;
+
+/// Total size the kept originals may use, the least recently changed ones are removed first.
+const ORIGINALS_STORAGE_BUDGET: u64 = 2 * 1024 * 1024 * 1024;
+
+/// Path of the original download a clip was processed from.
+pub fn original_path(name: &str, announcement_name: &str) -> String {
+    format!("/config/originals/{}/{}.source", encode_segment(name), encode_segment(announcement_name))
+}
+
+/// Moves the original download of a clip into the originals, then enforces the storage budget.
+pub fn keep_original(name: &str, announcement_name: &str, source_path: &str) -> Result<(), String> {
+    let kept_path = original_path(name, announcement_name);
+
+    if let Some(name_path) = Path::new(&kept_path).parent() {
+        if let Err(why) = fs::create_dir_all(name_path) {
+            error!("Failed to create directory {}: {}", name_path.display(), why);
+            return Err("Failed to create directory".to_string());
+        }
+    }
+
+    // fall back to copying if the directories are on different mounts
+    if fs::rename(source_path, &kept_path).is_err() {
+        if let Err(why) = fs::copy(source_path, &kept_path) {
+            error!("Failed to keep original {}: {}", source_path, why);
+            return Err(format!("Failed to keep original {}", source_path));
+        }
+        let _ = fs::remove_file(source_path);
+    }
+
+    enforce_storage_budget(Path::new(&kept_path));
+
+    Ok(())
+}
+
+/// Removes the original of a deleted clip, together with the directory of the name once it is empty.
+pub fn remove_original(name: &str, announcement_name: &str) {
+    let kept_path = original_path(name, announcement_name);
+
+    match fs::remove_file(&kept_path) {
+        Ok(_) => {
+            if let Some(name_path) = Path::new(&kept_path).parent() {
+                let _ = fs::remove_dir(name_path);
+            }
+        }
+        Err(why) if why.kind() != std::io::ErrorKind::NotFound => {
+            error!("Failed to remove original {}: {}", &kept_path, why);
+        }
+        Err(_) => (),
+    }
+}
+
+/// Moves the original of a renamed clip along, an original that is already stored under the new name is kept.
+pub fn rename_original(name: &str, announcement_name: &str, new_announcement_name: &str) {
+    let kept_path = original_path(name, announcement_name);
+    let new_kept_path = original_path(name, new_announcement_name);
+
+    if !Path::new(&kept_path).exists() {
+        return;
+    }
+
+    // linking fails instead of replacing an existing file
+    if let Err(why) = fs::hard_link(&kept_path, &new_kept_path) {
+        error!("Failed to move original {} to {}: {}", &kept_path, &new_kept_path, why);
+        return;
+    }
+    if let Err(why) = fs::remove_file(&kept_path) {
+        error!("Failed to remove original {}: {}", &kept_path, why);
+    }
+}
+
+/// Removes the oldest originals until they fit into the storage budget, `keep` is never removed.
+fn enforce_storage_budget(keep: &Path) {
+    let mut originals = list_originals();
+    let mut total_size = originals.iter().map(|(_, size, _)| size).sum::<u64>();
+
+    originals.sort_by_key(|(_, _, modified)| *modified);
+
+    for (path, size, _) in originals {
+        if total_size <= ORIGINALS_STORAGE_BUDGET {
+            break;
+        }
+        if path == keep {
+            continue;
+        }
+
+        match fs::remove_file(&path) {
+            Ok(_) => {
+                info!("Removed original {} to stay within the storage budget", path.display());
+                total_size -= size;
+            }
+            Err(why) => error!("Failed to remove original {}: {}", path.display(), why),
+        }
+    }
+}
+
+/// All kept originals with their size and modification time.
+fn list_originals() -> Vec<(PathBuf, u64, SystemTime)> {
+    let mut originals = vec![];
+
+    let name_dirs = match fs::read_dir("/config/originals") {
+        Ok(name_dirs) => name_dirs,
+        Err(why) => {
+            error!("Failed to read originals: {}", why);
+            return originals;
+        }
+    };
+
+    for name_dir in name_dirs.flatten() {
+        let entries = match fs::read_dir(name_dir.path()) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+
+        for entry in entries.flatten() {
+            if let Ok(metadata) = entry.metadata() {
+                let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                originals.push((entry.path(), metadata.len(), modified));
+            }
+        }
+    }
+
+    originals
+}
@@ -1,41 +1,51 @@
 use lazy_static::lazy_static;
 use regex::Regex;
 use std::time::Duration;
+use url::Url;
+
+/// Latest time that is accepted for a start, an end or a duration, anything later is a typo or an attack.
+pub const MAX_TIMESTAMP: Duration = Duration::from_secs(24 * 60 * 60);
 
 pub fn parse_duration(input: &str) -> Result<Duration, &str> {
     lazy_static! {
         static ref RE: Regex = Regex::new(r"(?x)
             -?(?P<hours>[0-9][0-9])?:?
             (?P<minutes>[0-5][0-9]):
-            (?P<seconds>[0-5]?[0-9])(?:.[0-9]+)?
+            (?P<seconds>[0-5]?[0-9])(?:\.(?P<fraction>[0-9]+))?
             |
-            -?(?P<value>[0-9]+)(?:.[0-9]+)?
+            -?(?P<value>[0-9]+)(?:\.(?P<value_fraction>[0-9]+))?
             (?P<unit>ms|us)?
         ").unwrap();
     }
     
-    let captures = RE.captures(input).unwrap();
-
-    let hours = match captures.name("hours") {
-        Some(hours) => hours.as_str().parse::<u64>().unwrap(),
-        None => 0
+    let captures = match RE.captures(input) {
+        Some(captures) => captures,
+        None => return Err("Invalid duration"),
     };
-    let minutes = match captures.name("minutes") {
-        Some(minutes) => minutes.as_str().parse::<u64>().unwrap(),
-        None => 0
-    };
-    let mut seconds = match captures.name("seconds") {
-        Some(seconds) => seconds.as_str().parse::<u64>().unwrap(),
-        None => 0
+
+    // digits that don't fit are rejected instead of panicking, durations are user input
+    let number = |name: &str| match captures.name(name) {
+        Some(number) => number.as_str().parse::<u64>().map_err(|_| "Invalid duration"),
+        None => Ok(0),
     };
-    let value = match captures.name("value") {
-        Some(value) => value.as_str().parse::<u64>().unwrap(),
-        None => 0
+    let hours = number("hours")?;
+    let minutes = number("minutes")?;
+    let mut seconds = number("seconds")?;
+    let value = number("value")?;
+    // fractions are kept to the millisecond for seconds and to the microsecond for milliseconds
+    let fraction = |name: &str| match captures.name(name) {
+        Some(fraction) => format!("0.{}", fraction.as_str()).parse::<f64>().map_err(|_| "Invalid duration"),
+        None => Ok(0.0),
     };
+    let mut fraction_millis = (fraction("fraction")? * 1000.0).round() as u64;
+    let value_fraction = fraction("value_fraction")?;
     let unit = match captures.name("unit") {
         Some(unit) => unit.as_str(),
         None => {
-            seconds = value;
+            if captures.name("value").is_some() {
+                seconds = value;
+                fraction_millis = (value_fraction * 1000.0).round() as u64;
+            }
             ""
         }
     };
@@ -45,10 +55,113 @@ pub fn parse_duration(input: &str) -> Result<Duration, &str> {
     if unit == "us" {
         duration = Duration::from_micros(value);
     } else if unit == "ms" {
-        duration = Duration::from_millis(value);
+        duration = Duration::from_millis(value) + Duration::from_micros((value_fraction * 1000.0).round() as u64);
     } else {
-        duration = Duration::from_secs(hours * 3600 + minutes * 60 + seconds);
+        duration = Duration::from_secs(hours * 3600 + minutes * 60 + seconds) + Duration::from_millis(fraction_millis);
+    }
+
+    if duration > MAX_TIMESTAMP {
+        return Err("Duration is too long");
     }
     
-    return Ok(duration);
-}
\ No newline at end of file
+    Ok(duration)
+}
+
+/// Reads the start time of a url, either from the `t` query parameter (`?t=1m20s`) or the fragment (`#t=80`).
+pub fn parse_url_timestamp(url: &Url) -> Option<Duration> {
+    let query_timestamp = url
+        .query_pairs()
+        .find(|(key, _)| key == "t" || key == "start")
+        .map(|(_, value)| value.into_owned());
+
+    let fragment_timestamp = url.fragment().and_then(|fragment| {
+        fragment
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("t="))
+            .map(|value| value.split(',').next().unwrap_or(value).to_string())
+    });
+
+    query_timestamp
+        .or(fragment_timestamp)
+        .and_then(|timestamp| parse_timestamp(&timestamp))
+}
+
+/// Parses timestamps like `80`, `80s`, `1m20s`, `1h2m3s` and `01:20`.
+fn parse_timestamp(input: &str) -> Option<Duration> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"^(?:(?P<hours>[0-9]+)h)?(?:(?P<minutes>[0-9]+)m)?(?:(?P<seconds>[0-9]+(?:\.[0-9]+)?)s?)?$").unwrap();
+    }
+
+    let input = input.trim();
+    if input.is_empty() {
+        return None;
+    }
+
+    let captures = match RE.captures(input) {
+        Some(captures) => captures,
+        None => return parse_duration(input).ok(),
+    };
+
+    let value = |name: &str| captures.name(name).and_then(|value| value.as_str().parse::<f64>().ok()).unwrap_or(0.0);
+    let seconds = value("hours") * 3600.0 + value("minutes") * 60.0 + value("seconds");
+
+    // huge values overflow to infinity, which `Duration` can't hold
+    Duration::try_from_secs_f64(seconds)
+        .ok()
+        .filter(|duration| *duration <= MAX_TIMESTAMP)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url_timestamp(url: &str) -> Option<Duration> {
+        parse_url_timestamp(&Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("00:06"), Ok(Duration::from_secs(6)));
+        assert_eq!(parse_duration("01:20"), Ok(Duration::from_secs(80)));
+        assert_eq!(parse_duration("01:02:03"), Ok(Duration::from_secs(3723)));
+        assert_eq!(parse_duration("80"), Ok(Duration::from_secs(80)));
+        assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        assert!(parse_duration("abc").is_err());
+    }
+
+    #[test]
+    fn keeps_fractional_seconds() {
+        assert_eq!(parse_duration("02:20.5"), Ok(Duration::from_millis(140_500)));
+        assert_eq!(parse_duration("01:02:03.25"), Ok(Duration::from_millis(3_723_250)));
+        assert_eq!(parse_duration("1.5"), Ok(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("0.0004"), Ok(Duration::ZERO));
+        assert_eq!(parse_duration("2.5ms"), Ok(Duration::from_micros(2500)));
+    }
+
+    #[test]
+    fn rejects_huge_durations() {
+        assert!(parse_duration("99999999999999999999999999").is_err());
+        assert!(parse_duration("18446744073709551615").is_err());
+        assert!(parse_duration("90000").is_err());
+    }
+
+    #[test]
+    fn parses_url_timestamps() {
+        assert_eq!(url_timestamp("https://youtu.be/abc?t=80"), Some(Duration::from_secs(80)));
+        assert_eq!(url_timestamp("https://youtu.be/abc?t=1m20s"), Some(Duration::from_secs(80)));
+        assert_eq!(url_timestamp("https://youtu.be/abc?t=1h2m3s"), Some(Duration::from_secs(3723)));
+        assert_eq!(url_timestamp("https://example.com/a.ogg#t=80"), Some(Duration::from_secs(80)));
+        assert_eq!(url_timestamp("https://example.com/a.ogg#t=1.5,4"), Some(Duration::from_secs_f64(1.5)));
+        assert_eq!(url_timestamp("https://example.com/a.ogg?start=01:20"), Some(Duration::from_secs(80)));
+        assert_eq!(url_timestamp("https://example.com/a.ogg"), None);
+    }
+
+    #[test]
+    fn rejects_huge_url_timestamps() {
+        let digits = "9".repeat(400);
+        assert_eq!(url_timestamp(&format!("https://youtu.be/abc?t={}", digits)), None);
+        assert_eq!(url_timestamp(&format!("https://youtu.be/abc?t={}h", digits)), None);
+        assert_eq!(url_timestamp("https://youtu.be/abc?t=100000"), None);
+        assert!(url_timestamp("https://youtu.be/abc?t=1e300").is_none_or(|start| start <= MAX_TIMESTAMP));
+    }
+}
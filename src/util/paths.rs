@@ -0,0 +1,370 @@
+use rusqlite::{params, Connection};
+use std::{fs, path::Path};
+use tracing::{error, info};
+
+use crate::util::consts::ELEMENT_LABEL_LENGTH;
+
+/// Replacement for `/` in names of older versions, it is escaped so encoded names never contain it.
+const LEGACY_SLASH: char = '⁄';
+/// Written once the paths of older versions were migrated.
+const MIGRATION_MARKER: &str = "/config/paths.migrated";
+/// Longest file name most filesystems accept, in bytes.
+const NAME_MAX: usize = 255;
+
+/// Encodes a user supplied name into a single path segment that can't escape its directory.
+///
+/// Characters that are special in paths are percent encoded per UTF-8 byte: `%`, `/`, `\`, control
+/// characters, the legacy slash replacement and a leading `.`, so `.`, `..` and hidden files can't be named.
+/// Everything else is kept, names stay readable on disk. The encoding is reversed by [`decode_segment`].
+pub fn encode_segment(name: &str) -> String {
+    let mut encoded = String::with_capacity(name.len());
+
+    for (index, c) in name.chars().enumerate() {
+        let escape = matches!(c, '%' | '/' | '\\' | LEGACY_SLASH) || c.is_control() || (index == 0 && c == '.');
+        if escape {
+            let mut buffer = [0; 4];
+            for byte in c.encode_utf8(&mut buffer).bytes() {
+                encoded.push_str(&format!("%{:02X}", byte));
+            }
+        } else {
+            encoded.push(c);
+        }
+    }
+
+    encoded
+}
+
+/// Decodes a path segment created by [`encode_segment`] back into the name, for display.
+///
+/// A `%` that isn't followed by two hex digits is kept as is.
+pub fn decode_segment(segment: &str) -> String {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = match (bytes[index], bytes.get(index + 1..index + 3)) {
+            (b'%', Some(hex)) => std::str::from_utf8(hex).ok().and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+/// Checks that a user supplied announcement name can be stored.
+///
+/// Empty names would encode to just the extension, a hidden file. The encoded file name has to fit into
+/// `NAME_MAX` bytes, escapes and multi-byte characters can exceed it with fewer characters than the label limit.
+pub fn validate_name(name: &str) -> Result<(), String> {
+    if name.trim().is_empty() {
+        return Err("Announcement names can't be empty".to_string());
+    }
+
+    if name.chars().count() > ELEMENT_LABEL_LENGTH || encode_segment(name).len() + ".flac".len() > NAME_MAX {
+        return Err("Announcement name is too long".to_string());
+    }
+
+    Ok(())
+}
+
+/// Decodes the announcement name from the path of a clip.
+pub fn decode_file_stem(path: &Path) -> Option<String> {
+    path.file_stem().map(|stem| decode_segment(&stem.to_string_lossy()))
+}
+
+/// Directory with the clips of a name.
+pub fn index_dir(name: &str) -> String {
+    format!("/config/index/{}", encode_segment(name))
+}
+
+/// Path of a clip.
+pub fn index_path(name: &str, announcement_name: &str) -> String {
+    format!("{}/{}.flac", index_dir(name), encode_segment(announcement_name))
+}
+
+/// Path of the TTS file of a name.
+pub fn tts_path(name: &str) -> String {
+    format!("/config/audio/{}.flac", encode_segment(name))
+}
+
+/// Path of the file that queues a name for TTS generation.
+pub fn queue_path(name: &str) -> String {
+    format!("/config/queue/{}", encode_segment(name))
+}
+
+/// Files of a submission while it is processed, relative to the processing directory.
+///
+/// They are keyed by the submitter and the job, so concurrent submissions of the same announcement don't clash.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProcessingFiles {
+    /// The downloaded, recorded or copied audio.
+    pub filename: String,
+    /// The audio after trimming, filtering and normalising.
+    pub processed_filename: String,
+}
+
+impl ProcessingFiles {
+    pub fn new(user_id: u64, job_id: u64) -> ProcessingFiles {
+        ProcessingFiles {
+            filename: format!("{}-{}.flac", user_id, job_id),
+            processed_filename: format!("{}-{}.processed.flac", user_id, job_id),
+        }
+    }
+}
+
+/// Renames files that older versions stored under raw names to their encoded names.
+///
+/// Runs once, a marker is written when everything was migrated. Raw names can look like encoded ones,
+/// e.g. a name containing `%2F`, so every entry that exists before the marker is treated as raw.
+pub fn migrate_legacy_paths() {
+    if Path::new(MIGRATION_MARKER).exists() {
+        return;
+    }
+
+    // names are stored as they are displayed, only the names of directories that are migrated lose their
+    // legacy slash, a ⁄ that was typed on purpose is kept
+    let legacy_names = legacy_name_dirs(Path::new("/config/index"));
+    let mut migrated = migrate_legacy_names(&legacy_names);
+
+    for dir in ["/config/index", "/config/originals"] {
+        for name_dir in migrate_entries(Path::new(dir), false, &mut migrated) {
+            migrate_entries(Path::new(&name_dir), true, &mut migrated);
+        }
+    }
+    migrate_entries(Path::new("/config/audio"), true, &mut migrated);
+    migrate_entries(Path::new("/config/queue"), false, &mut migrated);
+
+    if !migrated {
+        error!("Not all legacy paths were migrated, the migration runs again on the next start");
+        return;
+    }
+
+    if let Err(why) = fs::write(MIGRATION_MARKER, "") {
+        error!("Failed to write {}: {}", MIGRATION_MARKER, why);
+    }
+}
+
+/// Returns the raw directory names that contain the legacy slash replacement, before they are migrated.
+fn legacy_name_dirs(dir: &Path) -> Vec<String> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return vec![],
+    };
+
+    entries
+        .flatten()
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .filter(|name| name.contains(LEGACY_SLASH))
+        .collect()
+}
+
+/// Restores the slashes in the database names of the given legacy directories.
+///
+/// Runs before the directories are renamed, so it is repeated for directories that failed to migrate.
+fn migrate_legacy_names(legacy_names: &[String]) -> bool {
+    if legacy_names.is_empty() {
+        return true;
+    }
+
+    let db_path = Path::new("/config/database/db.sqlite");
+    let db = match Connection::open(db_path) {
+        Ok(db) => db,
+        Err(why) => {
+            error!("Failed to open database: {}", why);
+            return false;
+        }
+    };
+
+    let mut migrated = true;
+    for legacy_name in legacy_names {
+        if let Err(why) = db.execute(
+            "UPDATE OR IGNORE names SET name=?2 WHERE name=?1",
+            params![legacy_name, legacy_name.replace(LEGACY_SLASH, "/")],
+        ) {
+            error!("Failed to migrate legacy name {}: {}", legacy_name, why);
+            migrated = false;
+        }
+    }
+
+    migrated
+}
+
+/// Migrates the entries of a directory, returns the paths of all entries after the migration.
+///
+/// Only the stem of files is encoded if they have an extension, `migrated` is cleared if any entry failed.
+fn migrate_entries(dir: &Path, with_extension: bool, migrated: &mut bool) -> Vec<String> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return vec![],
+    };
+
+    let mut paths = vec![];
+    for entry in entries.flatten() {
+        let filename = entry.file_name().to_string_lossy().to_string();
+        let (segment, extension) = match filename.rsplit_once('.') {
+            Some((stem, extension)) if with_extension && !stem.is_empty() && entry.path().is_file() => {
+                (stem.to_string(), format!(".{}", extension))
+            }
+            _ => (filename.clone(), String::new()),
+        };
+
+        let migrated_filename = format!("{}{}", encode_segment(&segment.replace(LEGACY_SLASH, "/")), extension);
+        if migrated_filename == filename {
+            paths.push(entry.path().to_string_lossy().to_string());
+            continue;
+        }
+
+        let migrated_path = dir.join(&migrated_filename);
+        if migrated_path.exists() {
+            error!("Can't migrate {}, {} already exists", entry.path().display(), migrated_path.display());
+            paths.push(entry.path().to_string_lossy().to_string());
+            *migrated = false;
+            continue;
+        }
+
+        match fs::rename(entry.path(), &migrated_path) {
+            Ok(_) => {
+                info!("Migrated {} to {}", entry.path().display(), migrated_path.display());
+                paths.push(migrated_path.to_string_lossy().to_string());
+            }
+            Err(why) => {
+                error!("Failed to migrate {}: {}", entry.path().display(), why);
+                paths.push(entry.path().to_string_lossy().to_string());
+                *migrated = false;
+            }
+        }
+    }
+
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_traversal() {
+        assert_eq!(encode_segment(".."), "%2E.");
+        assert_eq!(encode_segment("."), "%2E");
+        assert_eq!(encode_segment(".hidden"), "%2Ehidden");
+        assert_eq!(encode_segment("../../etc/passwd"), "%2E.%2F..%2Fetc%2Fpasswd");
+        assert_eq!(encode_segment("a/b"), "a%2Fb");
+        assert_eq!(encode_segment("a\\b"), "a%5Cb");
+        assert_eq!(encode_segment("a\0b"), "a%00b");
+        assert_eq!(encode_segment("line\nbreak"), "line%0Abreak");
+        assert_eq!(encode_segment("a⁄b"), "a%E2%81%84b");
+
+        for name in ["..", ".", "../x", "a/../../b", "/etc/passwd", "a\0b", "..\\x"] {
+            let encoded = encode_segment(name);
+            assert!(!encoded.contains('/') && !encoded.contains('\\') && !encoded.contains('\0'), "{}", encoded);
+            assert!(!encoded.starts_with('.'), "{}", encoded);
+        }
+    }
+
+    #[test]
+    fn encodes_percent_signs() {
+        // a literal %2F must not decode to a slash
+        assert_eq!(encode_segment("a%2Fb"), "a%252Fb");
+        assert_eq!(decode_segment(&encode_segment("a%2Fb")), "a%2Fb");
+        assert_eq!(encode_segment("100%"), "100%25");
+    }
+
+    #[test]
+    fn keeps_unicode_readable() {
+        assert_eq!(encode_segment("Zoë"), "Zoë");
+        assert_eq!(encode_segment("e\u{301}"), "e\u{301}");
+        assert_eq!(encode_segment("\u{301}."), "\u{301}.");
+        assert_eq!(encode_segment("日本語 🎺"), "日本語 🎺");
+        // only the first character can make a hidden file
+        assert_eq!(encode_segment("a.b."), "a.b.");
+    }
+
+    #[test]
+    fn decodes_what_it_encodes() {
+        for name in ["..", ".hidden", "a/b", "a%2Fb", "%", "%%41", "a\0b", "e\u{301}", "⁄", "日本語 🎺", "\u{202E}txt.exe"] {
+            assert_eq!(decode_segment(&encode_segment(name)), name);
+        }
+        // invalid escapes are kept
+        assert_eq!(decode_segment("%"), "%");
+        assert_eq!(decode_segment("%G1"), "%G1");
+        assert_eq!(decode_segment("%4"), "%4");
+    }
+
+    #[test]
+    fn keeps_paths_in_their_directory() {
+        assert_eq!(index_path("..", ".."), "/config/index/%2E./%2E..flac");
+        assert_eq!(index_path("a/b", "../c"), "/config/index/a%2Fb/%2E.%2Fc.flac");
+        assert_eq!(tts_path("../../etc/passwd"), "/config/audio/%2E.%2F..%2Fetc%2Fpasswd.flac");
+        assert_eq!(queue_path("."), "/config/queue/%2E");
+        assert_eq!(decode_file_stem(Path::new(&index_path("a", "../c"))).as_deref(), Some("../c"));
+    }
+
+    #[test]
+    fn rejects_empty_names() {
+        assert!(validate_name("").is_err());
+        assert!(validate_name("   ").is_err());
+        assert!(validate_name(&"a".repeat(ELEMENT_LABEL_LENGTH + 1)).is_err());
+        assert!(validate_name(&"ä".repeat(ELEMENT_LABEL_LENGTH)).is_ok());
+        assert!(validate_name("..").is_ok());
+    }
+
+    #[test]
+    fn bounds_encoded_names() {
+        // 100 emoji are within the label limit but take 400 bytes
+        assert!(validate_name(&"🎺".repeat(ELEMENT_LABEL_LENGTH)).is_err());
+        // every % takes three bytes once escaped
+        assert!(validate_name(&"%".repeat(84)).is_err());
+        assert!(validate_name(&"%".repeat(83)).is_ok());
+        assert!(validate_name(&"🎺".repeat(62)).is_ok());
+    }
+
+    #[test]
+    fn finds_legacy_name_dirs() {
+        let dir = std::env::temp_dir().join(format!("announcer-legacy-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for name in ["a⁄b", "plain", "a%E2%81%84b"] {
+            fs::create_dir_all(dir.join(name)).unwrap();
+        }
+
+        // an encoded ⁄ was typed on purpose and isn't a legacy slash
+        assert_eq!(legacy_name_dirs(&dir), ["a⁄b"]);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn migrates_raw_names() {
+        let dir = std::env::temp_dir().join(format!("announcer-paths-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for filename in ["plain.flac", "a⁄b.flac", "100%2F.flac", ".hidden.flac", "v1.2.flac"] {
+            fs::write(dir.join(filename), b"").unwrap();
+        }
+
+        let mut migrated = true;
+        let paths = migrate_entries(&dir, true, &mut migrated);
+        assert!(migrated);
+
+        let mut filenames = paths
+            .iter()
+            .map(|path| Path::new(path).file_name().unwrap().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        filenames.sort();
+        assert_eq!(filenames, ["%2Ehidden.flac", "100%252F.flac", "a%2Fb.flac", "plain.flac", "v1.2.flac"]);
+        assert!(dir.join("100%252F.flac").exists());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,377 @@
+use rusqlite::{params, Connection};
+use serenity::{async_trait, model::id::{ChannelId, GuildId}};
+use songbird::{
+    events::{Event, EventContext, EventHandler as VoiceEventHandler, TrackEvent},
+    input::File,
+    tracks::{PlayMode, Track, TrackHandle},
+    Songbird,
+};
+use std::{
+    fs,
+    path::Path,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tracing::{debug, error, info};
+
+use crate::util::{
+    paths::{decode_file_stem, tts_path},
+    util::check_path,
+};
+
+/// Where the sound file of an announcement comes from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AnnouncementKind {
+    /// A file submitted for the user.
+    Custom,
+    /// A file generated with TTS.
+    Tts,
+    /// The intro or outro of a guild.
+    Jingle,
+}
+
+/// A sound file that is played for a user.
+#[derive(Clone, Debug)]
+pub struct Announcement {
+    /// Display name of the user, also used as text for the TTS fallback.
+    pub name: String,
+    pub user_id: u64,
+    pub path: String,
+    pub kind: AnnouncementKind,
+    /// TTS announcements of several users that were combined into this one, recorded in its place.
+    /// The file of a combined announcement is only played once and removed when its track ended.
+    pub combined: Vec<Announcement>,
+}
+
+impl Announcement {
+    pub fn new(name: &str, user_id: u64, path: &str) -> Announcement {
+        Announcement {
+            name: name.to_owned(),
+            user_id,
+            path: path.to_owned(),
+            kind: AnnouncementKind::Custom,
+            combined: vec![],
+        }
+    }
+
+    pub fn tts(name: &str, user_id: u64, path: &str) -> Announcement {
+        Announcement {
+            kind: AnnouncementKind::Tts,
+            ..Announcement::new(name, user_id, path)
+        }
+    }
+
+    pub fn jingle(name: &str, user_id: u64, path: &str) -> Announcement {
+        Announcement {
+            kind: AnnouncementKind::Jingle,
+            ..Announcement::new(name, user_id, path)
+        }
+    }
+
+    /// A TTS phrase that announces several users at once, it belongs to nobody.
+    pub fn combined(phrase: &str, path: &str, announcements: Vec<Announcement>) -> Announcement {
+        Announcement {
+            combined: announcements,
+            ..Announcement::tts(phrase, 0, path)
+        }
+    }
+
+    /// The announcement name, which is the file stem of the path.
+    pub fn file_stem(&self) -> String {
+        match decode_file_stem(Path::new(&self.path)) {
+            Some(stem) => stem,
+            None => self.path.clone(),
+        }
+    }
+
+    /// Path of the TTS file that is generated for the name.
+    pub fn tts_path(&self) -> String {
+        tts_path(&self.name)
+    }
+}
+
+/// Why an announcement is played, recorded with every playback.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PlaybackEvent {
+    /// A user joined or unmuted in a voice channel.
+    Join,
+    /// A user joined as part of a batch of joins.
+    BatchJoin,
+    /// Two particular users met in a voice channel.
+    Greeting,
+    /// A result of `/search` was played.
+    Search,
+    /// An entry of `/history` was played again.
+    Replay,
+    /// A submission was previewed before it was confirmed, previews aren't recorded.
+    Preview,
+}
+
+impl PlaybackEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PlaybackEvent::Join => "join",
+            PlaybackEvent::BatchJoin => "batch_join",
+            PlaybackEvent::Greeting => "greeting",
+            PlaybackEvent::Search => "search",
+            PlaybackEvent::Replay => "replay",
+            PlaybackEvent::Preview => "preview",
+        }
+    }
+}
+
+/// Which jingle of a guild to play.
+#[derive(Clone, Copy, Debug, PartialEq, poise::ChoiceParameter)]
+pub enum JinglePosition {
+    Intro,
+    Outro,
+}
+
+impl JinglePosition {
+    pub fn path(&self, guild_id: GuildId) -> String {
+        match self {
+            JinglePosition::Intro => format!("/config/guilds/{}/intro.flac", guild_id),
+            JinglePosition::Outro => format!("/config/guilds/{}/outro.flac", guild_id),
+        }
+    }
+}
+
+/// Surrounds the announcements with the intro and outro of the guild, if it has any.
+pub fn with_jingles(guild_id: GuildId, announcements: Vec<Announcement>) -> Vec<Announcement> {
+    let (name, user_id) = match announcements.first() {
+        Some(announcement) => (announcement.name.clone(), announcement.user_id),
+        None => return announcements,
+    };
+
+    let intro_path = JinglePosition::Intro.path(guild_id);
+    let outro_path = JinglePosition::Outro.path(guild_id);
+
+    let mut segments = vec![];
+    if Path::new(&intro_path).exists() {
+        segments.push(Announcement::jingle(&name, user_id, &intro_path));
+    }
+    segments.extend(announcements);
+    if Path::new(&outro_path).exists() {
+        segments.push(Announcement::jingle(&name, user_id, &outro_path));
+    }
+
+    segments
+}
+
+/// Reacts to the end or failure of an announcement track.
+///
+/// Playbacks are recorded once their track ended, so tracks that failed and were replaced by their
+/// TTS fallback are recorded as TTS.
+pub struct TrackEventHandler {
+    pub manager: Arc<Songbird>,
+    pub guild_id: GuildId,
+    pub channel_id: ChannelId,
+    pub announcement: Announcement,
+    pub event: PlaybackEvent,
+    /// The event the handler is registered for, songbird fires `End` for failed tracks as well.
+    pub track_event: TrackEvent,
+}
+
+#[async_trait]
+impl VoiceEventHandler for TrackEventHandler {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        let tracks = match ctx {
+            EventContext::Track(tracks) => tracks,
+            _ => return None,
+        };
+
+        for (state, track_handle) in tracks.iter() {
+            match (self.track_event, &state.playing) {
+                (TrackEvent::End, PlayMode::End) => {
+                    debug!(
+                        "Finished playing {} for {} ({})",
+                        &self.announcement.path, &self.announcement.name, self.announcement.user_id
+                    );
+
+                    record_playback(self.guild_id, self.channel_id, &self.announcement, self.event);
+                }
+                (TrackEvent::Error, PlayMode::Errored(why)) => {
+                    error!(
+                        "Failed to play announcement `{}` for {} ({}) from {}: {}",
+                        self.announcement.file_stem(),
+                        &self.announcement.name,
+                        self.announcement.user_id,
+                        &self.announcement.path,
+                        why
+                    );
+
+                    record_failure(self.guild_id, &self.announcement, &why.to_string());
+                    self.play_fallback(track_handle).await;
+                }
+                _ => {}
+            }
+        }
+
+        if self.track_event == TrackEvent::End && !self.announcement.combined.is_empty() {
+            if let Err(why) = fs::remove_file(&self.announcement.path) {
+                error!("Failed to remove combined announcement {}: {}", &self.announcement.path, why);
+            }
+        }
+
+        None
+    }
+}
+
+impl TrackEventHandler {
+    /// Plays the TTS version of the name in place of the failed track, before the rest of the queue.
+    async fn play_fallback(&self, failed: &TrackHandle) {
+        if self.announcement.kind != AnnouncementKind::Custom {
+            // only submitted files fall back to TTS, a failed jingle is skipped
+            return;
+        }
+        let tts_path = self.announcement.tts_path();
+
+        let handler_lock = match self.manager.get(self.guild_id) {
+            Some(handler_lock) => handler_lock,
+            None => {
+                debug!("Bot left the voice channel before the fallback for {}", &self.announcement.name);
+                return;
+            }
+        };
+
+        check_path(&tts_path, &self.announcement.name).await;
+
+        let fallback = Announcement::tts(&self.announcement.name, self.announcement.user_id, &tts_path);
+        let mut handler = handler_lock.lock().await;
+        let track_handle = handler.enqueue(Track::from(File::new(tts_path.clone()))).await;
+        add_track_events(&self.manager, self.guild_id, self.channel_id, &fallback, self.event, &track_handle);
+
+        handler.queue().modify_queue(|queue| {
+            let fallback = match queue.pop_back() {
+                Some(fallback) => fallback,
+                None => return,
+            };
+            match queue.iter().position(|queued| queued.uuid() == failed.uuid()) {
+                // the queue moves on to the fallback once it dropped the failed track
+                Some(index) => queue.insert(index + 1, fallback),
+                // the queue already started the next track, which waits until the fallback ended
+                None => {
+                    if let Some(current) = queue.front() {
+                        let _ = current.pause();
+                        let _ = fallback.play();
+                    }
+                    queue.push_front(fallback);
+                }
+            }
+        });
+
+        info!("Queued TTS fallback {}", &tts_path);
+    }
+}
+
+/// Registers the handlers that record the playback of a track and replace it by TTS if it fails.
+pub fn add_track_events(
+    manager: &Arc<Songbird>,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+    announcement: &Announcement,
+    event: PlaybackEvent,
+    track_handle: &TrackHandle,
+) {
+    for track_event in [TrackEvent::End, TrackEvent::Error] {
+        let event_handler = TrackEventHandler {
+            manager: manager.clone(),
+            guild_id,
+            channel_id,
+            announcement: announcement.clone(),
+            event,
+            track_event,
+        };
+        if let Err(why) = track_handle.add_event(Event::Track(track_event), event_handler) {
+            error!("Failed to add track event handler for {}: {}", &announcement.path, why);
+        }
+    }
+}
+
+/// Counts a playback failure for the file of the announcement in the guild.
+pub fn record_failure(guild_id: GuildId, announcement: &Announcement, why: &str) {
+    let db_path = Path::new("/config/database/db.sqlite");
+
+    let db = match Connection::open(db_path) {
+        Ok(db) => db,
+        Err(err) => {
+            error!("Failed to open database: {}", err);
+            return;
+        }
+    };
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+
+    if let Err(err) = db.execute(
+        "INSERT INTO playback_failures (guild_id, path, name, user_id, failures, last_error, last_failure)
+            VALUES (?1, ?2, ?3, ?4, 1, ?5, ?6)
+            ON CONFLICT(guild_id, path) DO UPDATE SET
+                failures=failures + 1,
+                last_error=excluded.last_error,
+                last_failure=excluded.last_failure",
+        params![
+            guild_id.get() as i64,
+            &announcement.path,
+            &announcement.name,
+            announcement.user_id as i64,
+            why,
+            timestamp as i64
+        ],
+    ) {
+        error!("Failed to record playback failure for {}, Error Code {}", &announcement.path, err);
+    }
+}
+
+/// Records that an announcement was played, jingles and previews are skipped.
+///
+/// Combined TTS of several users is recorded as the TTS of every one of them.
+pub fn record_playback(guild_id: GuildId, channel_id: ChannelId, announcement: &Announcement, event: PlaybackEvent) {
+    for combined in &announcement.combined {
+        record_playback(guild_id, channel_id, combined, event);
+    }
+
+    if announcement.kind == AnnouncementKind::Jingle || event == PlaybackEvent::Preview || announcement.user_id == 0 {
+        return;
+    }
+
+    let db_path = Path::new("/config/database/db.sqlite");
+
+    let db = match Connection::open(db_path) {
+        Ok(db) => db,
+        Err(err) => {
+            error!("Failed to open database: {}", err);
+            return;
+        }
+    };
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+
+    let announcement_name = match announcement.kind {
+        AnnouncementKind::Tts => "".to_string(),
+        _ => announcement.file_stem(),
+    };
+
+    if let Err(err) = db.execute(
+        "INSERT INTO playbacks (guild_id, channel_id, user_id, name, announcement, path, tts, event, timestamp)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            guild_id.get() as i64,
+            channel_id.get() as i64,
+            announcement.user_id as i64,
+            &announcement.name,
+            &announcement_name,
+            &announcement.path,
+            announcement.kind == AnnouncementKind::Tts,
+            event.as_str(),
+            timestamp as i64
+        ],
+    ) {
+        error!("Failed to record playback of {}, Error Code {}", &announcement.path, err);
+    }
+}
@@ -0,0 +1,336 @@
+use poise::ChoiceParameter;
+use rusqlite::{params, Connection, OptionalExtension};
+use serenity::model::id::GuildId;
+use std::path::Path;
+use tracing::error;
+
+use crate::{
+    util::filters::{validate_chain_size, validate_filters},
+    PContext,
+};
+
+/// Built-in filter presets that can be picked directly as a slash command choice.
+#[derive(Clone, Copy, Debug, PartialEq, poise::ChoiceParameter)]
+pub enum FilterPreset {
+    #[name = "chipmunk"]
+    Chipmunk,
+    #[name = "deep"]
+    Deep,
+    #[name = "echo"]
+    Echo,
+    #[name = "robot"]
+    Robot,
+    #[name = "reverse"]
+    Reverse,
+    #[name = "radio"]
+    Radio,
+}
+
+/// Optional parameter of a built-in preset.
+struct PresetParameter {
+    description: &'static str,
+    default: f64,
+    min: f64,
+    max: f64,
+}
+
+impl FilterPreset {
+    pub fn all() -> [FilterPreset; 6] {
+        [
+            FilterPreset::Chipmunk,
+            FilterPreset::Deep,
+            FilterPreset::Echo,
+            FilterPreset::Robot,
+            FilterPreset::Reverse,
+            FilterPreset::Radio,
+        ]
+    }
+
+    pub fn from_name(name: &str) -> Option<FilterPreset> {
+        FilterPreset::all()
+            .into_iter()
+            .find(|preset| preset.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            FilterPreset::Chipmunk => "Higher pitch at the same speed",
+            FilterPreset::Deep => "Lower pitch at the same speed",
+            FilterPreset::Echo => "Echo of the clip",
+            FilterPreset::Robot => "Robotic voice",
+            FilterPreset::Reverse => "Play the clip backwards",
+            FilterPreset::Radio => "Thin sound of an old radio",
+        }
+    }
+
+    fn parameter(&self) -> Option<PresetParameter> {
+        match self {
+            FilterPreset::Chipmunk => Some(PresetParameter {
+                description: "pitch factor",
+                default: 1.5,
+                min: 1.0,
+                max: 2.0,
+            }),
+            FilterPreset::Deep => Some(PresetParameter {
+                description: "pitch factor",
+                default: 0.75,
+                min: 0.5,
+                max: 1.0,
+            }),
+            FilterPreset::Echo => Some(PresetParameter {
+                description: "delay in ms",
+                default: 250.0,
+                min: 20.0,
+                max: 1000.0,
+            }),
+            FilterPreset::Radio => Some(PresetParameter {
+                description: "upper frequency in Hz",
+                default: 3000.0,
+                min: 1000.0,
+                max: 8000.0,
+            }),
+            FilterPreset::Robot | FilterPreset::Reverse => None,
+        }
+    }
+
+    /// Usage of the preset as shown in help texts, e.g. `echo[:delay in ms]`.
+    pub fn usage(&self) -> String {
+        match self.parameter() {
+            Some(parameter) => format!("{}[:{}]", self.name(), parameter.description),
+            None => self.name().to_string(),
+        }
+    }
+
+    /// Builds the ffmpeg filters of the preset, the parameter has to be within the bounds of the preset.
+    pub fn filters(&self, parameter: Option<f64>) -> Result<String, String> {
+        let value = match (self.parameter(), parameter) {
+            (Some(bounds), Some(value)) if value < bounds.min || value > bounds.max => {
+                return Err(format!(
+                    "The {} of {} has to be between {} and {}",
+                    bounds.description,
+                    self.name(),
+                    bounds.min,
+                    bounds.max
+                ));
+            }
+            (Some(_), Some(value)) => value,
+            (Some(bounds), None) => bounds.default,
+            (None, Some(_)) => return Err(format!("{} doesn't take a parameter", self.name())),
+            (None, None) => 0.0,
+        };
+
+        let filters = match self {
+            FilterPreset::Chipmunk | FilterPreset::Deep => format!(
+                "aresample=48000,asetrate={},aresample=48000,atempo={:.4}",
+                (48000.0 * value).round(),
+                1.0 / value
+            ),
+            FilterPreset::Echo => format!("aecho=0.8:0.88:{}:0.4", value.round()),
+            FilterPreset::Robot => {
+                "afftfilt=real='hypot(re,im)*sin(0)':imag='hypot(re,im)*cos(0)':win_size=512:overlap=0.75".to_string()
+            }
+            FilterPreset::Reverse => "areverse".to_string(),
+            FilterPreset::Radio => format!("highpass=f=300,lowpass=f={},acompressor", value.round()),
+        };
+
+        Ok(filters)
+    }
+}
+
+/// Reads the filters of a preset an admin defined for the guild.
+pub fn get_guild_preset(guild_id: GuildId, name: &str) -> Option<String> {
+    let db_path = Path::new("/config/database/db.sqlite");
+
+    let db = match Connection::open(db_path) {
+        Ok(db) => db,
+        Err(err) => {
+            error!("Failed to open database: {}", err);
+            return None;
+        }
+    };
+
+    match db
+        .query_row::<String, _, _>(
+            "SELECT filters FROM filter_presets WHERE guild_id=?1 AND name=?2",
+            params![guild_id.get() as i64, name.to_lowercase()],
+            |row| row.get(0),
+        )
+        .optional()
+    {
+        Ok(filters) => filters,
+        Err(err) => {
+            error!("Failed to query preset {} for guild {}, Error Code {}", name, guild_id, err);
+            None
+        }
+    }
+}
+
+/// All presets admins defined for the guild as name and filters.
+pub fn get_guild_presets(guild_id: GuildId) -> Result<Vec<(String, String)>, rusqlite::Error> {
+    let db_path = Path::new("/config/database/db.sqlite");
+    let db = Connection::open(db_path)?;
+
+    let mut stmt = db.prepare("SELECT name, filters FROM filter_presets WHERE guild_id=?1 ORDER BY name")?;
+    let presets = stmt
+        .query_map(params![guild_id.get() as i64], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<Vec<(String, String)>, _>>()?;
+
+    Ok(presets)
+}
+
+pub fn set_guild_preset(guild_id: GuildId, name: &str, filters: &str) -> Result<(), rusqlite::Error> {
+    let db_path = Path::new("/config/database/db.sqlite");
+    let db = Connection::open(db_path)?;
+
+    db.execute(
+        "INSERT OR REPLACE INTO filter_presets (guild_id, name, filters)
+            VALUES (?1, ?2, ?3)",
+        params![guild_id.get() as i64, name.to_lowercase(), filters],
+    )?;
+
+    Ok(())
+}
+
+/// Removes a preset of the guild, returns whether it existed.
+pub fn remove_guild_preset(guild_id: GuildId, name: &str) -> Result<bool, rusqlite::Error> {
+    let db_path = Path::new("/config/database/db.sqlite");
+    let db = Connection::open(db_path)?;
+
+    let removed = db.execute(
+        "DELETE FROM filter_presets WHERE guild_id=?1 AND name=?2",
+        params![guild_id.get() as i64, name.to_lowercase()],
+    )?;
+
+    Ok(removed > 0)
+}
+
+/// Builds the filters of a chain of presets like `chipmunk echo:500`, applied in the given order.
+fn chain_filters(guild_id: GuildId, chain: &str) -> Result<Vec<String>, String> {
+    let mut filters = vec![];
+
+    for element in chain.split(|c: char| c.is_whitespace() || c == '+').filter(|element| !element.is_empty()) {
+        let (name, parameter) = match element.split_once(':') {
+            Some((name, parameter)) => match parameter.parse::<f64>() {
+                Ok(parameter) if parameter.is_finite() => (name, Some(parameter)),
+                _ => return Err(format!("Invalid parameter for preset {}", name)),
+            },
+            None => (element, None),
+        };
+
+        if let Some(preset) = FilterPreset::from_name(name) {
+            filters.push(preset.filters(parameter)?);
+            continue;
+        }
+
+        match get_guild_preset(guild_id, name) {
+            Some(_) if parameter.is_some() => return Err(format!("{} doesn't take a parameter", name)),
+            Some(preset_filters) => {
+                validate_filters(&preset_filters).map_err(|why| format!("Preset {} is invalid: {}", name, why))?;
+                filters.push(preset_filters);
+            }
+            None => return Err(format!("There is no preset called {}", name)),
+        }
+    }
+
+    Ok(filters)
+}
+
+/// Combines the chosen preset, the preset chain and raw ffmpeg filters, in that order.
+pub fn resolve_filters(
+    guild_id: GuildId,
+    preset: Option<FilterPreset>,
+    presets: Option<&str>,
+    filters: Option<&str>,
+) -> Result<Option<String>, String> {
+    let mut all_filters = vec![];
+
+    if let Some(preset) = preset {
+        all_filters.push(preset.filters(None)?);
+    }
+    if let Some(presets) = presets {
+        all_filters.extend(chain_filters(guild_id, presets)?);
+    }
+    if let Some(filters) = filters.filter(|filters| !filters.trim().is_empty()) {
+        validate_filters(filters)?;
+        all_filters.push(filters.to_string());
+    }
+
+    if all_filters.is_empty() {
+        return Ok(None);
+    }
+
+    // built-in presets aren't validated, so chaining many of them has to be bounded as a whole
+    let all_filters = all_filters.join(",");
+    validate_chain_size(&all_filters)?;
+
+    Ok(Some(all_filters))
+}
+
+/// Suggests presets for the last element of a preset chain.
+pub async fn autocomplete_presets(ctx: PContext<'_>, partial: &str) -> Vec<String> {
+    let (chained, last) = match partial.rsplit_once(' ') {
+        Some((chained, last)) => (format!("{} ", chained), last),
+        None => ("".to_string(), partial),
+    };
+
+    let mut names = FilterPreset::all()
+        .iter()
+        .map(|preset| preset.name().to_string())
+        .collect::<Vec<_>>();
+
+    if let Some(guild_id) = ctx.guild_id() {
+        if let Ok(guild_presets) = get_guild_presets(guild_id) {
+            names.extend(guild_presets.into_iter().map(|(name, _)| name));
+        }
+    }
+
+    names
+        .into_iter()
+        .filter(|name| name.starts_with(&last.to_lowercase()))
+        .map(|name| format!("{}{}", chained, name))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bounds_preset_parameters() {
+        assert!(FilterPreset::Chipmunk.filters(Some(2.0)).is_ok());
+        assert!(FilterPreset::Chipmunk.filters(Some(2.5)).is_err());
+        assert!(FilterPreset::Deep.filters(Some(0.4)).is_err());
+        assert!(FilterPreset::Echo.filters(Some(20.0)).is_ok());
+        assert!(FilterPreset::Echo.filters(Some(5000.0)).is_err());
+        assert!(FilterPreset::Radio.filters(Some(999.0)).is_err());
+        assert!(FilterPreset::Robot.filters(Some(1.0)).is_err());
+        assert_eq!(FilterPreset::Echo.filters(None).unwrap(), "aecho=0.8:0.88:250:0.4");
+        assert_eq!(FilterPreset::Echo.filters(Some(499.6)).unwrap(), "aecho=0.8:0.88:500:0.4");
+    }
+
+    #[test]
+    fn parses_preset_chains() {
+        let guild_id = GuildId::new(1);
+
+        let filters = chain_filters(guild_id, "reverse echo:500").unwrap();
+        assert_eq!(filters, ["areverse", "aecho=0.8:0.88:500:0.4"]);
+        assert_eq!(chain_filters(guild_id, "reverse+ECHO  radio").unwrap().len(), 3);
+        assert!(chain_filters(guild_id, "").unwrap().is_empty());
+
+        assert!(chain_filters(guild_id, "echo:").is_err());
+        assert!(chain_filters(guild_id, "echo:inf").is_err());
+        assert!(chain_filters(guild_id, "echo:NaN").is_err());
+        assert!(chain_filters(guild_id, "reverse:2").is_err());
+    }
+
+    #[test]
+    fn bounds_preset_chains() {
+        let guild_id = GuildId::new(1);
+
+        assert!(resolve_filters(guild_id, Some(FilterPreset::Robot), Some("reverse"), Some("volume=2")).is_ok());
+        assert!(resolve_filters(guild_id, None, Some(&["robot"; 6].join(" ")), None).is_err());
+        assert!(resolve_filters(guild_id, None, Some(&["reverse"; 11].join(" ")), None).is_err());
+        assert!(resolve_filters(guild_id, Some(FilterPreset::Chipmunk), Some("chipmunk chipmunk"), None).is_err());
+        assert_eq!(resolve_filters(guild_id, None, None, Some(" ")).unwrap(), None);
+    }
+}
@@ -0,0 +1,231 @@
+use serenity::{
+    client::Context,
+    model::id::{ChannelId, GuildId, UserId},
+};
+use std::collections::HashSet;
+use tracing::{debug, error, info};
+
+use crate::util::batch::{replay_join_batch, take_join_batch};
+
+/// Voice state of the bot in a guild as seen by songbird and by the guild cache.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VoiceSnapshot {
+    /// Channel songbird thinks it is connected to, `None` if there is no call or it is disconnected.
+    pub connection_channel: Option<ChannelId>,
+    /// Channel the guild cache has the bot in.
+    pub cached_channel: Option<ChannelId>,
+    /// Number of users in the cached channel that aren't bots.
+    pub listeners: usize,
+}
+
+/// Voice state of a user in the guild cache.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CachedVoiceState {
+    pub user_id: UserId,
+    pub channel_id: Option<ChannelId>,
+    pub bot: bool,
+}
+
+/// What to do with the voice connection of a guild.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReconcileAction {
+    /// Nothing to do, there is no connection or it matches the cache.
+    None,
+    /// The connection is stale or nobody is listening.
+    Leave,
+    /// The bot is in a channel with listeners that songbird doesn't drive, join it again.
+    Rejoin(ChannelId),
+}
+
+/// Builds the snapshot of a guild from the channel songbird is connected to and the cached voice states.
+pub fn voice_snapshot(connection_channel: Option<ChannelId>, bot_id: UserId, voice_states: &[CachedVoiceState]) -> VoiceSnapshot {
+    let cached_channel = voice_states
+        .iter()
+        .find(|state| state.user_id == bot_id)
+        .and_then(|state| state.channel_id);
+
+    let listeners = match cached_channel {
+        Some(channel_id) => voice_states
+            .iter()
+            .filter(|state| state.channel_id == Some(channel_id) && !state.bot)
+            .count(),
+        None => 0,
+    };
+
+    VoiceSnapshot {
+        connection_channel,
+        cached_channel,
+        listeners,
+    }
+}
+
+pub fn reconcile_action(snapshot: &VoiceSnapshot) -> ReconcileAction {
+    let cached_channel = match snapshot.cached_channel {
+        Some(cached_channel) => cached_channel,
+        None if snapshot.connection_channel.is_some() => return ReconcileAction::Leave,
+        None => return ReconcileAction::None,
+    };
+
+    if snapshot.listeners == 0 {
+        return ReconcileAction::Leave;
+    }
+
+    if snapshot.connection_channel != Some(cached_channel) {
+        return ReconcileAction::Rejoin(cached_channel);
+    }
+
+    ReconcileAction::None
+}
+
+/// Compares the songbird calls with the guild cache after a reconnect or restart and fixes any differences.
+///
+/// Guilds whose connection matches the cache are left alone.
+pub async fn reconcile_voice_connections(ctx: &Context) {
+    let manager = songbird::get(ctx)
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone();
+
+    let mut guild_ids = ctx.cache.guilds().into_iter().collect::<HashSet<_>>();
+    for (guild_id, _) in manager.iter() {
+        guild_ids.insert(GuildId::new(guild_id.0.get()));
+    }
+
+    for guild_id in guild_ids {
+        let connection_channel = match manager.get(guild_id) {
+            Some(handler_lock) => handler_lock
+                .lock()
+                .await
+                .current_channel()
+                .map(|channel_id| ChannelId::new(channel_id.0.get())),
+            None => None,
+        };
+
+        let bot_id = ctx.cache.current_user().id;
+        let snapshot = voice_snapshot(connection_channel, bot_id, &cached_voice_states(ctx, guild_id));
+
+        let action = reconcile_action(&snapshot);
+        if action != ReconcileAction::None {
+            debug!("Reconciling voice connection for guild {}: {:?} -> {:?}", guild_id, snapshot, action);
+        }
+
+        match action {
+            ReconcileAction::None => continue,
+            ReconcileAction::Leave => {
+                // after a restart songbird has no call for the stale channel, a new call still sends the
+                // voice state update that disconnects the bot
+                let handler_lock = manager.get_or_insert(guild_id);
+                handler_lock.lock().await.queue().stop();
+                if let Err(why) = manager.remove(guild_id).await {
+                    error!("Failed to leave voice channel in guild {}: {}", guild_id, why);
+                } else {
+                    info!("Left stale voice connection in guild {}", guild_id);
+                }
+
+                // nobody is left to hear the joins waiting to be announced
+                for channel_id in [snapshot.cached_channel, snapshot.connection_channel].into_iter().flatten() {
+                    let joins = take_join_batch(ctx, channel_id).await;
+                    if !joins.is_empty() {
+                        debug!("Dropped {} pending joins of channel {}", joins.len(), channel_id);
+                    }
+                }
+            }
+            ReconcileAction::Rejoin(channel_id) => {
+                if let Err(why) = manager.join(guild_id, channel_id).await {
+                    error!("Failed to rejoin channel with id {} with err {}", channel_id, why);
+                    continue;
+                }
+                info!("Rejoined voice channel {} in guild {}", channel_id, guild_id);
+
+                replay_join_batch(ctx, channel_id, guild_id).await;
+            }
+        }
+    }
+}
+
+/// Returns the cached voice states of the guild.
+fn cached_voice_states(ctx: &Context, guild_id: GuildId) -> Vec<CachedVoiceState> {
+    let guild = match guild_id.to_guild_cached(&ctx) {
+        Some(guild) => guild,
+        None => return vec![],
+    };
+
+    guild
+        .voice_states
+        .values()
+        .map(|state| CachedVoiceState {
+            user_id: state.user_id,
+            channel_id: state.channel_id,
+            bot: match &state.member {
+                Some(member) => member.user.bot,
+                None => guild.members.get(&state.user_id).is_some_and(|member| member.user.bot),
+            },
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOT: UserId = UserId::new(1);
+    const LOBBY: ChannelId = ChannelId::new(10);
+    const GAMING: ChannelId = ChannelId::new(20);
+
+    fn state(user_id: u64, channel_id: Option<ChannelId>, bot: bool) -> CachedVoiceState {
+        CachedVoiceState {
+            user_id: UserId::new(user_id),
+            channel_id,
+            bot,
+        }
+    }
+
+    #[test]
+    fn reconciles_against_the_cache() {
+        let cases = [
+            // not connected and not in a channel
+            (None, vec![state(2, Some(LOBBY), false)], ReconcileAction::None),
+            // connected to the channel the cache has the bot in, with a listener
+            (Some(LOBBY), vec![state(1, Some(LOBBY), true), state(2, Some(LOBBY), false)], ReconcileAction::None),
+            // connected, but the cache lost the bot
+            (Some(LOBBY), vec![state(2, Some(LOBBY), false)], ReconcileAction::Leave),
+            (Some(LOBBY), vec![state(1, None, true), state(2, Some(LOBBY), false)], ReconcileAction::Leave),
+            // only other bots or nobody are listening
+            (Some(LOBBY), vec![state(1, Some(LOBBY), true), state(3, Some(LOBBY), true)], ReconcileAction::Leave),
+            (Some(LOBBY), vec![state(1, Some(LOBBY), true), state(2, Some(GAMING), false)], ReconcileAction::Leave),
+            (None, vec![state(1, Some(LOBBY), true)], ReconcileAction::Leave),
+            // the cache has the bot somewhere songbird isn't connected to
+            (None, vec![state(1, Some(LOBBY), true), state(2, Some(LOBBY), false)], ReconcileAction::Rejoin(LOBBY)),
+            (
+                Some(GAMING),
+                vec![state(1, Some(LOBBY), true), state(2, Some(LOBBY), false), state(3, Some(GAMING), false)],
+                ReconcileAction::Rejoin(LOBBY),
+            ),
+        ];
+
+        for (connection_channel, voice_states, expected) in cases {
+            let snapshot = voice_snapshot(connection_channel, BOT, &voice_states);
+            assert_eq!(reconcile_action(&snapshot), expected, "{:?}", snapshot);
+        }
+    }
+
+    #[test]
+    fn counts_listeners_in_the_bots_channel() {
+        let voice_states = [
+            state(1, Some(LOBBY), true),
+            state(2, Some(LOBBY), false),
+            state(3, Some(LOBBY), false),
+            state(4, Some(LOBBY), true),
+            state(5, Some(GAMING), false),
+            state(6, None, false),
+        ];
+
+        let snapshot = voice_snapshot(Some(LOBBY), BOT, &voice_states);
+        assert_eq!(snapshot.cached_channel, Some(LOBBY));
+        assert_eq!(snapshot.listeners, 2);
+
+        let snapshot = voice_snapshot(None, UserId::new(99), &voice_states);
+        assert_eq!(snapshot.cached_channel, None);
+        assert_eq!(snapshot.listeners, 0);
+    }
+}
@@ -0,0 +1,419 @@
+use serenity::{
+    async_trait,
+    client::Context,
+    model::id::{ChannelId, GuildId, UserId},
+};
+use songbird::{
+    driver::{DecodeConfig, DecodeMode},
+    events::{context_data::VoiceTick, CoreEvent, Event, EventContext, EventHandler as VoiceEventHandler},
+    model::payload::Speaking,
+    Call,
+    Config,
+};
+use std::{
+    collections::HashMap,
+    fs,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::Mutex as AsyncMutex;
+use tracing::{debug, error};
+
+/// Longest recording, clips are cut to the length limit afterwards anyway.
+pub const MAX_RECORDING_DURATION: u64 = 15;
+/// Seconds counted down before the recording starts.
+pub const RECORDING_COUNTDOWN: u64 = 3;
+/// Songbird decodes received voice to 48kHz stereo by default.
+const SAMPLE_RATE: u32 = 48000;
+const CHANNELS: u16 = 2;
+/// Samples of one 20ms voice tick, for all channels.
+const TICK_SAMPLES: usize = (SAMPLE_RATE as usize / 50) * CHANNELS as usize;
+
+/// Audio received from a single user, ticks without audio from them are filled with silence to keep the timing.
+#[derive(Default)]
+struct Recording {
+    ssrc: Option<u32>,
+    samples: Vec<i16>,
+    received_voice: bool,
+    stopped: bool,
+}
+
+impl Recording {
+    fn speaking(&mut self, user_id: UserId, speaking: &Speaking) {
+        if speaking.user_id.is_some_and(|speaker| speaker.0 == user_id.get()) {
+            self.ssrc = Some(speaking.ssrc);
+        }
+    }
+
+    /// Appends the voice of the user from the decoded voice of all speakers in one tick.
+    fn tick(&mut self, voice: &HashMap<u32, &[i16]>) {
+        let voice = self.ssrc.and_then(|ssrc| voice.get(&ssrc).copied());
+
+        self.push(voice);
+    }
+
+    /// Appends the decoded voice of one tick, a tick without any is a gap of one tick of silence.
+    fn push(&mut self, voice: Option<&[i16]>) {
+        match voice {
+            Some(voice) if !voice.is_empty() => {
+                self.samples.extend_from_slice(voice);
+                self.received_voice = true;
+            }
+            _ => self.samples.resize(self.samples.len() + TICK_SAMPLES, 0),
+        }
+    }
+}
+
+/// Collects the voice of one user from the call until the recording is stopped.
+#[derive(Clone)]
+struct VoiceReceiver {
+    user_id: UserId,
+    recording: Arc<Mutex<Recording>>,
+}
+
+/// What the receiver gets from songbird, the decoded voice of a tick is keyed by SSRC.
+enum Received<'a> {
+    Speaking(&'a Speaking),
+    Tick(HashMap<u32, &'a [i16]>),
+}
+
+impl VoiceReceiver {
+    fn receive(&self, received: Received<'_>) -> Option<Event> {
+        let mut recording = match self.recording.lock() {
+            Ok(recording) => recording,
+            Err(_) => return Some(Event::Cancel),
+        };
+
+        if recording.stopped {
+            return Some(Event::Cancel);
+        }
+
+        match received {
+            Received::Speaking(speaking) => recording.speaking(self.user_id, speaking),
+            Received::Tick(voice) => recording.tick(&voice),
+        }
+
+        None
+    }
+}
+
+#[async_trait]
+impl VoiceEventHandler for VoiceReceiver {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        match ctx {
+            EventContext::SpeakingStateUpdate(speaking) => self.receive(Received::Speaking(speaking)),
+            EventContext::VoiceTick(tick) => self.receive(Received::Tick(decoded_voice(tick))),
+            _ => None,
+        }
+    }
+}
+
+/// The decoded voice of every speaker in the tick.
+fn decoded_voice(tick: &VoiceTick) -> HashMap<u32, &[i16]> {
+    tick.speaking
+        .iter()
+        .filter_map(|(ssrc, data)| data.decoded_voice.as_deref().map(|voice| (*ssrc, voice)))
+        .collect()
+}
+
+/// A recording that runs in a voice channel until it is stopped.
+pub struct VoiceRecorder {
+    handler_lock: Arc<AsyncMutex<Call>>,
+    receiver: VoiceReceiver,
+    previous_config: Config,
+}
+
+impl VoiceRecorder {
+    /// Drops what was received so far, e.g. while counting down.
+    pub fn restart(&self) {
+        if let Ok(mut recording) = self.receiver.recording.lock() {
+            recording.samples.clear();
+            recording.received_voice = false;
+        }
+    }
+
+    /// Stops receiving and returns the recorded samples.
+    pub async fn stop(self) -> Result<Vec<i16>, String> {
+        self.handler_lock.lock().await.set_config(self.previous_config);
+
+        let mut recording = match self.receiver.recording.lock() {
+            Ok(recording) => recording,
+            Err(_) => return Err("Failed to read the recording".to_string()),
+        };
+        recording.stopped = true;
+
+        if !recording.received_voice {
+            return Err("No audio was received from you, make sure you are not muted".to_string());
+        }
+
+        Ok(std::mem::take(&mut recording.samples))
+    }
+}
+
+/// Joins the voice channel and starts recording the voice of the user.
+///
+/// Joining takes a moment, so the recording is started before the user is asked to speak. Decoding received
+/// voice is only enabled while recording, as it costs time for every packet.
+pub async fn start_recording(
+    ctx: &Context,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+    user_id: UserId,
+) -> Result<VoiceRecorder, String> {
+    let manager = songbird::get(ctx)
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone();
+
+    let handler_lock = match manager.join(guild_id, channel_id).await {
+        Ok(handler_lock) => handler_lock,
+        Err(why) => {
+            error!("Failed to connect to channel with id {} with err {}", channel_id, why);
+            return Err("Failed to join your voice channel".to_string());
+        }
+    };
+
+    let receiver = VoiceReceiver {
+        user_id,
+        recording: Arc::new(Mutex::new(Recording::default())),
+    };
+
+    let previous_config = {
+        let mut handler = handler_lock.lock().await;
+        let previous_config = handler.config().clone();
+        handler.set_config(previous_config.clone().decode_mode(DecodeMode::Decode(DecodeConfig::default())));
+        handler.add_global_event(Event::Core(CoreEvent::SpeakingStateUpdate), receiver.clone());
+        handler.add_global_event(Event::Core(CoreEvent::VoiceTick), receiver.clone());
+        previous_config
+    };
+
+    debug!("Recording {} in {}", user_id, channel_id);
+
+    Ok(VoiceRecorder {
+        handler_lock,
+        receiver,
+        previous_config,
+    })
+}
+
+/// Writes interleaved 16-bit samples as a wav file that ffmpeg can process like any upload.
+pub fn write_wav(path: &str, samples: &[i16]) -> Result<(), String> {
+    let data_size = (samples.len() * 2) as u32;
+    let block_align = CHANNELS * 2;
+
+    let mut wav = Vec::with_capacity(44 + data_size as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_size).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&CHANNELS.to_le_bytes());
+    wav.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    wav.extend_from_slice(&(SAMPLE_RATE * block_align as u32).to_le_bytes());
+    wav.extend_from_slice(&block_align.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_size.to_le_bytes());
+    for sample in samples {
+        wav.extend_from_slice(&sample.to_le_bytes());
+    }
+
+    fs::write(path, wav).map_err(|why| why.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opus2::{Application, Channels, Decoder, Encoder};
+    use songbird::model::{id::UserId as VoiceUserId, SpeakingState};
+
+    const SSRC: u32 = 42;
+    const OTHER_SSRC: u32 = 43;
+
+    /// Encodes 20ms frames of a stereo sine wave with the given amplitude, like a Discord client would send them.
+    fn encode_frames(frames: usize, amplitude: f64) -> Vec<Vec<u8>> {
+        let mut encoder = Encoder::new(SAMPLE_RATE, Channels::Stereo, Application::Voip).unwrap();
+
+        (0..frames)
+            .map(|frame| {
+                let pcm = (0..TICK_SAMPLES / 2)
+                    .flat_map(|index| {
+                        let time = (frame * TICK_SAMPLES / 2 + index) as f64 / SAMPLE_RATE as f64;
+                        let sample = (amplitude * (2.0 * std::f64::consts::PI * 440.0 * time).sin()) as i16;
+                        [sample, sample]
+                    })
+                    .collect::<Vec<_>>();
+
+                let mut packet = vec![0; 4000];
+                let length = encoder.encode(&pcm, &mut packet).unwrap();
+                packet.truncate(length);
+                packet
+            })
+            .collect()
+    }
+
+    /// Decodes a packet like songbird does for every tick, `None` is a lost packet that is concealed.
+    ///
+    /// Songbird decodes into a buffer of one tick, so concealment doesn't produce more than a tick either.
+    fn decode(decoder: &mut Decoder, packet: Option<&[u8]>) -> Vec<i16> {
+        let mut pcm = vec![0; TICK_SAMPLES];
+        let length = decoder.decode(packet.unwrap_or(&[]), &mut pcm, false).unwrap();
+        pcm.truncate(length * CHANNELS as usize);
+        pcm
+    }
+
+    fn rms(samples: &[i16]) -> f64 {
+        let sum = samples.iter().map(|sample| (*sample as f64).powi(2)).sum::<f64>();
+        (sum / samples.len() as f64).sqrt()
+    }
+
+    fn speaking(user_id: u64, ssrc: u32) -> EventContext<'static> {
+        EventContext::SpeakingStateUpdate(Speaking {
+            delay: None,
+            speaking: SpeakingState::MICROPHONE,
+            ssrc,
+            user_id: Some(VoiceUserId(user_id)),
+        })
+    }
+
+    fn receiver(user_id: u64) -> VoiceReceiver {
+        VoiceReceiver {
+            user_id: UserId::new(user_id),
+            recording: Arc::new(Mutex::new(Recording::default())),
+        }
+    }
+
+    /// Hands the decoded voice of one tick to the receiver, like `act` does for a `VoiceTick`.
+    ///
+    /// `VoiceTick` and `VoiceData` are non-exhaustive, so they can't be built outside of songbird.
+    fn tick(receiver: &VoiceReceiver, voice: &[(u32, &[i16])]) -> Option<Event> {
+        receiver.receive(Received::Tick(voice.iter().copied().collect()))
+    }
+
+    fn samples(receiver: &VoiceReceiver) -> Vec<i16> {
+        receiver.recording.lock().unwrap().samples.clone()
+    }
+
+    fn received_voice(receiver: &VoiceReceiver) -> bool {
+        receiver.recording.lock().unwrap().received_voice
+    }
+
+    #[tokio::test]
+    async fn decodes_synthetic_packets() {
+        let mut decoder = Decoder::new(SAMPLE_RATE, Channels::Stereo).unwrap();
+        let mut other_decoder = Decoder::new(SAMPLE_RATE, Channels::Stereo).unwrap();
+        let receiver = receiver(1);
+        receiver.act(&speaking(1, SSRC)).await;
+        receiver.act(&speaking(2, OTHER_SSRC)).await;
+
+        let packets = encode_frames(10, 8000.0);
+        let other_packets = encode_frames(10, 16000.0);
+        for (packet, other_packet) in packets.iter().zip(other_packets.iter()) {
+            let voice = decode(&mut decoder, Some(packet));
+            let other_voice = decode(&mut other_decoder, Some(other_packet));
+            assert_eq!(voice.len(), TICK_SAMPLES);
+            assert!(tick(&receiver, &[(SSRC, &voice), (OTHER_SSRC, &other_voice)]).is_none());
+        }
+
+        assert!(received_voice(&receiver));
+        let samples = samples(&receiver);
+        assert_eq!(samples.len(), 10 * TICK_SAMPLES);
+        // the codec needs a few ms to settle, afterwards the tone is there at roughly its level
+        let level = rms(&samples[2 * TICK_SAMPLES..]);
+        let expected = 8000.0 / 2.0_f64.sqrt();
+        assert!(level > expected * 0.5 && level < expected * 1.5, "{}", level);
+    }
+
+    #[tokio::test]
+    async fn keeps_ticks_in_order() {
+        let mut decoder = Decoder::new(SAMPLE_RATE, Channels::Stereo).unwrap();
+        let receiver = receiver(1);
+        receiver.act(&speaking(1, SSRC)).await;
+
+        let loud = encode_frames(5, 16000.0);
+        let quiet = encode_frames(5, 1000.0);
+        for packet in loud.iter().chain(quiet.iter()) {
+            let voice = decode(&mut decoder, Some(packet));
+            tick(&receiver, &[(SSRC, &voice)]);
+        }
+
+        let samples = samples(&receiver);
+        let (first, second) = samples.split_at(5 * TICK_SAMPLES);
+        assert!(rms(&first[TICK_SAMPLES..]) > 4.0 * rms(&second[TICK_SAMPLES..]));
+    }
+
+    #[tokio::test]
+    async fn fills_gaps_with_silence() {
+        let mut decoder = Decoder::new(SAMPLE_RATE, Channels::Stereo).unwrap();
+        let receiver = receiver(1);
+        let packets = encode_frames(4, 8000.0);
+
+        // before the speaking update the SSRC of the user is unknown, their voice is a gap
+        let voice = decode(&mut decoder, Some(&packets[0]));
+        tick(&receiver, &[(SSRC, &voice)]);
+        receiver.act(&speaking(1, SSRC)).await;
+        // an empty decode is a gap as well
+        tick(&receiver, &[(SSRC, &[])]);
+        assert!(!received_voice(&receiver));
+        assert_eq!(samples(&receiver), vec![0; 2 * TICK_SAMPLES]);
+
+        let voice = decode(&mut decoder, Some(&packets[1]));
+        tick(&receiver, &[(SSRC, &voice)]);
+        // a lost packet is concealed by the decoder and still takes exactly one tick
+        let concealed = decode(&mut decoder, None);
+        assert_eq!(concealed.len(), TICK_SAMPLES);
+        tick(&receiver, &[(SSRC, &concealed)]);
+        // a tick in which only somebody else speaks
+        tick(&receiver, &[(OTHER_SSRC, &voice)]);
+        let voice = decode(&mut decoder, Some(&packets[3]));
+        tick(&receiver, &[(SSRC, &voice)]);
+
+        assert!(received_voice(&receiver));
+        let samples = samples(&receiver);
+        assert_eq!(samples.len(), 6 * TICK_SAMPLES);
+        assert!(samples[4 * TICK_SAMPLES..5 * TICK_SAMPLES].iter().all(|sample| *sample == 0));
+    }
+
+    #[tokio::test]
+    async fn records_only_the_chosen_user() {
+        let mut decoder = Decoder::new(SAMPLE_RATE, Channels::Stereo).unwrap();
+        let receiver = receiver(1);
+        let voice = decode(&mut decoder, Some(&encode_frames(1, 8000.0)[0]));
+
+        receiver.act(&speaking(2, OTHER_SSRC)).await;
+        tick(&receiver, &[(OTHER_SSRC, &voice)]);
+        assert!(!received_voice(&receiver));
+
+        receiver.act(&speaking(1, SSRC)).await;
+        tick(&receiver, &[(OTHER_SSRC, &voice), (SSRC, &voice)]);
+        assert!(received_voice(&receiver));
+        assert_eq!(samples(&receiver)[TICK_SAMPLES..], voice[..]);
+    }
+
+    #[tokio::test]
+    async fn cancels_once_stopped() {
+        let receiver = receiver(1);
+        assert!(receiver.act(&speaking(1, SSRC)).await.is_none());
+
+        receiver.recording.lock().unwrap().stopped = true;
+        assert!(matches!(receiver.act(&speaking(1, SSRC)).await, Some(Event::Cancel)));
+        assert!(matches!(tick(&receiver, &[(SSRC, &[0; TICK_SAMPLES])]), Some(Event::Cancel)));
+        assert!(samples(&receiver).is_empty());
+    }
+
+    #[test]
+    fn writes_wav_headers() {
+        let path = std::env::temp_dir().join(format!("announcer-recording-{}.wav", std::process::id()));
+        let samples = [0i16, 1, -1, i16::MAX, i16::MIN, 2];
+        write_wav(path.to_str().unwrap(), &samples).unwrap();
+
+        let wav = fs::read(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        assert_eq!(wav.len(), 44 + samples.len() * 2);
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(u32::from_le_bytes(wav[4..8].try_into().unwrap()), 36 + 12);
+        assert_eq!(u16::from_le_bytes(wav[22..24].try_into().unwrap()), CHANNELS);
+        assert_eq!(u32::from_le_bytes(wav[24..28].try_into().unwrap()), SAMPLE_RATE);
+        assert_eq!(&wav[36..40], b"data");
+        assert_eq!(i16::from_le_bytes(wav[50..52].try_into().unwrap()), i16::MAX);
+    }
+}
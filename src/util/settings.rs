@@ -0,0 +1,65 @@
+use rusqlite::{params, Connection, OptionalExtension};
+use serenity::model::id::GuildId;
+use std::path::Path;
+use tracing::error;
+
+// guild setting keys
+pub const BATCH_JOINS: &str = "batch_joins";
+pub const CLIP_LENGTH: &str = "clip_length";
+pub const LOUDNESS_TARGET: &str = "loudness_target";
+pub const MODERATION: &str = "moderation";
+pub const MODERATION_CHANNEL: &str = "moderation_channel";
+pub const MODERATOR_ROLE: &str = "moderator_role";
+pub const TRUE_PEAK_TARGET: &str = "true_peak_target";
+
+/// Reads a setting of a guild, returns `None` if it was never set.
+pub fn get_setting(guild_id: GuildId, key: &str) -> Option<String> {
+    let db_path = Path::new("/config/database/db.sqlite");
+
+    let db = match Connection::open(db_path) {
+        Ok(db) => db,
+        Err(err) => {
+            error!("Failed to open database: {}", err);
+            return None;
+        }
+    };
+
+    match db
+        .query_row::<String, _, _>(
+            "SELECT value FROM guild_settings WHERE guild_id=?1 AND key=?2",
+            params![guild_id.get() as i64, key],
+            |row| row.get(0),
+        )
+        .optional()
+    {
+        Ok(value) => value,
+        Err(err) => {
+            error!("Failed to query setting {} for guild {}, Error Code {}", key, guild_id, err);
+            None
+        }
+    }
+}
+
+pub fn get_bool_setting(guild_id: GuildId, key: &str, default: bool) -> bool {
+    match get_setting(guild_id, key) {
+        Some(value) => value == "1",
+        None => default,
+    }
+}
+
+pub fn set_setting(guild_id: GuildId, key: &str, value: &str) -> Result<(), rusqlite::Error> {
+    let db_path = Path::new("/config/database/db.sqlite");
+    let db = Connection::open(db_path)?;
+
+    db.execute(
+        "INSERT OR REPLACE INTO guild_settings (guild_id, key, value)
+            VALUES (?1, ?2, ?3)",
+        params![guild_id.get() as i64, key, value],
+    )?;
+
+    Ok(())
+}
+
+pub fn set_bool_setting(guild_id: GuildId, key: &str, value: bool) -> Result<(), rusqlite::Error> {
+    set_setting(guild_id, key, if value { "1" } else { "0" })
+}
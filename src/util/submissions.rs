@@ -0,0 +1,654 @@
+use poise::CreateReply;
+use rusqlite::{params, Connection, OptionalExtension};
+use serenity::{
+    all::{
+        ButtonStyle,
+        ComponentInteraction,
+        CreateActionRow,
+        CreateAttachment,
+        CreateEmbed,
+        CreateInteractionResponse,
+        CreateInteractionResponseMessage,
+        CreateMessage,
+    },
+    client::Context,
+    model::{
+        colour::Colour,
+        prelude::*,
+    },
+};
+use std::{
+    fs,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tracing::{debug, error, info};
+
+use crate::{
+    util::{
+        check::is_moderator,
+        component_ids::{
+            CONSENT_ACCEPT_BUTTON,
+            CONSENT_DECLINE_BUTTON,
+            MODERATION_APPROVE_BUTTON,
+            MODERATION_REJECT_BUTTON
+        },
+        fingerprint::{remove_fingerprint, rename_fingerprint, update_fingerprint},
+        messages::create_button,
+        originals::{keep_original, original_path, remove_original, rename_original},
+        paths::{decode_file_stem, index_dir, index_path, queue_path, ProcessingFiles},
+        settings::{get_setting, MODERATION_CHANNEL},
+        util::{send_error_to, send_reply, send_warning_to},
+    },
+    PContext, PError,
+};
+
+/// A submission that waits for a moderator and the target user.
+pub struct PendingSubmission {
+    pub id: i64,
+    pub guild_id: u64,
+    pub name: String,
+    pub user_id: u64,
+    pub announcement: String,
+    pub submitter_id: u64,
+    /// Whether the submission replaces an existing clip instead of adding a new one.
+    pub edit: bool,
+}
+
+impl PendingSubmission {
+    pub fn path(&self) -> String {
+        pending_path(self.id)
+    }
+
+    pub fn original_path(&self) -> String {
+        pending_original_path(self.id)
+    }
+}
+
+fn pending_path(id: i64) -> String {
+    format!("/config/pending/{}.flac", id)
+}
+
+fn pending_original_path(id: i64) -> String {
+    format!("/config/pending/{}.source", id)
+}
+
+/// Moves a processed file into the index and makes it the active announcement of the name.
+///
+/// The original the file was processed from is kept, so the clip can be edited later.
+/// On failure the reason is logged and a message for the user is returned.
+pub fn install_file(
+    name: &str,
+    announcement_name: &str,
+    user_id: u64,
+    source_path: &str,
+    original_path: Option<&str>,
+) -> Result<(), String> {
+    let db_path = Path::new("/config/database/db.sqlite");
+
+    let name_path = index_dir(name);
+
+    if !Path::new(&name_path).exists() {
+        if let Err(why) = fs::create_dir(&name_path) {
+            error!("Failed to create directory {}: {}", &name_path, why);
+            return Err("Failed to create directory".to_string());
+        }
+    }
+
+    let db = match Connection::open(db_path) {
+        Ok(db) => db,
+        Err(why) => {
+            error!("Failed to open database: {}", why);
+            return Err("Failed to open database".to_string());
+        }
+    };
+
+    let insert_res = db.execute(
+        "INSERT OR IGNORE INTO names (name, user_id, active_file)
+              VALUES (?1, ?2, ?3)
+              ON CONFLICT(name, user_id) DO UPDATE SET
+                active_file=excluded.active_file",
+        params![&name, user_id as i64, announcement_name],
+    );
+    if let Err(why) = insert_res {
+        error!("Failed to insert new name {}: {}", &name, why);
+        return Err("Failed to insert new name".to_string());
+    };
+
+    if let Err(why) = fs::rename(source_path, index_path(name, announcement_name)) {
+        error!("Failed to rename file {}: {}", &source_path, why);
+        return Err(format!("Failed to rename file {}", &source_path));
+    }
+
+    if let Some(original_path) = original_path {
+        if let Err(why) = keep_original(name, announcement_name, original_path) {
+            debug!("Failed to keep original of {}: {}", announcement_name, why);
+        }
+    }
+
+    update_fingerprint(name, announcement_name);
+
+    let text_path = queue_path(name);
+
+    if let Err(why) = fs::remove_file(&text_path) {
+        debug!("Failed to remove queue file {} ERROR: {}", &text_path, why);
+    }
+
+    Ok(())
+}
+
+/// Copies an existing clip and its original into the processing files, so it can be committed like an upload.
+pub fn copy_to_processing(files: &ProcessingFiles, source_name: &str, source_announcement_name: &str) -> Result<(), String> {
+    let processing_path = "/config/processing/";
+    let source_path = index_path(source_name, source_announcement_name);
+
+    if let Err(why) = fs::copy(&source_path, format!("{}{}", processing_path, &files.processed_filename)) {
+        error!("Failed to copy {} of {}: {}", source_announcement_name, source_name, why);
+        return Err(format!("Failed to copy {}", source_announcement_name));
+    }
+
+    let source_original_path = original_path(source_name, source_announcement_name);
+    let kept_path = if Path::new(&source_original_path).exists() { source_original_path } else { source_path };
+    let processing_original_path = format!("{}{}", processing_path, &files.filename);
+    if let Err(why) = fs::copy(&kept_path, &processing_original_path) {
+        debug!("Failed to copy original of {} ERROR: {}", source_announcement_name, why);
+        let _ = fs::remove_file(&processing_original_path);
+    }
+
+    Ok(())
+}
+
+/// Replaces an indexed clip with an edited version, the active announcement of the name stays the same.
+pub fn replace_file(name: &str, announcement_name: &str, source_path: &str) -> Result<(), String> {
+    if let Err(why) = fs::rename(source_path, index_path(name, announcement_name)) {
+        error!("Failed to replace file {}: {}", announcement_name, why);
+        return Err(format!("Failed to replace file {}", announcement_name));
+    }
+
+    update_fingerprint(name, announcement_name);
+
+    Ok(())
+}
+
+/// Removes a clip from the index together with its original, fingerprint and playback failures.
+///
+/// Names that had the clip active fall back to another clip of the name, names without any clip left are
+/// removed, so they are announced with TTS again. Returns the clip that became active instead, if any.
+pub fn uninstall_file(name: &str, announcement_name: &str) -> Result<Option<String>, String> {
+    let db_path = Path::new("/config/database/db.sqlite");
+
+    let db = match Connection::open(db_path) {
+        Ok(db) => db,
+        Err(why) => {
+            error!("Failed to open database: {}", why);
+            return Err("Failed to open database".to_string());
+        }
+    };
+
+    let indexed_path = index_path(name, announcement_name);
+    if let Err(why) = fs::remove_file(&indexed_path) {
+        error!("Failed to remove file {}: {}", &indexed_path, why);
+        return Err(format!("Failed to remove file {}", announcement_name));
+    }
+
+    remove_original(name, announcement_name);
+
+    if let Err(why) = remove_fingerprint(name, announcement_name) {
+        error!("Failed to remove fingerprint of {}: {}", &indexed_path, why);
+    }
+
+    if let Err(why) = db.execute("DELETE FROM playback_failures WHERE path=?1", params![&indexed_path]) {
+        error!("Failed to remove playback failures of {}: {}", &indexed_path, why);
+    }
+
+    let name_path = index_dir(name);
+    let mut remaining = match fs::read_dir(&name_path) {
+        Ok(entries) => entries
+            .flatten()
+            .filter_map(|entry| decode_file_stem(&entry.path()))
+            .collect::<Vec<_>>(),
+        Err(_) => vec![],
+    };
+    remaining.sort();
+
+    let fallback = match remaining.into_iter().next() {
+        Some(fallback) => fallback,
+        None => {
+            if let Err(why) = db.execute("DELETE FROM names WHERE name=?1", params![&name]) {
+                error!("Failed to remove name {}: {}", &name, why);
+                return Err("Failed to remove name".to_string());
+            }
+            if let Err(why) = fs::remove_dir(&name_path) {
+                debug!("Failed to remove directory {}: {}", &name_path, why);
+            }
+            return Ok(None);
+        }
+    };
+
+    let update_res = db.execute(
+        "UPDATE names SET active_file=?3 WHERE name=?1 AND active_file=?2",
+        params![&name, announcement_name, &fallback],
+    );
+    match update_res {
+        Ok(0) => Ok(None),
+        Ok(_) => Ok(Some(fallback)),
+        Err(why) => {
+            error!("Failed to update active file of {}: {}", &name, why);
+            Err("Failed to update active announcement".to_string())
+        }
+    }
+}
+
+/// Renames a clip in the index together with its original, fingerprint, playback failures and active references.
+///
+/// An existing clip with the new name is never replaced.
+pub fn rename_file(name: &str, announcement_name: &str, new_announcement_name: &str) -> Result<(), String> {
+    let db_path = Path::new("/config/database/db.sqlite");
+
+    let db = match Connection::open(db_path) {
+        Ok(db) => db,
+        Err(why) => {
+            error!("Failed to open database: {}", why);
+            return Err("Failed to open database".to_string());
+        }
+    };
+
+    let indexed_path = index_path(name, announcement_name);
+    let new_indexed_path = index_path(name, new_announcement_name);
+
+    // linking fails if the target exists, unlike renaming, so a clip can't be replaced between checking and moving
+    if let Err(why) = fs::hard_link(&indexed_path, &new_indexed_path) {
+        if why.kind() == std::io::ErrorKind::AlreadyExists {
+            return Err(format!("An announcement named {} already exists", new_announcement_name));
+        }
+        error!("Failed to rename file {} to {}: {}", &indexed_path, &new_indexed_path, why);
+        return Err(format!("Failed to rename file {}", announcement_name));
+    }
+    if let Err(why) = fs::remove_file(&indexed_path) {
+        error!("Failed to remove file {}: {}", &indexed_path, why);
+        let _ = fs::remove_file(&new_indexed_path);
+        return Err(format!("Failed to rename file {}", announcement_name));
+    }
+
+    let update_res = db.execute(
+        "UPDATE names SET active_file=?3 WHERE name=?1 AND active_file=?2",
+        params![&name, announcement_name, new_announcement_name],
+    );
+    if let Err(why) = update_res {
+        error!("Failed to update active file of {}: {}", &name, why);
+        // move the clip back so it stays the active one
+        if let Err(why) = fs::hard_link(&new_indexed_path, &indexed_path).and_then(|_| fs::remove_file(&new_indexed_path)) {
+            error!("Failed to move file {} back to {}: {}", &new_indexed_path, &indexed_path, why);
+        }
+        return Err("Failed to update active announcement".to_string());
+    }
+
+    rename_original(name, announcement_name, new_announcement_name);
+
+    if let Err(why) = rename_fingerprint(name, announcement_name, new_announcement_name) {
+        error!("Failed to rename fingerprint of {}: {}", &indexed_path, why);
+    }
+
+    let failures_res = db.execute(
+        "UPDATE OR REPLACE playback_failures SET path=?2 WHERE path=?1",
+        params![&indexed_path, &new_indexed_path],
+    );
+    if let Err(why) = failures_res {
+        error!("Failed to rename playback failures of {}: {}", &indexed_path, why);
+    }
+
+    Ok(())
+}
+
+/// Stores a processed file as pending and asks the moderators and the target user to approve it.
+///
+/// Approved edits replace the clip without changing the active announcement, like unmoderated edits.
+/// Replies go to `followup` when the submission was made with a button, see [`send_reply`].
+#[allow(clippy::too_many_arguments)]
+pub async fn submit_for_moderation(
+    ctx: PContext<'_>,
+    followup: Option<&ComponentInteraction>,
+    name: &str,
+    announcement_name: &str,
+    user: &User,
+    submitter_id: UserId,
+    files: &ProcessingFiles,
+    edit: bool,
+) -> Result<(), PError> {
+    let guild_id = match ctx.guild_id() {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+    let processed_path = format!("/config/processing/{}", &files.processed_filename);
+    let original_path = format!("/config/processing/{}", &files.filename);
+
+    let moderation_channel = match moderation_channel(guild_id) {
+        Some(channel_id) => channel_id,
+        None => {
+            let why = guild_id;
+            let err_str = "Moderation is enabled, but no moderation channel is set".to_string();
+            return send_warning_to(ctx, followup, err_str, why.to_string()).await;
+        }
+    };
+
+    let consented = submitter_id == user.id;
+    let id = match insert_pending(guild_id, name, user.id, announcement_name, submitter_id, consented, edit) {
+        Ok(id) => id,
+        Err(why) => {
+            let err_str = "Failed to store submission".to_string();
+            return send_error_to(ctx, followup, err_str, why.to_string()).await;
+        }
+    };
+
+    if let Err(why) = fs::rename(&processed_path, pending_path(id)) {
+        delete_pending(id);
+        let err_str = format!("Failed to rename file {}", &processed_path);
+        return send_error_to(ctx, followup, err_str, why.to_string()).await;
+    }
+
+    if let Err(why) = fs::rename(&original_path, pending_original_path(id)) {
+        debug!("Failed to keep original {} ERROR: {}", &original_path, why);
+    }
+
+    let mut attachment = CreateAttachment::path(pending_path(id)).await?;
+    attachment.filename = format!("{}.flac", announcement_name);
+
+    let kind = if edit { "edit" } else { "file" };
+    let moderation_message = CreateMessage::new()
+        .embed(CreateEmbed::new()
+            .title(format!("New {} submitted for {}", kind, name))
+            .description(format!(
+                "`{}` [{}] submitted by {}",
+                announcement_name,
+                user.mention(),
+                submitter_id.mention()
+            ))
+            .colour(Colour::from_rgb(128, 128, 128))
+        )
+        .add_file(attachment)
+        .components(create_decision_buttons(id, MODERATION_APPROVE_BUTTON, "Approve", MODERATION_REJECT_BUTTON, "Reject"));
+
+    if let Err(why) = moderation_channel.send_message(&ctx, moderation_message).await {
+        delete_pending(id);
+        let err_str = "Failed to send submission to the moderators".to_string();
+        return send_error_to(ctx, followup, err_str, why.to_string()).await;
+    }
+
+    if !consented {
+        let consent_reply = CreateReply::default()
+            .content(format!(
+                "{}, {} wants to {} `{}` as your announcement. Do you agree?",
+                user.mention(),
+                submitter_id.mention(),
+                if edit { "edit" } else { "set" },
+                announcement_name
+            ))
+            .components(create_decision_buttons(id, CONSENT_ACCEPT_BUTTON, "Accept", CONSENT_DECLINE_BUTTON, "Decline"));
+        send_reply(ctx, followup, consent_reply).await?;
+    }
+
+    let reply = CreateReply::default()
+        .embed(CreateEmbed::new()
+            .title(format!("Submitted {} for {} for approval", if edit { "edit" } else { "new file" }, name))
+            .description(format!("`{}` [{}]", announcement_name, user.mention()))
+            .colour(Colour::from_rgb(128, 128, 128))
+        );
+
+    send_reply(ctx, followup, reply).await
+}
+
+/// The channel submissions of the guild are sent to for approval.
+pub fn moderation_channel(guild_id: GuildId) -> Option<ChannelId> {
+    get_setting(guild_id, MODERATION_CHANNEL)
+        .and_then(|id| id.parse::<u64>().ok())
+        .map(ChannelId::new)
+}
+
+/// Handles the buttons of moderation and consent messages, which have to outlive the command that sent them.
+pub async fn handle_submission_interaction(ctx: &Context, interaction: &ComponentInteraction) {
+    let (button, id) = match interaction.data.custom_id.split_once(':') {
+        Some((button, id)) => match id.parse::<i64>() {
+            Ok(id) => (button, id),
+            Err(_) => return,
+        },
+        None => return,
+    };
+
+    if ![MODERATION_APPROVE_BUTTON, MODERATION_REJECT_BUTTON, CONSENT_ACCEPT_BUTTON, CONSENT_DECLINE_BUTTON]
+        .contains(&button)
+    {
+        return;
+    }
+
+    let submission = match get_pending(id) {
+        Some(submission) => submission,
+        None => {
+            respond_ephemeral(ctx, interaction, "This submission doesn't exist anymore").await;
+            return;
+        }
+    };
+
+    let allowed = match button {
+        MODERATION_APPROVE_BUTTON | MODERATION_REJECT_BUTTON => match &interaction.member {
+            Some(member) => is_moderator(GuildId::new(submission.guild_id), member),
+            None => false,
+        },
+        _ => interaction.user.id.get() == submission.user_id,
+    };
+
+    if !allowed {
+        respond_ephemeral(ctx, interaction, "You are not allowed to decide on this submission").await;
+        return;
+    }
+
+    let status = match button {
+        MODERATION_REJECT_BUTTON | CONSENT_DECLINE_BUTTON => {
+            delete_pending(id);
+            let verb = if button == MODERATION_REJECT_BUTTON { "Rejected" } else { "Declined" };
+            info!("{} submission {} for {}", verb, id, &submission.name);
+            format!("{} by {}", verb, interaction.user.mention())
+        }
+        _ => {
+            // only the clicked flag is written, the flags of a moderator and the owner clicking at once both stick
+            let flag = if button == MODERATION_APPROVE_BUTTON { "approved" } else { "consented" };
+            let (approved, consented) = match set_pending_flag(id, flag) {
+                Ok(Some(flags)) => flags,
+                Ok(None) => {
+                    respond_ephemeral(ctx, interaction, "This submission was already decided on").await;
+                    return;
+                }
+                Err(why) => {
+                    error!("Failed to update submission {}: {}", id, why);
+                    respond_ephemeral(ctx, interaction, "Failed to update submission").await;
+                    return;
+                }
+            };
+
+            if approved && consented {
+                let install_res = if submission.edit {
+                    replace_file(&submission.name, &submission.announcement, &submission.path())
+                } else {
+                    install_file(
+                        &submission.name,
+                        &submission.announcement,
+                        submission.user_id,
+                        &submission.path(),
+                        Some(&submission.original_path()),
+                    )
+                };
+                match install_res {
+                    Ok(_) => {
+                        delete_pending(id);
+                        format!(
+                            "`{}` submitted by {} is now live for {}",
+                            &submission.announcement,
+                            UserId::new(submission.submitter_id).mention(),
+                            UserId::new(submission.user_id).mention()
+                        )
+                    }
+                    Err(err_str) => {
+                        // the submission is kept, clicking again retries the install
+                        if let Err(why) = clear_pending_flag(id, flag) {
+                            error!("Failed to reset submission {}: {}", id, why);
+                        }
+                        respond_ephemeral(ctx, interaction, &err_str).await;
+                        return;
+                    }
+                }
+            } else {
+                if approved {
+                    format!("Approved by {}, waiting for {} to accept", interaction.user.mention(), UserId::new(submission.user_id).mention())
+                } else {
+                    format!("Accepted by {}, waiting for a moderator to approve", interaction.user.mention())
+                }
+            }
+        }
+    };
+
+    let interaction_response = CreateInteractionResponseMessage::default()
+        .content(status)
+        .components(vec![]);
+
+    if let Err(why) = interaction
+        .create_response(ctx, CreateInteractionResponse::UpdateMessage(interaction_response))
+        .await
+    {
+        error!("Failed to respond to submission interaction: {}", why);
+    }
+}
+
+async fn respond_ephemeral(ctx: &Context, interaction: &ComponentInteraction, content: &str) {
+    let interaction_response = CreateInteractionResponseMessage::default()
+        .content(content)
+        .ephemeral(true);
+
+    if let Err(why) = interaction
+        .create_response(ctx, CreateInteractionResponse::Message(interaction_response))
+        .await
+    {
+        error!("Failed to respond to submission interaction: {}", why);
+    }
+}
+
+fn create_decision_buttons(id: i64, accept_id: &str, accept: &str, decline_id: &str, decline: &str) -> Vec<CreateActionRow> {
+    vec![CreateActionRow::Buttons(vec![
+        create_button(&format!("{}:{}", accept_id, id), accept, true).style(ButtonStyle::Success),
+        create_button(&format!("{}:{}", decline_id, id), decline, true).style(ButtonStyle::Danger)
+    ])]
+}
+
+fn insert_pending(
+    guild_id: GuildId,
+    name: &str,
+    user_id: UserId,
+    announcement_name: &str,
+    submitter_id: UserId,
+    consented: bool,
+    edit: bool,
+) -> Result<i64, rusqlite::Error> {
+    let db_path = Path::new("/config/database/db.sqlite");
+    let db = Connection::open(db_path)?;
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+
+    db.execute(
+        "INSERT INTO pending_submissions (guild_id, name, user_id, announcement, submitter_id, consented, edit, created)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![
+            guild_id.get() as i64,
+            name,
+            user_id.get() as i64,
+            announcement_name,
+            submitter_id.get() as i64,
+            consented,
+            edit,
+            timestamp as i64
+        ],
+    )?;
+
+    Ok(db.last_insert_rowid())
+}
+
+fn get_pending(id: i64) -> Option<PendingSubmission> {
+    let db_path = Path::new("/config/database/db.sqlite");
+    let db = Connection::open(db_path).ok()?;
+
+    let submission = db
+        .query_row(
+            "SELECT id, guild_id, name, user_id, announcement, submitter_id, edit
+                FROM pending_submissions WHERE id=?1",
+            params![id],
+            |row| {
+                Ok(PendingSubmission {
+                    id: row.get(0)?,
+                    guild_id: row.get::<_, i64>(1)? as u64,
+                    name: row.get(2)?,
+                    user_id: row.get::<_, i64>(3)? as u64,
+                    announcement: row.get(4)?,
+                    submitter_id: row.get::<_, i64>(5)? as u64,
+                    edit: row.get(6)?,
+                })
+            },
+        )
+        .optional();
+
+    match submission {
+        Ok(submission) => submission,
+        Err(why) => {
+            error!("Failed to query submission {}: {}", id, why);
+            None
+        }
+    }
+}
+
+/// Sets the `approved` or `consented` flag of a submission and returns both flags afterwards.
+///
+/// Returns `None` if the flag was set already or the submission is gone, so only one click installs it.
+fn set_pending_flag(id: i64, flag: &str) -> Result<Option<(bool, bool)>, rusqlite::Error> {
+    let db_path = Path::new("/config/database/db.sqlite");
+    let db = Connection::open(db_path)?;
+
+    db.query_row(
+        &format!(
+            "UPDATE pending_submissions SET {0}=1 WHERE id=?1 AND {0}=0 RETURNING approved, consented",
+            flag
+        ),
+        params![id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+    .optional()
+}
+
+/// Clears a flag set by `set_pending_flag`, after installing the submission failed.
+fn clear_pending_flag(id: i64, flag: &str) -> Result<(), rusqlite::Error> {
+    let db_path = Path::new("/config/database/db.sqlite");
+    let db = Connection::open(db_path)?;
+
+    db.execute(&format!("UPDATE pending_submissions SET {}=0 WHERE id=?1", flag), params![id])?;
+
+    Ok(())
+}
+
+/// Removes a pending submission and its file.
+fn delete_pending(id: i64) {
+    if let Err(why) = fs::remove_file(pending_path(id)) {
+        debug!("Failed to remove pending file {} ERROR: {}", pending_path(id), why);
+    }
+    let _ = fs::remove_file(pending_original_path(id));
+
+    let db_path = Path::new("/config/database/db.sqlite");
+    let db = match Connection::open(db_path) {
+        Ok(db) => db,
+        Err(why) => {
+            error!("Failed to open database: {}", why);
+            return;
+        }
+    };
+
+    if let Err(why) = db.execute("DELETE FROM pending_submissions WHERE id=?1", params![id]) {
+        error!("Failed to delete submission {}: {}", id, why);
+    }
+}
@@ -5,25 +5,45 @@ use songbird::{input::File, tracks::Track};
 use std::{
     fs::{self, read_dir},
     path::Path,
-    process::Command,
 };
+use tokio::process::Command;
 use tracing::{debug, error, info, warn};
 
 use serenity::{
+    all::{ComponentInteraction, CreateInteractionResponseFollowup},
     client::Context,
-    model::id::{ChannelId, GuildId},
+    model::id::{ChannelId, GuildId, UserId},
 };
 
-use crate::{PContext, PError};
+use crate::{
+    util::{
+        paths::{index_dir, index_path, queue_path, tts_path},
+        playback::{add_track_events, record_failure, with_jingles, Announcement, AnnouncementKind, PlaybackEvent},
+    },
+    PContext, PError,
+};
 
 pub async fn announce(ctx: &Context, channel_id: ChannelId, guild_id: GuildId, name: &str, user_id: u64) {
+    let announcement = match find_announcement(name, user_id) {
+        Some(announcement) => announcement,
+        None => return,
+    };
+
+    let announcement = prepare_announcement(guild_id, announcement).await;
+
+    let segments = with_jingles(guild_id, vec![announcement]);
+    play_file(ctx, channel_id, guild_id, &segments, PlaybackEvent::Join).await;
+}
+
+/// Looks up the file that should be played for a name, which is the TTS file if there is no custom one.
+pub fn find_announcement(name: &str, user_id: u64) -> Option<Announcement> {
     let db_path = Path::new("/config/database/db.sqlite");
 
-    let db = match Connection::open(&db_path) {
+    let db = match Connection::open(db_path) {
         Ok(db) => db,
         Err(err) => {
             error!("Failed to open database: {}", err);
-            return;
+            return None;
         }
     };
 
@@ -38,14 +58,14 @@ pub async fn announce(ctx: &Context, channel_id: ChannelId, guild_id: GuildId, n
         Ok(row) => row,
         Err(err) => {
             error!("Failed to query active file for {}, Error Code {}", name, err);
-            return;
+            return None;
         }
     };
 
-    let index_base_path = format!("/config/index/{}", &name);
+    let index_base_path = index_dir(name);
     let files = read_dir(&index_base_path).ok();
     let path;
-    if filename.is_some() && files.is_some() {
+    if let (Some(filename), Some(files)) = (filename, files) {
         let random = match db.query_row::<bool, _, _>(
             "SELECT random FROM names WHERE name=?1 AND user_id=?2",
             params![&name, user_id as i64],
@@ -54,11 +74,11 @@ pub async fn announce(ctx: &Context, channel_id: ChannelId, guild_id: GuildId, n
             Ok(row) => row,
             Err(err) => {
                 error!("Failed to query random file for {}, Error Code {}", name, err);
-                return;
+                return None;
             }
         };
 
-        let count = files.unwrap().count();
+        let count = files.count();
         if random && count > 0 {
             let between = Uniform::try_from(0..count);
             let mut rng = rand::rng();
@@ -67,18 +87,35 @@ pub async fn announce(ctx: &Context, channel_id: ChannelId, guild_id: GuildId, n
             let mut paths = read_dir(&index_base_path).unwrap();
             path = paths.nth(index).unwrap().unwrap().path().to_str().unwrap().to_owned();
         } else {
-            path = format!("{}/{}.flac", &index_base_path, &filename.unwrap());
+            path = index_path(name, &filename);
         }
     } else {
-        path = format!("/config/audio/{}.flac", &name);
+        return Some(Announcement::tts(name, user_id, &tts_path(name)));
     }
 
-    check_path(&path, &name);
+    Some(Announcement::new(name, user_id, &path))
+}
 
-    play_file(ctx, channel_id, guild_id, &path).await;
+/// Makes sure the file of the announcement exists, a missing submitted file counts as failure and is replaced by TTS.
+pub async fn prepare_announcement(guild_id: GuildId, announcement: Announcement) -> Announcement {
+    match announcement.kind {
+        AnnouncementKind::Tts => check_path(&announcement.path, &announcement.name).await,
+        AnnouncementKind::Custom if !Path::new(&announcement.path).exists() => {
+            error!("Didn't find announcement {} for {}", &announcement.path, &announcement.name);
+            record_failure(guild_id, &announcement, "File not found");
+
+            let fallback = Announcement::tts(&announcement.name, announcement.user_id, &announcement.tts_path());
+            check_path(&fallback.path, &fallback.name).await;
+            return fallback;
+        }
+        _ => {}
+    }
+
+    announcement
 }
 
-pub async fn play_file(ctx: &Context, channel_id: ChannelId, guild_id: GuildId, path: &str) {
+/// Plays the segments back-to-back using the queue of the voice channel, their playbacks are recorded once they ended.
+pub async fn play_file(ctx: &Context, channel_id: ChannelId, guild_id: GuildId, segments: &[Announcement], event: PlaybackEvent) {
     let manager = songbird::get(ctx)
         .await
         .expect("Songbird Voice client placed in at initialisation.")
@@ -94,11 +131,15 @@ pub async fn play_file(ctx: &Context, channel_id: ChannelId, guild_id: GuildId,
 
     let mut handler = handler_lock.lock().await;
 
-    let source = File::new(path.to_owned());
-    let track = Track::from(source);
+    for announcement in segments {
+        let source = File::new(announcement.path.clone());
+        let track = Track::from(source);
+
+        info!("Playing sound file {}", &announcement.path);
+        let track_handle = handler.enqueue(track).await;
 
-    info!("Playing sound file {}", path);
-    handler.play(track);
+        add_track_events(&manager, guild_id, channel_id, announcement, event, &track_handle);
+    }
 }
 
 pub async fn leave_channel(ctx: &Context, guild_id: GuildId) {
@@ -116,7 +157,7 @@ pub async fn leave_channel(ctx: &Context, guild_id: GuildId) {
     };
 
     let mut handler = handler_lock.lock().await;
-    let _ = handler.leave().await.expect("Failed to leave voice channel");
+    handler.leave().await.expect("Failed to leave voice channel");
 }
 
 pub async fn bot_voice_channel_is_empty(ctx: &Context, guild_id: GuildId) -> bool {
@@ -148,36 +189,42 @@ pub async fn bot_voice_channel_is_empty(ctx: &Context, guild_id: GuildId) -> boo
         };
         is_empty &= user.bot;
     }
-    return is_empty;
+    is_empty
+}
+
+/// Returns the voice channel the given user is currently connected to in the guild.
+pub fn user_voice_channel(ctx: &Context, guild_id: GuildId, user_id: UserId) -> Option<ChannelId> {
+    let guild = guild_id.to_guild_cached(&ctx)?;
+
+    guild.voice_states.get(&user_id).and_then(|voice_state| voice_state.channel_id)
 }
 
-pub fn check_path(path: &str, name: &str) {
+pub async fn check_path(path: &str, name: &str) {
     if !Path::new(path).exists() {
         debug!("Didn't find file: {}.", path);
         debug!("Creating new file with espeak.");
 
-        Command::new("espeak")
+        let espeak_res = Command::new("espeak")
             .arg("-w")
             .arg(path)
             .arg(name)
             .output()
-            .expect("Failed to run espeak!");
-        let text_path = format!("/config/queue/{}", &name);
+            .await;
+        if let Err(why) = espeak_res {
+            error!("Failed to run espeak for {}: {}", &name, why);
+            return;
+        }
+        let text_path = queue_path(name);
 
-        let _ = match fs::write(&text_path, name) {
+        match fs::write(&text_path, name) {
             Ok(()) => (),
             Err(err) => {
                 error!("Unable to write file {} for name: {} err: {}", &text_path, &name, &err);
-                return;
             }
-        };
+        }
     }
 }
 
-pub fn print_type_of<T>(_: &T) {
-    println!("{}", std::any::type_name::<T>())
-}
-
 pub async fn send_debug(ctx: PContext<'_>, content: String, err: String) -> Result<(), PError> {
     debug!("{}: {}", content, err);
 
@@ -216,3 +263,50 @@ pub async fn send_error(ctx: PContext<'_>, content: String, err: String) -> Resu
         .map(drop)
         .map_err(Into::into)
 }
+
+/// Sends a reply to the command, or a followup to a button on its reply, as the button may be pressed by someone else.
+pub async fn send_reply(ctx: PContext<'_>, followup: Option<&ComponentInteraction>, reply: CreateReply) -> Result<(), PError> {
+    match followup {
+        Some(interaction) => interaction
+            .create_followup(&ctx, reply.to_slash_followup_response(CreateInteractionResponseFollowup::new()))
+            .await
+            .map(drop)
+            .map_err(Into::into),
+        None => ctx.send(reply)
+            .await
+            .map(drop)
+            .map_err(Into::into),
+    }
+}
+
+/// Like `send_warning`, sent with [`send_reply`].
+pub async fn send_warning_to(
+    ctx: PContext<'_>,
+    followup: Option<&ComponentInteraction>,
+    content: String,
+    err: String,
+) -> Result<(), PError> {
+    warn!("{}: {}", content, err);
+
+    let reply = CreateReply::default()
+            .content(content)
+            .ephemeral(true);
+
+    send_reply(ctx, followup, reply).await
+}
+
+/// Like `send_error`, sent with [`send_reply`].
+pub async fn send_error_to(
+    ctx: PContext<'_>,
+    followup: Option<&ComponentInteraction>,
+    content: String,
+    err: String,
+) -> Result<(), PError> {
+    error!("{}: {}", content, err);
+
+    let reply = CreateReply::default()
+            .content(content)
+            .ephemeral(true);
+
+    send_reply(ctx, followup, reply).await
+}